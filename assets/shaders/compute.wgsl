@@ -1,4 +1,6 @@
 const MIN_HIT_DISTANCE: f32 = 0.000000001;
+// distance new rays start off the surface, so they don't hit it again right away
+const SURFACE_OFFSET: f32 = 0.0001;
 fn pcg_hash(v: u32) -> u32 {
     let p1 = 747796405u;
     let p2 = 2891336453u;
@@ -8,25 +10,103 @@ fn pcg_hash(v: u32) -> u32 {
 
     return((word >> 22u) ^ word);
 }
-fn randf(seed: u32) -> f32 {
-    let hashed = pcg_hash(seed) % 0xffffffffu;
-    return(f32(hashed) / 0xffffffff.);
+// maps the upper 24 bits to a float in [0, 1)
+fn u32_to_unit(x: u32) -> f32 {
+    return f32(x >> 8u) * (1. / 16777216.);
 }
 
-// source http://holger.dammertz.org/stuff/notes_HammersleyOnHemisphere.html
-const PI: f32 = 3.14159265358979;
-fn radical_inverse_vdc(bits_input: u32) -> f32 {
-    var bits = (bits_input << 16u) | (bits_input >> 16u);
-    bits = ((bits & 0x55555555u) << 1u) | ((bits & 0xAAAAAAAAu) >> 1u);
-    bits = ((bits & 0x33333333u) << 2u) | ((bits & 0xCCCCCCCCu) >> 2u);
-    bits = ((bits & 0x0F0F0F0Fu) << 4u) | ((bits & 0xF0F0F0F0u) >> 4u);
-    bits = ((bits & 0x00FF00FFu) << 8u) | ((bits & 0xFF00FF00u) >> 8u);
-    return f32(bits) * 2.3283064365386963e-10; // / 0x100000000
+// Samplers. All of them are mirrored on the cpu in `sampler.rs`
+const SAMPLER_PCG: u32 = 0u;
+const SAMPLER_SOBOL: u32 = 1u;
+const SAMPLER_RANK1: u32 = 2u;
+
+struct SamplerState {
+    pixel: vec2u,
+    // decorrelates the pixels
+    pixel_seed: u32,
+    // index of the sample within the pixel
+    index: u32,
+    // next dimension (pair) to draw
+    dimension: u32,
+    pcg_state: u32,
+}
+var<private> rng: SamplerState;
+
+fn sampler_init(pixel: vec2u, index: u32) {
+    rng.pixel = pixel;
+    rng.pixel_seed = pcg_hash(pixel.x + pcg_hash(pixel.y + pcg_hash(globals.seed)));
+    rng.index = index;
+    rng.dimension = 0u;
+    rng.pcg_state = pcg_hash(rng.pixel_seed ^ pcg_hash(index));
 }
-fn hammersley2d(i: u32, n: u32) -> vec2f {
-    return vec2f(f32(i)/f32(n), radical_inverse_vdc(i));
+
+fn pcg_next() -> u32 {
+    rng.pcg_state = pcg_hash(rng.pcg_state);
+    return rng.pcg_state;
 }
 
+// source: Burley, Practical Hash-based Owen Scrambling
+fn laine_karras_permutation(x_input: u32, seed: u32) -> u32 {
+    var x = x_input + seed;
+    x ^= x * 0x6c50b47cu;
+    x ^= x * 0xb82f1e52u;
+    x ^= x * 0xc7afe638u;
+    x ^= x * 0x8d22f6e6u;
+    return x;
+}
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    return reverseBits(laine_karras_permutation(reverseBits(x), seed));
+}
+// second dimension of the sobol sequence, the first one is reverseBits(index)
+fn sobol_dimension1(index: u32) -> u32 {
+    var result = 0u;
+    var v = 1u << 31u;
+    var i = index;
+    while (i != 0u) {
+        if ((i & 1u) != 0u) {
+            result ^= v;
+        }
+        i = i >> 1u;
+        v ^= v >> 1u;
+    }
+    return result;
+}
+fn sobol_2d(dimension: u32) -> vec2f {
+    // every dimension pair gets its own shuffle and scramble
+    let seed = pcg_hash(rng.pixel_seed ^ (dimension * 0x9e3779b9u));
+    let index = nested_uniform_scramble(rng.index, seed);
+    let x = nested_uniform_scramble(reverseBits(index), pcg_hash(seed + 1u));
+    let y = nested_uniform_scramble(sobol_dimension1(index), pcg_hash(seed + 2u));
+    return vec2f(u32_to_unit(x), u32_to_unit(y));
+}
+
+// generators of the R2 sequence in 0.32 fixed point
+const R2_X: u32 = 3242174889u;
+const R2_Y: u32 = 2447445414u;
+fn rank1_2d(dimension: u32) -> vec2f {
+    // the R2 sequence over the pixel grid gives offsets with blue noise like characteristics
+    let dither = rng.pixel.x * R2_X + rng.pixel.y * R2_Y;
+    // every dimension pair gets a different, pixel independent shift
+    let shift = pcg_hash(pcg_hash(globals.seed) ^ (dimension * 0x9e3779b9u));
+    let x = rng.index * R2_X + dither + shift;
+    let y = rng.index * R2_Y + dither + pcg_hash(shift);
+    return vec2f(u32_to_unit(x), u32_to_unit(y));
+}
+
+// draws the next two dimensions of the current sample
+fn sample_2d() -> vec2f {
+    let dimension = rng.dimension;
+    rng.dimension += 1u;
+    if (globals.sampler_kind == SAMPLER_SOBOL) {
+        return sobol_2d(dimension);
+    }
+    if (globals.sampler_kind == SAMPLER_RANK1) {
+        return rank1_2d(dimension);
+    }
+    return vec2f(u32_to_unit(pcg_next()), u32_to_unit(pcg_next()));
+}
+
+const PI: f32 = 3.14159265358979;
 fn hemisphereSample_uniform(u: f32, v: f32) -> vec3f {
     let phi = v * 2.0 * PI;
     let cosTheta = 1.0 - u;
@@ -34,9 +114,21 @@ fn hemisphereSample_uniform(u: f32, v: f32) -> vec3f {
     return vec3f(cos(phi) * sinTheta, sin(phi) * sinTheta, cosTheta);
 }
 
-fn in_unit_sphere(i: u32, n: u32) -> vec3f {
-    let hammersley = hammersley2d(i,n);
-    return(hemisphereSample_uniform(hammersley.x, hammersley.y));
+fn in_unit_sphere() -> vec3f {
+    let u = sample_2d();
+    return(hemisphereSample_uniform(u.x, u.y));
+}
+// uniformly distributed direction
+fn unit_vector() -> vec3f {
+    let u = sample_2d();
+    let z = 1. - 2. * u.x;
+    let r = sqrt(max(1. - z * z, 0.));
+    let phi = 2. * PI * u.y;
+    return vec3f(r * cos(phi), r * sin(phi), z);
+}
+// schlick's approximation of the fraction of light reflected at the given angle
+fn schlick(cos_theta: f32, reflectance: f32) -> f32 {
+    return reflectance + (1. - reflectance) * pow(1. - cos_theta, 5.);
 }
 
 fn refract(uv: vec3f, n: vec3f, etai_over_etat: f32) -> vec3f{
@@ -48,31 +140,82 @@ fn refract(uv: vec3f, n: vec3f, etai_over_etat: f32) -> vec3f{
 struct Globals {
     seed: u32,
     bounces: u32,
+    adaptive_sampling: u32,
+    convergence_threshold: f32,
     sky_color: vec4f,
+    min_samples: u32,
+    recheck_interval: u32,
+    acc_frame: u32,
+    samples_per_frame: u32,
+    temporal: u32,
+    reproject: u32,
+    history_limit: f32,
+    depth_tolerance: f32,
+    sampler_kind: u32,
 }
 struct Camera {
     fov: vec2f,
     viewport: vec2f,
     camera_position: vec4f,
-    _offset1: vec4f,
-    _offset2: vec4f,
+    previous_position: vec4f,
+    projection: u32,
+    ortho_width: f32,
+    // in degrees
+    fisheye_fov: f32,
+    _offset1: f32,
+    stereo_layout: u32,
+    // interpupillary distance
+    eye_distance: f32,
+    convergence: f32,
+    _offset2: f32,
+    // seconds relative to the frame, the spheres move during this interval
+    shutter: vec2f,
+    // radius of the lens, zero for a pinhole
+    aperture: f32,
+    focus_distance: f32,
     inverse_projection: mat4x4f,
     inverse_view: mat4x4f,
-    _offset3: mat4x4f,
+    // maps world positions of the last frame to homogeneous screen coordinates
+    previous_reprojection: mat4x4f,
 }
 struct Sphere {
     center: vec3<f32>,
     radius: f32,
+    // units per second, for motion blur
+    velocity: vec3f,
     material_index: u32,
 }
+struct Instance {
+    object_to_world: mat4x4f,
+    world_to_object: mat4x4f,
+    // out of range for the placeholder of a scene without instances
+    sphere_index: u32,
+}
+struct ProceduralTexture {
+    color_a: vec4f,
+    color_b: vec4f,
+    kind: u32,
+    space: u32,
+    scale: f32,
+    octaves: u32,
+}
 struct Material {
-    dialectric: f32,
+    metallic: f32,
     specular_intensity: f32,
     roughness: f32,
     fog: f32,
     albedo: vec4<f32>,
     emission_color: vec3<f32>,
     emission_strength: f32,
+    // texture slots, see `sample_texture`
+    albedo_texture: u32,
+    roughness_texture: u32,
+    metallic_texture: u32,
+    emission_texture: u32,
+    // fraction of the light passing into the surface instead of reflecting off it
+    transmission: f32,
+    // index of refraction
+    ior: f32,
 }
 @group(0) @binding(0)
 var<uniform> globals: Globals;
@@ -88,40 +231,218 @@ var<storage> spheres: array<Sphere>;
 
 @group(0) @binding(4)
 var<storage> materials: array<Material>;
-fn calc_ray_direction(
+
+struct PixelStats {
+    // sum of the luminance and the squared luminance of all samples of the pixel
+    luminance: vec2f,
+    // samples traced since the accumulation restarted. Unlike the sample count in the alpha
+    // channel it is not reset by reprojection, so the sample sequence never repeats
+    sample_index: u32,
+}
+@group(0) @binding(5)
+var<storage, read_write> pixel_stats: array<PixelStats>;
+
+// amount of pixels below the convergence threshold in this frame
+@group(0) @binding(6)
+var<storage, read_write> converged_pixels: atomic<u32>;
+
+@group(0) @binding(7)
+var history_texture: texture_2d<f32>;
+
+// distance to the first hit of every pixel
+@group(0) @binding(8)
+var<storage, read_write> depth: array<f32>;
+
+@group(0) @binding(9)
+var<storage> history_depth: array<f32>;
+
+// the spheres placed in the world by the scene graph
+@group(0) @binding(10)
+var<storage> instances: array<Instance>;
+
+// textures of the materials, all of the same size
+@group(0) @binding(11)
+var textures: texture_2d_array<f32>;
+
+@group(0) @binding(12)
+var texture_sampler: sampler;
+
+@group(0) @binding(13)
+var<storage> procedurals: array<ProceduralTexture>;
+
+const SKY_DEPTH: f32 = -1.;
+
+// the texture channels are stored as srgb, see `texture.rs`
+fn srgb_to_linear(color: vec3f) -> vec3f {
+    let linear = color / 12.92;
+    let curve = pow((color + 0.055) / 1.055, vec3f(2.4));
+    return select(curve, linear, color <= vec3f(0.04045));
+}
+// uv coordinates of a point on the unit sphere, the top of the image lies at -y
+fn sphere_uv(p: vec3f) -> vec2f {
+    let u = 0.5 + atan2(p.z, p.x) / (2. * PI);
+    let v = acos(clamp(-p.y, -1., 1.)) / PI;
+    return vec2f(u, v);
+}
+fn luminance(color: vec3f) -> f32 {
+    return dot(color, vec3f(0.2126, 0.7152, 0.0722));
+}
+// a pixel is converged if the standard error of its mean luminance
+// is small relative to the luminance itself
+fn is_converged(samples: f32, stats: vec2f) -> bool {
+    if (samples < f32(globals.min_samples) || samples < 2.) {
+        return false;
+    }
+    let mean = stats.x / samples;
+    let variance = max(stats.y / samples - mean * mean, 0.);
+    let standard_error = sqrt(variance / samples);
+    return standard_error <= globals.convergence_threshold * max(mean, 0.001);
+}
+// Projections. Mirrored on the cpu in `Camera::ray`
+const PROJECTION_PERSPECTIVE: u32 = 0u;
+const PROJECTION_ORTHOGRAPHIC: u32 = 1u;
+const PROJECTION_FISHEYE: u32 = 2u;
+const PROJECTION_EQUIRECTANGULAR: u32 = 3u;
+// Stereo layouts. Mirrored on the cpu in `Stereo::split`
+const STEREO_MONO: u32 = 0u;
+const STEREO_SIDE_BY_SIDE: u32 = 1u;
+const STEREO_TOP_BOTTOM: u32 = 2u;
+
+struct Ray {
+    origin: vec3f,
+    direction: vec3f,
+    // false for pixels outside of the image circle of a fisheye
+    valid: bool,
+    // point in time within the shutter interval
+    time: f32,
+}
+fn calc_ray(
     invocation_id: vec3u
-) -> vec3f {
-    var coord = vec2f(f32(invocation_id.x), f32(invocation_id.y)) / camera.viewport ;
+) -> Ray {
+    // the texture may be smaller than the viewport while previewing
+    var dimensions = textureDimensions(output_texture);
+    var pixel = invocation_id.xy;
+    // -1 for the left eye, 1 for the right eye
+    var eye = 0.;
+    if (camera.stereo_layout != STEREO_MONO) {
+        var axis = 0u;
+        if (camera.stereo_layout == STEREO_TOP_BOTTOM) {
+            axis = 1u;
+        }
+        let half = dimensions[axis] / 2u;
+        if (pixel[axis] < half) {
+            dimensions[axis] = half;
+            eye = -1.;
+        } else {
+            pixel[axis] -= half;
+            dimensions[axis] -= half;
+            eye = 1.;
+        }
+    }
+    let size = vec2f(dimensions);
+    var coord = vec2f(f32(pixel.x), f32(pixel.y)) / size;
     coord = coord * 2. - vec2f(1.);
-    let target1 = transpose(camera.inverse_projection) * vec4f(coord,1.,1.);
-    let target2 = normalize(target1.xyz / target1.w);
-    let ray_direction = (camera.inverse_view * vec4f(target2, 0.)).xyz;
-    return ray_direction;
+    var ray: Ray;
+    ray.valid = true;
+    var view_origin = vec3f(0.);
+    var direction: vec3f;
+    if (camera.projection == PROJECTION_ORTHOGRAPHIC) {
+        let half_size = vec2f(camera.ortho_width, camera.ortho_width * size.y / size.x) * 0.5;
+        view_origin = vec3f(coord * half_size, 0.);
+        direction = vec3f(0., 0., -1.);
+    } else if (camera.projection == PROJECTION_FISHEYE) {
+        let p = vec2f(coord.x, coord.y * size.y / size.x);
+        let r = length(p);
+        ray.valid = r <= 1.;
+        let theta = r * radians(camera.fisheye_fov) * 0.5;
+        var xy = vec2f(0.);
+        if (r > 0.) {
+            xy = p / r * sin(theta);
+        }
+        direction = vec3f(xy, -cos(theta));
+    } else if (camera.projection == PROJECTION_EQUIRECTANGULAR) {
+        let longitude = coord.x * PI;
+        let latitude = coord.y * PI * 0.5;
+        direction = vec3f(
+            cos(latitude) * sin(longitude),
+            sin(latitude),
+            -cos(latitude) * cos(longitude),
+        );
+    } else {
+        let target1 = transpose(camera.inverse_projection) * vec4f(coord,1.,1.);
+        direction = normalize(target1.xyz / target1.w);
+    }
+    if (eye != 0.) {
+        var axis = vec3f(1., 0., 0.);
+        if (camera.projection == PROJECTION_EQUIRECTANGULAR) {
+            // omni-directional stereo: the eyes rotate with the viewing direction
+            let longitude = coord.x * PI;
+            axis = vec3f(cos(longitude), 0., sin(longitude));
+        }
+        let offset = axis * eye * camera.eye_distance * 0.5;
+        // both eyes see the same point at the convergence distance
+        direction = normalize(direction * camera.convergence - offset);
+        view_origin += offset;
+    }
+    ray.origin = camera.camera_position.xyz + (camera.inverse_view * vec4f(view_origin, 0.)).xyz;
+    // the time is only sampled for motion blur, to keep the sample sequence otherwise
+    ray.time = camera.shutter.x;
+    ray.direction = (camera.inverse_view * vec4f(direction, 0.)).xyz;
+    return ray;
+}
+// moves the origin of the ray to a point on the lens, keeping the point at the focus distance
+fn thin_lens(ray: Ray, u: vec2f) -> Ray {
+    let r = camera.aperture * sqrt(u.x);
+    let phi = 2. * PI * u.y;
+    let offset = (camera.inverse_view * vec4f(r * cos(phi), r * sin(phi), 0., 0.)).xyz;
+    let focus = ray.origin + ray.direction * camera.focus_distance;
+    var lens_ray = ray;
+    lens_ray.origin = ray.origin + offset;
+    lens_ray.direction = normalize(focus - lens_ray.origin);
+    return lens_ray;
 }
 struct HitPayload {
     normal: vec3f,
     hit_position: vec3f,
     sphere_index: i32,
     hit_distance: f32,
+    // texture coordinates, every primitive provides its own parametrization
+    uv: vec2f,
+    // relative to the center of the sphere, before its transform
+    object_position: vec3f,
 }
 fn trace_ray(
     ray_origin: vec3f,
     ray_direction: vec3f,
+    time: f32,
 ) -> HitPayload {
     var closest_hit_distance = 0xffffffff.;
     var closest_index = -1;
-    let sphere_count = i32(arrayLength(&spheres));
-    for (var i = 0; i < sphere_count; i++) {
-        let sphere = spheres[i];
-        let origin = ray_origin - sphere.center;
-        let a = dot(ray_direction, ray_direction);
-        let b = 2. * dot(origin, ray_direction);
+    let sphere_count = arrayLength(&spheres);
+    let instance_count = i32(arrayLength(&instances));
+    for (var i = 0; i < instance_count; i++) {
+        let instance = instances[i];
+        if (instance.sphere_index >= sphere_count) {
+            continue;
+        }
+        let sphere = spheres[instance.sphere_index];
+        // intersect in object space. The direction is not normalized,
+        // so the hit distance is the same as in world space
+        let direction = (instance.world_to_object * vec4f(ray_direction, 0.)).xyz;
+        let origin = (instance.world_to_object * vec4f(ray_origin, 1.)).xyz
+            - sphere.center - sphere.velocity * time;
+        let a = dot(direction, direction);
+        let b = 2. * dot(origin, direction);
         let c = dot(origin,origin) - sphere.radius * sphere.radius;
         let discriminant = b * b - 4. * a * c;
         if (discriminant < 0.) {
             continue;
         }
-        let hit_distance = (-b - sqrt(discriminant)) / (2. * a);
+        var hit_distance = (-b - sqrt(discriminant)) / (2. * a);
+        if (hit_distance <= MIN_HIT_DISTANCE) {
+            // the ray starts inside of the sphere
+            hit_distance = (-b + sqrt(discriminant)) / (2. * a);
+        }
         if (hit_distance > MIN_HIT_DISTANCE && closest_hit_distance > hit_distance ) {
             closest_hit_distance = hit_distance;
             closest_index = i;
@@ -129,39 +450,233 @@ fn trace_ray(
         
     }
     var payload: HitPayload;
-    payload.sphere_index = closest_index;
+    payload.sphere_index = -1;
 
     if (closest_index == -1) {
         // no hit
         return payload;
     }
-    let sphere = spheres[closest_index];
-    let origin = ray_origin - sphere.center; 
+    let instance = instances[closest_index];
+    let sphere = spheres[instance.sphere_index];
+    let center = sphere.center + sphere.velocity * time;
+    let origin = (instance.world_to_object * vec4f(ray_origin, 1.)).xyz - center;
+    let direction = (instance.world_to_object * vec4f(ray_direction, 0.)).xyz;
 
-    let hit_point = (origin + ray_direction * closest_hit_distance);
-    let normal = normalize(hit_point);
+    let hit_point = (origin + direction * closest_hit_distance);
+    // normals transform with the inverse transpose
+    let normal = normalize((transpose(instance.world_to_object) * vec4f(hit_point, 0.)).xyz);
+    payload.sphere_index = i32(instance.sphere_index);
     payload.normal = normal;
-    payload.hit_position = hit_point + sphere.center;
+    payload.hit_position = (instance.object_to_world * vec4f(hit_point + center, 1.)).xyz;
     payload.hit_distance = closest_hit_distance;
+    payload.uv = sphere_uv(normalize(hit_point));
+    payload.object_position = hit_point;
     return payload;
 }
-@compute
-@workgroup_size(16, 16, 1)
-fn main(
-    @builtin(global_invocation_id)
+// Texture slots of the materials. Mirrored on the cpu in `TextureRef`
+const NO_TEXTURE: u32 = 0xffffffffu;
+// set for procedural textures, the other bits are the index into `procedurals`
+const PROCEDURAL_TEXTURE: u32 = 0x80000000u;
+// Procedural textures. Mirrored on the cpu in `procedural.rs`
+const PROCEDURAL_CHECKER: u32 = 0u;
+const PROCEDURAL_NOISE: u32 = 1u;
+const PROCEDURAL_VORONOI: u32 = 2u;
+const PROCEDURAL_MARBLE: u32 = 3u;
+const PROCEDURAL_GRADIENT: u32 = 4u;
+const SPACE_WORLD: u32 = 1u;
+const MAX_OCTAVES: u32 = 8u;
+
+fn hash3(cell: vec3i) -> u32 {
+    let c = bitcast<vec3u>(cell);
+    return pcg_hash(c.x + pcg_hash(c.y + pcg_hash(c.z)));
+}
+// dot product with one of the 12 edge directions of a cube, source: Perlin, Improving Noise
+fn gradient(hash: u32, p: vec3f) -> f32 {
+    let h = hash & 15u;
+    var u = p.y;
+    if (h < 8u) {
+        u = p.x;
+    }
+    var v = p.z;
+    if (h < 4u) {
+        v = p.y;
+    } else if (h == 12u || h == 14u) {
+        v = p.x;
+    }
+    if ((h & 1u) != 0u) {
+        u = -u;
+    }
+    if ((h & 2u) != 0u) {
+        v = -v;
+    }
+    return u + v;
+}
+fn perlin_corner(cell: vec3i, f: vec3f, offset: vec3i) -> f32 {
+    return gradient(hash3(cell + offset), f - vec3f(offset));
+}
+// perlin noise between about -1 and 1
+fn perlin(p: vec3f) -> f32 {
+    let cell = floor(p);
+    let f = p - cell;
+    let i = vec3i(cell);
+    let w = f * f * f * (f * (f * 6. - 15.) + 10.);
+    let x00 = mix(perlin_corner(i, f, vec3i(0, 0, 0)), perlin_corner(i, f, vec3i(1, 0, 0)), w.x);
+    let x10 = mix(perlin_corner(i, f, vec3i(0, 1, 0)), perlin_corner(i, f, vec3i(1, 1, 0)), w.x);
+    let x01 = mix(perlin_corner(i, f, vec3i(0, 0, 1)), perlin_corner(i, f, vec3i(1, 0, 1)), w.x);
+    let x11 = mix(perlin_corner(i, f, vec3i(0, 1, 1)), perlin_corner(i, f, vec3i(1, 1, 1)), w.x);
+    return mix(mix(x00, x10, w.y), mix(x01, x11, w.y), w.z);
+}
+// layers of perlin noise with doubling frequency and halving amplitude, mapped to about 0 to 1
+fn fbm(p: vec3f, octaves: u32) -> f32 {
+    var sum = 0.;
+    var amplitude = 0.5;
+    var total = 0.;
+    var frequency = 1.;
+    for (var i = 0u; i < octaves; i++) {
+        sum += amplitude * perlin(p * frequency);
+        total += amplitude;
+        amplitude *= 0.5;
+        frequency *= 2.;
+    }
+    return 0.5 + 0.5 * sum / total;
+}
+// distance to the closest feature point, every cell holds one point
+fn voronoi(p: vec3f) -> f32 {
+    let cell = vec3i(floor(p));
+    var closest = 3.40282e38;
+    for (var z = -1; z <= 1; z++) {
+        for (var y = -1; y <= 1; y++) {
+            for (var x = -1; x <= 1; x++) {
+                let neighbor = cell + vec3i(x, y, z);
+                let h = hash3(neighbor);
+                let offset = vec3f(u32_to_unit(h), u32_to_unit(pcg_hash(h)), u32_to_unit(pcg_hash(pcg_hash(h))));
+                closest = min(closest, distance(p, vec3f(neighbor) + offset));
+            }
+        }
+    }
+    return closest;
+}
+fn procedural_texture(texture: ProceduralTexture, payload: HitPayload) -> vec4f {
+    var p = payload.object_position;
+    if (texture.space == SPACE_WORLD) {
+        p = payload.hit_position;
+    }
+    p *= texture.scale;
+    let octaves = clamp(texture.octaves, 1u, MAX_OCTAVES);
+    var t: f32;
+    if (texture.kind == PROCEDURAL_NOISE) {
+        t = fbm(p, octaves);
+    } else if (texture.kind == PROCEDURAL_VORONOI) {
+        t = voronoi(p);
+    } else if (texture.kind == PROCEDURAL_MARBLE) {
+        t = 0.5 + 0.5 * sin((p.x + 4. * fbm(p, octaves)) * PI);
+    } else if (texture.kind == PROCEDURAL_GRADIENT) {
+        t = 0.5 - 0.5 * p.y;
+    } else {
+        // PROCEDURAL_CHECKER and unknown kinds
+        let cell = floor(p);
+        let sum = cell.x + cell.y + cell.z;
+        t = sum - 2. * floor(sum / 2.);
+    }
+    return mix(texture.color_a, texture.color_b, clamp(t, 0., 1.));
+}
+// value of a material texture at the hit, one without texture.
+// Colors of images are stored as srgb and decoded if `color` is true
+fn sample_texture(slot: u32, payload: HitPayload, color: bool) -> vec4f {
+    if (slot == NO_TEXTURE) {
+        return vec4f(1.);
+    }
+    if ((slot & PROCEDURAL_TEXTURE) != 0u) {
+        let index = slot & ~PROCEDURAL_TEXTURE;
+        if (index >= arrayLength(&procedurals)) {
+            return vec4f(1.);
+        }
+        return procedural_texture(procedurals[index], payload);
+    }
+    if (slot >= u32(textureNumLayers(textures))) {
+        return vec4f(1.);
+    }
+    let value = textureSampleLevel(textures, texture_sampler, payload.uv, i32(slot), 0.);
+    if (color) {
+        return vec4f(srgb_to_linear(value.rgb), value.a);
+    }
+    return value;
+}
+// first surface a path hit, used for the temporal reprojection
+struct PrimaryHit {
+    // position with w = 1, or the direction with w = 0 for the sky
+    world: vec4f,
+    // distance to the camera position or SKY_DEPTH
+    distance: f32,
+}
+// written by every call of trace_path
+var<private> primary_hit: PrimaryHit;
+struct History {
+    color: vec3f,
+    samples: f32,
+}
+// looks up the accumulated color of the last frame at the given world position.
+// `world` is a direction with w = 0 for the sky.
+// Returns no samples if the position was not visible in the last frame.
+fn reproject(world: vec4f, distance: f32, dimensions: vec2u) -> History {
+    var history: History;
+    let h = (camera.previous_reprojection * world).xyz;
+    if (h.z <= 0.) {
+        return history;
+    }
+    // inverse of the perspective projection in calc_ray
+    let pixel = vec2i(floor((h.xy / h.z + 1.) * 0.5 * vec2f(dimensions) + 0.5));
+    if (any(pixel < vec2i(0)) || any(pixel >= vec2i(dimensions))) {
+        return history;
+    }
+    let previous_distance = history_depth[u32(pixel.y) * dimensions.x + u32(pixel.x)];
+    if ((distance == SKY_DEPTH) != (previous_distance == SKY_DEPTH)) {
+        return history;
+    }
+    if (distance != SKY_DEPTH) {
+        // disocclusion: something else was visible at this pixel
+        let expected = length(world.xyz - camera.previous_position.xyz);
+        if (abs(previous_distance - expected) > globals.depth_tolerance * expected) {
+            return history;
+        }
+    }
+    let accumulated = textureLoad(history_texture, pixel, 0);
+    if (accumulated.a <= 0.) {
+        return history;
+    }
+    history.color = accumulated.rgb / accumulated.a;
+    history.samples = min(accumulated.a, globals.history_limit);
+    return history;
+}
+// reflectance of non metallic surfaces at normal incidence
+const DIELECTRIC_REFLECTANCE: f32 = 0.04;
+// traces a single path through the scene and returns the gathered light
+fn trace_path(
     invocation_id: vec3<u32>,
-) {
-    var seed = pcg_hash(globals.seed + (invocation_id.x + 747796405u) * (invocation_id.y + 48327948u));
-    let n_max = 45794357u;
-    var ii = (0u + seed) % 45794357u;
-    var ray_direction = calc_ray_direction(invocation_id);
-    var ray_origin = camera.camera_position.xyz;
+) -> vec3f {
+    var ray = calc_ray(invocation_id);
+    primary_hit = PrimaryHit(vec4f(ray.direction, 0.), SKY_DEPTH);
+    if (!ray.valid) {
+        return vec3f(0.);
+    }
+    if (camera.shutter.x != camera.shutter.y) {
+        ray.time = mix(camera.shutter.x, camera.shutter.y, sample_2d().x);
+    }
+    // like the time, the lens is only sampled for depth of field
+    if (camera.aperture > 0.) {
+        ray = thin_lens(ray, sample_2d());
+    }
+    var ray_direction = ray.direction;
+    var ray_origin = ray.origin;
     var light = vec3f(0.);
     var contribution = vec3f(1.);
-    let image_location = vec2i(i32(invocation_id.x), i32(invocation_id.y));
     var bounced = globals.bounces;
     for (var i: u32 = 0u; i < globals.bounces ; i = i + 1u) {
-        let payload = trace_ray(ray_origin, ray_direction);
+        let payload = trace_ray(ray_origin, ray_direction, ray.time);
+        if (i == 0u && payload.sphere_index != -1) {
+            let distance = length(payload.hit_position - camera.camera_position.xyz);
+            primary_hit = PrimaryHit(vec4f(payload.hit_position, 1.), distance);
+        }
         if (payload.sphere_index == -1) {
             light = light + globals.sky_color.xyz * contribution;
             bounced = 1u + i;
@@ -169,22 +684,106 @@ fn main(
         }
         let sphere = spheres[payload.sphere_index];
         let material = materials[sphere.material_index];
-        ray_origin = payload.hit_position;
-        seed = pcg_hash(seed + 5u);
-
-        let computed_roughness = pow(material.roughness, 2.);
-        let n = normalize(payload.normal + computed_roughness * in_unit_sphere(ii, n_max));
-        ii = ii + 1u;
-        let reflected = reflect(ray_direction, n);
-        ray_direction = reflected;
-        
-        contribution *= material.albedo.xyz * material.specular_intensity;
-        light += material.emission_color * material.emission_strength * contribution;
+        // rays leaving a sphere hit its inside, where the surface faces the other way
+        let front_face = dot(ray_direction, payload.normal) < 0.;
+        let normal = select(-payload.normal, payload.normal, front_face);
+
+        let roughness = material.roughness * sample_texture(material.roughness_texture, payload, false).r;
+        let metallic = material.metallic * sample_texture(material.metallic_texture, payload, false).r;
+        let albedo = material.albedo.xyz * sample_texture(material.albedo_texture, payload, true).rgb;
+        let computed_roughness = pow(roughness, 2.);
+        let unit_direction = normalize(ray_direction);
+        // metals reflect all light tinted by their albedo. Other materials reflect a small,
+        // untinted part of it specularly and scatter the rest diffusely
+        let cos_theta = clamp(-dot(unit_direction, normal), 0., 1.);
+        let specular = mix(schlick(cos_theta, DIELECTRIC_REFLECTANCE), 1., metallic);
+        let choice = sample_2d();
+        var tint = albedo;
+        var side = normal;
+        if (choice.y < material.transmission) {
+            // glass reflects by the fresnel term and refracts the rest, tinted by its albedo
+            let n = normalize(normal + computed_roughness * in_unit_sphere());
+            let eta = select(material.ior, 1. / material.ior, front_face);
+            let cos_i = clamp(-dot(unit_direction, n), 0., 1.);
+            let sin_t = eta * sqrt(1. - cos_i * cos_i);
+            let r0 = pow((1. - material.ior) / (1. + material.ior), 2.);
+            // beyond the critical angle all light is reflected
+            if (sin_t > 1. || choice.x < schlick(cos_i, r0)) {
+                ray_direction = reflect(unit_direction, n);
+                tint = vec3f(1.);
+            } else {
+                ray_direction = refract(unit_direction, n, eta);
+                side = -normal;
+            }
+        } else if (choice.x < specular) {
+            let n = normalize(normal + computed_roughness * in_unit_sphere());
+            ray_direction = reflect(ray_direction, n);
+            tint = mix(vec3f(1.), albedo, metallic);
+        } else {
+            let scattered = normal + unit_vector();
+            ray_direction = select(normal, scattered, dot(scattered, scattered) > 1e-8);
+        }
+        ray_origin = payload.hit_position + side * SURFACE_OFFSET;
+        contribution *= tint * material.specular_intensity;
+        let emission_texture = sample_texture(material.emission_texture, payload, true).rgb;
+        let emission = material.emission_color * emission_texture;
+        light += emission * material.emission_strength * contribution;
+    }
+
+    return light / f32(bounced);
+}
+@compute
+@workgroup_size(16, 16, 1)
+fn main(
+    @builtin(global_invocation_id)
+    invocation_id: vec3<u32>,
+) {
+    if (any(invocation_id.xy >= textureDimensions(output_texture))) {
+        return;
+    }
+    let image_location = vec2i(i32(invocation_id.x), i32(invocation_id.y));
+    var old_color = textureLoad(output_texture, image_location);
+    let dimensions = textureDimensions(output_texture);
+    let stats_index = invocation_id.y * dimensions.x + invocation_id.x;
+    var stats = pixel_stats[stats_index].luminance;
+    let first_sample = pixel_stats[stats_index].sample_index;
+    let recheck = globals.acc_frame % globals.recheck_interval == 0u || globals.reproject != 0u;
+    if (globals.adaptive_sampling != 0u && !recheck && is_converged(old_color.a, stats)) {
+        atomicAdd(&converged_pixels, 1u);
+        return;
+    }
+    var color_sum = vec3f(0.);
+    var sample_stats = vec2f(0.);
+    var first_hit = PrimaryHit(vec4f(0.), SKY_DEPTH);
+    for (var s: u32 = 0u; s < globals.samples_per_frame; s = s + 1u) {
+        // the sample index continues the sequence of the pixel
+        sampler_init(invocation_id.xy, first_sample + s);
+        let color = trace_path(invocation_id);
+        if (s == 0u) {
+            first_hit = primary_hit;
+        }
+        let l = luminance(color);
+        sample_stats += vec2f(l, l * l);
+        color_sum += color;
+    }
+    // kept for every traced pixel, since converged pixels are skipped
+    // while the camera does not move
+    depth[stats_index] = first_hit.distance;
+    if (globals.temporal != 0u && globals.reproject != 0u) {
+        // the history is blended as an exponential moving average,
+        // since it contributes at most `history_limit` samples
+        let history = reproject(first_hit.world, first_hit.distance, dimensions);
+        old_color = vec4f(history.color * history.samples, history.samples);
+        let l = luminance(history.color);
+        stats = vec2f(l, l * l) * history.samples;
+    }
+    stats += sample_stats;
+    let samples = old_color.a + f32(globals.samples_per_frame);
+    pixel_stats[stats_index] = PixelStats(stats, first_sample + globals.samples_per_frame);
+    if (is_converged(samples, stats)) {
+        atomicAdd(&converged_pixels, 1u);
     }
 
-    let color = light / f32(bounced);
-    
-    // hit
-    let old_color = textureLoad(output_texture, image_location);
-    textureStore(output_texture, image_location, vec4f(color, 1.) + old_color);
+    // the alpha channel counts the samples of the pixel
+    textureStore(output_texture, image_location, vec4f(color_sum + old_color.rgb, samples));
 }
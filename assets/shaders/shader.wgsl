@@ -2,8 +2,24 @@
 
 @group(0) @binding(0)
 var tex: texture_2d<f32>;
+struct Display {
+    // samples per pixel dispatched since the pixel stats were reset
+    dispatched_samples: u32,
+    view_mode: u32,
+    // resolution of the texture relative to the surface
+    render_scale: f32,
+}
 @group(0) @binding(1)
-var<uniform> acc_frame: u32;
+var<uniform> display: Display;
+// written by the compute shader, see `PixelStats` there
+struct PixelStats {
+    luminance: vec2<f32>,
+    sample_index: u32,
+}
+@group(0) @binding(2)
+var<storage> pixel_stats: array<PixelStats>;
+
+const VIEW_MODE_SAMPLE_HEATMAP: u32 = 1u;
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
 };
@@ -31,9 +47,34 @@ fn vs_main(
     return out;
 }
 
+// blue for few samples, over green to red for the maximum amount of samples
+fn heatmap(t: f32) -> vec3<f32> {
+    let low = mix(vec3<f32>(0., 0., 1.), vec3<f32>(0., 1., 0.), clamp(t * 2., 0., 1.));
+    return mix(low, vec3<f32>(1., 0., 0.), clamp(t * 2. - 1., 0., 1.));
+}
+
+// averaged color of a texel. The alpha channel holds the amount of samples of the pixel
+fn resolve(position: vec2<i32>) -> vec3<f32> {
+    let max_position = vec2<i32>(textureDimensions(tex)) - vec2<i32>(1);
+    let accumulated = textureLoad(tex, clamp(position, vec2<i32>(0), max_position), 0);
+    return accumulated.rgb / max(accumulated.a, 1.);
+}
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    var color = textureLoad(tex, vec2<u32>(u32(in.clip_position.x), u32(in.clip_position.y)),0) / f32(acc_frame);
-    return color;
+    let position = in.clip_position.xy * display.render_scale;
+    if (display.view_mode == VIEW_MODE_SAMPLE_HEATMAP) {
+        // the sample index counts the samples the pixel traced, even across reprojections
+        let pixel = min(vec2<u32>(position), textureDimensions(tex) - vec2<u32>(1u));
+        let traced = pixel_stats[pixel.y * textureDimensions(tex).x + pixel.x].sample_index;
+        return vec4<f32>(heatmap(f32(traced) / f32(max(display.dispatched_samples, 1u))), 1.);
+    }
+    // bilinear upscaling, which is a noop at full resolution
+    let texel = position - 0.5;
+    let base = vec2<i32>(floor(texel));
+    let t = fract(texel);
+    let top = mix(resolve(base), resolve(base + vec2<i32>(1, 0)), t.x);
+    let bottom = mix(resolve(base + vec2<i32>(0, 1)), resolve(base + vec2<i32>(1, 1)), t.x);
+    return vec4<f32>(mix(top, bottom, t.y), 1.);
 }
  
\ No newline at end of file
@@ -0,0 +1,293 @@
+//! Keyframe animation of the scene.
+//!
+//! A [`Timeline`] holds a [`Track`] of keyframes for every animated property.
+//! The camera follows one of the camera paths of the scene.
+use glam::Vec4;
+
+use crate::{globals::Globals, scene::Scene};
+
+/// A value of the scene which can be keyframed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Property {
+    SphereCenter(usize),
+    SphereRadius(usize),
+    MaterialAlbedo(usize),
+    /// Color and strength
+    MaterialEmission(usize),
+    MaterialRoughness(usize),
+    MaterialMetallic(usize),
+    SkyColor,
+}
+
+impl Property {
+    /// One property of every kind, used by the ui to pick new tracks
+    pub const KINDS: [Property; 7] = [
+        Property::SphereCenter(0),
+        Property::SphereRadius(0),
+        Property::MaterialAlbedo(0),
+        Property::MaterialEmission(0),
+        Property::MaterialRoughness(0),
+        Property::MaterialMetallic(0),
+        Property::SkyColor,
+    ];
+
+    /// Name of the kind, without the index
+    pub fn kind_name(self) -> &'static str {
+        match self {
+            Property::SphereCenter(_) => "sphere center",
+            Property::SphereRadius(_) => "sphere radius",
+            Property::MaterialAlbedo(_) => "material albedo",
+            Property::MaterialEmission(_) => "material emission",
+            Property::MaterialRoughness(_) => "material roughness",
+            Property::MaterialMetallic(_) => "material metallic",
+            Property::SkyColor => "sky color",
+        }
+    }
+    /// Index of the sphere or material
+    pub fn index(self) -> Option<usize> {
+        match self {
+            Property::SphereCenter(i)
+            | Property::SphereRadius(i)
+            | Property::MaterialAlbedo(i)
+            | Property::MaterialEmission(i)
+            | Property::MaterialRoughness(i)
+            | Property::MaterialMetallic(i) => Some(i),
+            Property::SkyColor => None,
+        }
+    }
+    /// The same kind of property for another sphere or material
+    pub fn with_index(self, index: usize) -> Self {
+        match self {
+            Property::SphereCenter(_) => Property::SphereCenter(index),
+            Property::SphereRadius(_) => Property::SphereRadius(index),
+            Property::MaterialAlbedo(_) => Property::MaterialAlbedo(index),
+            Property::MaterialEmission(_) => Property::MaterialEmission(index),
+            Property::MaterialRoughness(_) => Property::MaterialRoughness(index),
+            Property::MaterialMetallic(_) => Property::MaterialMetallic(index),
+            Property::SkyColor => Property::SkyColor,
+        }
+    }
+    /// E.g. `sphere 3 center`, the format used in scene files
+    pub fn name(self) -> String {
+        let (kind, field) = self.kind_name().split_once(' ').unwrap();
+        match self.index() {
+            Some(i) => format!("{kind} {i} {field}"),
+            None => self.kind_name().to_string(),
+        }
+    }
+    /// Inverse of [`Property::name`]
+    pub fn parse(name: &str) -> Option<Self> {
+        let parts: Vec<&str> = name.split_whitespace().collect();
+        match parts[..] {
+            ["sky", "color"] => Some(Property::SkyColor),
+            [kind, index, field] => {
+                let index = index.parse().ok()?;
+                Property::KINDS
+                    .into_iter()
+                    .find(|p| p.kind_name() == format!("{kind} {field}"))
+                    .map(|p| p.with_index(index))
+            }
+            _ => None,
+        }
+    }
+    /// Current value, None if the sphere or material does not exist
+    pub fn get(self, scene: &Scene, globals: &Globals) -> Option<Vec4> {
+        Some(match self {
+            Property::SphereCenter(i) => scene.spheres.get(i)?.center.extend(0.),
+            Property::SphereRadius(i) => Vec4::splat(scene.spheres.get(i)?.radius),
+            Property::MaterialAlbedo(i) => Vec4::from(scene.materials.get(i)?.material.albedo.0),
+            Property::MaterialEmission(i) => Vec4::from(scene.materials.get(i)?.material.emission),
+            Property::MaterialRoughness(i) => {
+                Vec4::splat(scene.materials.get(i)?.material.roughness)
+            }
+            Property::MaterialMetallic(i) => Vec4::splat(scene.materials.get(i)?.material.metallic),
+            Property::SkyColor => Vec4::from(globals.sky_color),
+        })
+    }
+    /// Sets the value and marks it for upload
+    pub fn set(self, value: Vec4, scene: &mut Scene, globals: &mut Globals) {
+        match self {
+            Property::SphereCenter(i) | Property::SphereRadius(i) => {
+                let Some(sphere) = scene.spheres.get_mut(i) else {
+                    return;
+                };
+                match self {
+                    Property::SphereCenter(_) => sphere.center = value.truncate(),
+                    _ => sphere.radius = value.x,
+                }
+                scene.dirty_spheres.mark(i);
+            }
+            Property::MaterialAlbedo(i)
+            | Property::MaterialEmission(i)
+            | Property::MaterialRoughness(i)
+            | Property::MaterialMetallic(i) => {
+                let Some(material) = scene.materials.get_mut(i).map(|m| &mut m.material) else {
+                    return;
+                };
+                match self {
+                    Property::MaterialAlbedo(_) => material.albedo.0 = value.to_array(),
+                    Property::MaterialEmission(_) => material.emission = value.to_array(),
+                    Property::MaterialRoughness(_) => material.roughness = value.x,
+                    _ => material.metallic = value.x,
+                }
+                scene.dirty_materials.mark(i);
+            }
+            Property::SkyColor => globals.sky_color = value.to_array(),
+        }
+    }
+}
+
+/// Keyframes of a single property, linearly interpolated
+#[derive(Clone, Debug)]
+pub struct Track {
+    pub property: Property,
+    /// Time in seconds and value, sorted by time
+    keys: Vec<(f32, Vec4)>,
+}
+
+impl Track {
+    pub fn new(property: Property) -> Self {
+        Self {
+            property,
+            keys: Vec::new(),
+        }
+    }
+    pub fn keys(&self) -> &[(f32, Vec4)] {
+        &self.keys
+    }
+    /// Adds a keyframe, replacing the one at the same time
+    pub fn insert(&mut self, time: f32, value: Vec4) {
+        match self.keys.binary_search_by(|(t, _)| t.total_cmp(&time)) {
+            Ok(i) => self.keys[i].1 = value,
+            Err(i) => self.keys.insert(i, (time, value)),
+        }
+    }
+    pub fn remove(&mut self, index: usize) {
+        self.keys.remove(index);
+    }
+    /// Change per second at `time`. Zero outside of the keyframes
+    pub fn derivative(&self, time: f32) -> Vec4 {
+        let i = self.keys.partition_point(|(t, _)| *t <= time);
+        match (i.checked_sub(1).map(|i| self.keys[i]), self.keys.get(i)) {
+            (Some((t0, v0)), Some(&(t1, v1))) => (v1 - v0) / (t1 - t0),
+            _ => Vec4::ZERO,
+        }
+    }
+    /// Value at `time`, held constant before the first and after the last keyframe
+    pub fn sample(&self, time: f32) -> Option<Vec4> {
+        let i = self.keys.partition_point(|(t, _)| *t <= time);
+        match (i.checked_sub(1).map(|i| self.keys[i]), self.keys.get(i)) {
+            (Some((t0, v0)), Some(&(t1, v1))) => Some(v0.lerp(v1, (time - t0) / (t1 - t0))),
+            (Some((_, v)), None) | (None, Some(&(_, v))) => Some(v),
+            (None, None) => None,
+        }
+    }
+}
+
+/// All animated properties of a scene
+#[derive(Clone, Debug)]
+pub struct Timeline {
+    pub tracks: Vec<Track>,
+    /// Index into `Scene::camera_paths` of the path the camera follows
+    pub camera_path: Option<usize>,
+    pub fps: f32,
+    pub start_frame: u32,
+    /// Included in the animation
+    pub end_frame: u32,
+}
+
+impl Default for Timeline {
+    fn default() -> Self {
+        Self {
+            tracks: Vec::new(),
+            camera_path: None,
+            fps: 24.,
+            start_frame: 0,
+            end_frame: 120,
+        }
+    }
+}
+
+impl Timeline {
+    pub fn frame_time(&self, frame: u32) -> f32 {
+        frame as f32 / self.fps
+    }
+    pub fn start_time(&self) -> f32 {
+        self.frame_time(self.start_frame)
+    }
+    pub fn end_time(&self) -> f32 {
+        self.frame_time(self.end_frame)
+    }
+    pub fn is_empty(&self) -> bool {
+        self.tracks.is_empty() && self.camera_path.is_none()
+    }
+    /// Returns the track of `property`, creating it if needed
+    pub fn track(&mut self, property: Property) -> &mut Track {
+        match self.tracks.iter().position(|t| t.property == property) {
+            Some(i) => &mut self.tracks[i],
+            None => {
+                self.tracks.push(Track::new(property));
+                self.tracks.last_mut().unwrap()
+            }
+        }
+    }
+}
+
+impl Scene {
+    /// Sets every animated property to its value at `time`.
+    /// Animated sphere centers also set the velocity of the sphere
+    pub fn apply_animation(&mut self, time: f32, globals: &mut Globals) {
+        let timeline = std::mem::take(&mut self.timeline);
+        for track in &timeline.tracks {
+            if let Some(value) = track.sample(time) {
+                track.property.set(value, self, globals);
+            }
+            // moving spheres are motion blurred
+            if let Property::SphereCenter(i) = track.property {
+                if let Some(sphere) = self.spheres.get_mut(i) {
+                    sphere.velocity = track.derivative(time).truncate();
+                }
+            }
+        }
+        let pose = timeline
+            .camera_path
+            .and_then(|i| self.camera_paths.get(i))
+            .and_then(|path| path.sample(time));
+        if let Some(pose) = pose {
+            self.camera.set_pose(pose);
+        }
+        self.timeline = timeline;
+    }
+}
+
+/// Plays the timeline in the editor
+#[derive(Debug, Default)]
+pub struct Playback {
+    /// In seconds
+    pub time: f32,
+    pub playing: bool,
+    pub looping: bool,
+    /// The time was changed from outside, so the scene has to follow even if paused
+    pub seeked: bool,
+}
+
+impl Playback {
+    /// Advances the time. Returns true if the scene has to be updated to the new time
+    pub fn update(&mut self, timeline: &Timeline, dt: f32) -> bool {
+        if !self.playing {
+            return std::mem::take(&mut self.seeked);
+        }
+        self.seeked = false;
+        let (start, end) = (timeline.start_time(), timeline.end_time());
+        self.time = self.time.max(start) + dt;
+        if self.time > end {
+            if self.looping && end > start {
+                self.time = start + (self.time - start) % (end - start);
+            } else {
+                self.time = end;
+                self.playing = false;
+            }
+        }
+        true
+    }
+}
@@ -1,29 +1,40 @@
 use std::{
-    any::type_name,
     iter::{self},
+    path::Path,
+    time::{Duration, Instant},
 };
 
-use bytemuck::Pod;
 use egui_wgpu::renderer::ScreenDescriptor;
+use glam::{Mat4, Vec3};
 use log::info;
 use wgpu::{
-    util::DeviceExt, Buffer, BufferAddress, CommandEncoder, Device, Features, Label, Limits,
-    PresentMode, Color, TextureViewDescriptor, SurfaceTexture,
-};
-use winit::{
-    dpi::PhysicalSize,
-    event::{KeyboardInput, WindowEvent},
-    event_loop::EventLoop,
-    window::Window,
+    Color, CommandEncoder, Features, Label, Limits, PresentMode, SurfaceTexture, Texture,
+    TextureViewDescriptor,
 };
+use winit::{dpi::PhysicalSize, event::WindowEvent, event_loop::EventLoop, window::Window};
 
 use crate::{
-    camera::CameraUniform,
+    animation::Playback,
+    budget::{SampleBudget, SampleTarget, SampleTuner},
+    camera::{CameraUniform, Projection, StereoLayout},
+    camera_path::PathPlayer,
+    controller::CameraController,
     globals::Globals,
-    renderer::{compute_pipeline::ComputePipeline, render_pipeline::RenderPipeline, Renderer},
+    physics::Simulation,
+    renderer::{
+        compute_pipeline::ComputePipeline,
+        gpu_timer::{GpuTimer, Pass, PassTimes},
+        hot_reload::ShaderWatcher,
+        material_preview::MaterialPreviews,
+        preview::Preview,
+        readback::U32Readback,
+        render_pipeline::{DisplayUniform, RenderPipeline, ViewMode},
+        Renderer,
+    },
     scene::Scene,
+    stats::Stats,
     timer::Timer,
-    ui::UiManager,
+    ui::{EditorState, FileRequest, SceneFileState, UiManager},
 };
 
 pub struct App {
@@ -45,9 +56,32 @@ pub struct App {
     timer: Timer,
     ui_manager: UiManager,
     scale_factor: f32,
+    view_mode: ViewMode,
+    budget: SampleBudget,
+    tuner: SampleTuner,
+    converged_readback: U32Readback,
+    /// Samples per pixel of the current frame.
+    /// Can be lower than `Globals::samples_per_frame` to not overshoot the sample budget
+    frame_samples: u32,
+    preview: Preview,
+    /// The camera moved and the next frame has to reproject the history
+    reproject_pending: bool,
+    /// Position and reprojection matrix of the camera which rendered the last frame
+    history_camera: (Vec3, Mat4),
+    shader_watcher: ShaderWatcher,
+    /// None if the adapter does not support timestamp queries
+    gpu_timer: Option<GpuTimer>,
+    stats: Stats,
+    controller: CameraController,
+    player: PathPlayer,
+    scene_file: SceneFileState,
+    playback: Playback,
+    simulation: Simulation,
+    material_previews: MaterialPreviews,
 }
 impl App {
-    pub async fn new(window: Window, event_loop: &EventLoop<()>) -> Self {
+    /// Returns None if no suitable gpu adapter is available
+    pub async fn new(window: Window, event_loop: &EventLoop<()>) -> Option<Self> {
         let size = window.inner_size();
 
         // The instance is a handle to our GPU
@@ -71,14 +105,15 @@ impl App {
                 compatible_surface: Some(&surface),
                 force_fallback_adapter: false,
             })
-            .await
-            .unwrap();
+            .await?;
         info!("Gpu used: {:?}", adapter.get_info());
 
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
-                    features: Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES,
+                    // timestamps are optional, the stats fall back to cpu timings without them
+                    features: Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
+                        | (adapter.features() & Features::TIMESTAMP_QUERY),
                     limits: Limits::default(),
                     label: Label::Some("gpu device. Used to open connections to the gpu"),
                 },
@@ -114,7 +149,8 @@ impl App {
         let input_texture_view = input_texture.create_view(&wgpu::TextureViewDescriptor::default());
         let render_pipeline =
             RenderPipeline::new(&device, &surface_config, input_texture_view, input_texture);
-        let compute_pipeline = ComputePipeline::new(&device, &scene);
+        let pixel_resources = renderer.create_pixel_resources(&device);
+        let compute_pipeline = ComputePipeline::new(&device, &scene, pixel_resources);
         let globals = Globals::default();
         let timer = Timer::new();
         let screen_descriptor = ScreenDescriptor {
@@ -122,9 +158,14 @@ impl App {
             pixels_per_point: 1.,
         };
         let camera_uniform = CameraUniform::from(&scene.camera);
+        let history_camera = (scene.camera.position, scene.camera.reprojection_matrix());
         let ui_manager = UiManager::new(&device, surface_format, screen_descriptor, event_loop);
+        let converged_readback = U32Readback::new(&device, "Converged pixels readback");
+        let gpu_timer = GpuTimer::new(&device, &queue);
+        let stats = Stats::new(gpu_timer.is_some());
+        let material_previews = MaterialPreviews::new(&device);
 
-        Self {
+        Some(Self {
             window,
             surface,
             device,
@@ -139,22 +180,70 @@ impl App {
             ui_manager,
             scale_factor: 0.,
             scene,
-        }
+            view_mode: ViewMode::default(),
+            budget: SampleBudget::new(SampleTarget::Unlimited),
+            tuner: SampleTuner::new(),
+            converged_readback,
+            frame_samples: 1,
+            preview: Preview::new(),
+            reproject_pending: false,
+            history_camera,
+            shader_watcher: ShaderWatcher::new(),
+            gpu_timer,
+            stats,
+            controller: CameraController::default(),
+            player: PathPlayer::default(),
+            scene_file: SceneFileState::default(),
+            playback: Playback::default(),
+            simulation: Simulation::default(),
+            material_previews,
+        })
     }
+    /// Restarts the accumulation after the camera or scene changed
     pub fn clear_renderer(&mut self) {
+        self.preview.changed();
+        self.reset_accumulation();
+    }
+    fn reset_accumulation(&mut self) {
         self.renderer.reset_acc();
+        self.budget.reset();
         let input_texture = self.renderer.create_input_texture(&self.device);
+        self.set_input_texture(input_texture);
+    }
+    /// Called after the camera moved.
+    /// With temporal reprojection enabled the accumulated image is kept and reprojected
+    pub fn camera_moved(&mut self) {
+        // reprojection only knows the perspective projection of a single eye
+        let camera = &self.scene.camera;
+        if self.globals.temporal == 0
+            || camera.projection != Projection::Perspective
+            || camera.stereo.layout != StereoLayout::Mono
+        {
+            self.clear_renderer();
+            return;
+        }
+        self.reproject_pending = true;
+        self.renderer.acc_frame = 0;
+        self.renderer.acc_samples = 0;
+        self.budget.reset();
+    }
+    /// Replaces the texture the compute pipeline renders to, together with the other per pixel resources.
+    /// Both bind groups reference the texture and have to be recreated
+    fn set_input_texture(&mut self, input_texture: Texture) {
         self.render_pipeline.set_input_texture(input_texture);
+        let pixel_resources = self.renderer.create_pixel_resources(&self.device);
+        self.compute_pipeline.set_pixel_resources(pixel_resources);
     }
     pub fn resize(&mut self, new_size: PhysicalSize<u32>, scale_factor: Option<f32>) {
         self.renderer.resize(new_size);
+        self.budget.reset();
         self.scene.camera.resize(new_size);
         // resize surface
         self.surface_config.height = new_size.height;
         self.surface_config.width = new_size.width;
         self.surface.configure(&self.device, &self.surface_config);
         let input_texture = self.renderer.create_input_texture(&self.device);
-        self.render_pipeline.set_input_texture(input_texture);
+        self.set_input_texture(input_texture);
         if let Some(scale_factor) = scale_factor {
             self.scale_factor = scale_factor;
         }
@@ -165,26 +254,83 @@ impl App {
         self.ui_manager.resize(screen_descriptor);
     }
     pub fn handle_window_event(&mut self, window_event: &WindowEvent) {
-        self.ui_manager.handle_window_event(window_event);
+        let consumed = self.ui_manager.handle_window_event(window_event);
+        self.controller.handle_window_event(window_event, consumed);
     }
     pub fn render_ui(&mut self) {
         let renderer_need_reset = self.ui_manager.run(
             &self.device,
             &self.queue,
             &self.window,
-            &mut self.scene,
-            &mut self.globals,
+            EditorState {
+                scene: &mut self.scene,
+                globals: &mut self.globals,
+                view_mode: &mut self.view_mode,
+                budget: &mut self.budget,
+                tuner: &mut self.tuner,
+                preview: &mut self.preview,
+                shader_watcher: &mut self.shader_watcher,
+                stats: &self.stats,
+                controller: &mut self.controller,
+                player: &mut self.player,
+                scene_file: &mut self.scene_file,
+                playback: &mut self.playback,
+                simulation: &mut self.simulation,
+                material_previews: &self.material_previews,
+                acc_samples: self.renderer.acc_samples,
+            },
         );
         if renderer_need_reset {
             self.clear_renderer();
         }
+        if let Some(request) = self.scene_file.request.take() {
+            let path = self.scene_file.path.clone();
+            self.scene_file.status = Some(match request {
+                FileRequest::Save => {
+                    let saved = self.scene.save(&self.globals, &path);
+                    saved.map(|()| format!("Saved {path}"))
+                }
+                FileRequest::Load => self.open_scene(&path).map(|()| format!("Loaded {path}")),
+            });
+        }
+    }
+    /// Replaces the scene with the one stored in the scene file at `path`
+    pub fn open_scene(&mut self, path: impl AsRef<Path>) -> Result<(), String> {
+        let mut scene = Scene::load(&path, &mut self.globals)?;
+        scene.camera.resize(self.window.inner_size());
+        self.scene = scene;
+        self.scene_file.path = path.as_ref().display().to_string();
+        self.player = PathPlayer::default();
+        self.playback = Playback::default();
+        self.simulation = Simulation::default();
+        self.controller.target_sphere = None;
+        self.clear_renderer();
+        Ok(())
     }
     pub fn prepare(&mut self) -> Result<SurfaceTexture, wgpu::SurfaceError> {
         let surface_texture = self.surface.get_current_texture()?;
-        
-        // self.render_pipeline.surface_texture = Some(surface_texture);
-        self.render_pipeline.prepare_bind_group(&self.device);
-        self.compute_pipeline.prepare_bind_group(&self.device, &self.render_pipeline.input_texture_view);
+
+        // upload changed data first, since it might invalidate the bind groups
+        self.compute_pipeline.upload(
+            &self.device,
+            &self.queue,
+            &mut self.scene,
+            Globals {
+                samples_per_frame: self.frame_samples,
+                ..self.globals
+            },
+            self.camera_uniform,
+        );
+        let display = DisplayUniform::new(
+            self.renderer.dispatched_samples,
+            self.view_mode,
+            self.renderer.render_scale,
+        );
+        self.render_pipeline.upload(&self.queue, display);
+        self.render_pipeline
+            .prepare_bind_group(&self.device, &self.compute_pipeline.pixels);
+        self.compute_pipeline
+            .prepare_bind_group(&self.device, &self.render_pipeline.input_texture_view);
         Ok(surface_texture)
     }
     pub fn queue(&mut self, surface_texture: SurfaceTexture) {
@@ -200,39 +346,18 @@ impl App {
         // update buffers
         self.ui_manager
             .update_buffers(&mut encoder, &self.device, &self.queue);
-        let mut encoder = encoder
-            // compute_pipeline
-            .write_buffer(
-                self.globals,
-                &self.compute_pipeline.globals_buffer,
-                &self.device,
-            )
-            .write_buffer(
-                self.camera_uniform,
-                &self.compute_pipeline.camera_buffer,
-                &self.device,
-            )
-            .write_slice_buffer(
-                &self.scene.spheres,
-                &self.compute_pipeline.sphere_buffer,
-                &self.device,
-            )
-            .write_slice_buffer(
-                &self.scene.materials,
-                &self.compute_pipeline.material_buffer,
-                &self.device,
-            )
-            // render pipeline
-            .write_buffer(
-                self.renderer.acc_frame,
-                &self.render_pipeline.acc_frame_buffer,
-                &self.device,
-            );
-       
-
-        {
+        let mut cpu_times: PassTimes = [None; 3];
+        if let Some(timer) = &mut self.gpu_timer {
+            timer.begin_frame();
+        }
+        if !self.budget.is_paused() {
             // dispatch compute pass
-            let size = self.renderer.image_buffer.dimensions();
+            encoder.clear_buffer(&self.compute_pipeline.converged_buffer, 0, None);
+            if self.reproject_pending {
+                self.copy_history(&mut encoder);
+            }
+            let size = self.renderer.render_size();
+            let start = begin_pass(&mut self.gpu_timer, &mut encoder, Pass::Compute);
             let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
                 label: "Compute Pass".into(),
             });
@@ -241,14 +366,31 @@ impl App {
             // defined in the shader
             const WORKGROUP_SIZE: u32 = 16;
             compute_pass.dispatch_workgroups(
-                size.0 / WORKGROUP_SIZE,
-                size.1 / WORKGROUP_SIZE,
+                size.0.div_ceil(WORKGROUP_SIZE),
+                size.1.div_ceil(WORKGROUP_SIZE),
                 1,
             );
+            drop(compute_pass);
+            end_pass(
+                &mut self.gpu_timer,
+                &mut encoder,
+                Pass::Compute,
+                start,
+                &mut cpu_times,
+            );
+            self.converged_readback.record(
+                &mut encoder,
+                &self.compute_pipeline.converged_buffer,
+                self.budget.epoch(),
+            );
+            self.reproject_pending = false;
+            let camera = &self.scene.camera;
+            self.history_camera = (camera.position, camera.reprojection_matrix());
         }
         // let view = self.render_pipeline.surface_texture_view();
 
         let render_bind_group = self.render_pipeline.bind_group.as_ref().unwrap();
+        let start = begin_pass(&mut self.gpu_timer, &mut encoder, Pass::Display);
         {
             // dispatch render pass
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
@@ -268,6 +410,14 @@ impl App {
             render_pass.set_bind_group(0, render_bind_group, &[]);
             render_pass.draw(0..6, 0..1);
         }
+        end_pass(
+            &mut self.gpu_timer,
+            &mut encoder,
+            Pass::Display,
+            start,
+            &mut cpu_times,
+        );
+        let start = begin_pass(&mut self.gpu_timer, &mut encoder, Pass::Ui);
         {
             // dispatch gui pass
 
@@ -286,61 +436,155 @@ impl App {
             });
             self.ui_manager.render(&mut render_pass);
         }
+        end_pass(
+            &mut self.gpu_timer,
+            &mut encoder,
+            Pass::Ui,
+            start,
+            &mut cpu_times,
+        );
+        if let Some(timer) = &mut self.gpu_timer {
+            timer.resolve(&mut encoder);
+        }
         // submit will accept anything that implements IntoIter
         self.queue.submit(iter::once(encoder.finish()));
+        self.converged_readback.submitted();
+        match &mut self.gpu_timer {
+            Some(timer) => timer.submitted(),
+            None => self.stats.pass_times = cpu_times,
+        }
         surface_texture.present();
     }
 
+    /// Keeps the current image and depth as history for the reprojection
+    fn copy_history(&self, encoder: &mut CommandEncoder) {
+        let pixels = &self.compute_pipeline.pixels;
+        encoder.copy_texture_to_texture(
+            self.render_pipeline.input_texture.as_image_copy(),
+            pixels.history_texture.as_image_copy(),
+            self.render_pipeline.input_texture.size(),
+        );
+        encoder.copy_buffer_to_buffer(
+            &pixels.depth,
+            0,
+            &pixels.history_depth,
+            0,
+            pixels.depth.size(),
+        );
+    }
     pub fn update(&mut self) {
         self.timer.update();
-        self.camera_uniform = CameraUniform::from(&self.scene.camera);
-        self.globals.seed = fastrand::u32(..);
-        self.renderer.acc_frame += 1;
+        if self.shader_watcher.enabled {
+            self.reload_shaders();
+        }
+        let dt = self.timer.dt();
+        if self.playback.update(&self.scene.timeline, dt) {
+            self.scene
+                .apply_animation(self.playback.time, &mut self.globals);
+            self.clear_renderer();
+        }
+        if self.simulation.update(&mut self.scene, dt) {
+            self.clear_renderer();
+        }
+        self.material_previews.update(
+            &self.device,
+            &self.queue,
+            self.ui_manager.context(),
+            &self.scene,
+            &self.globals,
+        );
+        let path_moved = self.player.update(&mut self.scene, dt);
+        if self.controller.update(&mut self.scene, dt) || path_moved {
+            self.camera_moved();
+        }
+        let (history_position, history_reprojection) = self.history_camera;
+        self.camera_uniform = CameraUniform::from(&self.scene.camera)
+            .with_history(history_position, history_reprojection);
+        self.globals.reproject = self.reproject_pending as u32;
+        let render_scale = self.preview.render_scale();
+        if render_scale != self.renderer.render_scale {
+            // switching between preview and full resolution
+            self.renderer.render_scale = render_scale;
+            self.reset_accumulation();
+        }
+        if let Some((converged, epoch)) = self.converged_readback.poll(&self.device) {
+            let (width, height) = self.renderer.render_size();
+            let pixels = (width * height).max(1);
+            self.budget
+                .report_converged(epoch, converged as f32 / pixels as f32);
+        }
+        if let Some(times) = self.gpu_timer.as_mut().and_then(|t| t.poll(&self.device)) {
+            self.stats.pass_times = times;
+        }
+        if self.tuner.enabled {
+            // averaged over a few frames, since single frame times are noisy
+            let frame_time = self.timer.average_dt(8) * 1000.;
+            self.globals.samples_per_frame =
+                self.tuner.tune(self.globals.samples_per_frame, frame_time);
+        }
+        self.budget.update(self.renderer.acc_samples);
+        self.frame_samples = match self.budget.remaining_samples(self.renderer.acc_samples) {
+            Some(remaining) => self.globals.samples_per_frame.min(remaining as u32),
+            None => self.globals.samples_per_frame,
+        }
+        .max(1);
+        let mut paths = 0;
+        if !self.budget.is_paused() {
+            self.renderer.acc_frame += 1;
+            self.renderer.acc_samples += self.frame_samples as usize;
+            self.renderer.dispatched_samples += self.frame_samples as usize;
+            let (width, height) = self.renderer.render_size();
+            paths = self.frame_samples as u64 * width as u64 * height as u64;
+        }
+        self.stats
+            .frame(Duration::from_secs_f32(self.timer.dt()), paths);
+        self.globals.acc_frame = self.renderer.acc_frame as u32;
+    }
+    /// Recompiles the pipelines whose shader changed on disk
+    fn reload_shaders(&mut self) {
+        for (name, source) in self.shader_watcher.poll() {
+            let result = match name.as_str() {
+                "compute.wgsl" => self
+                    .compute_pipeline
+                    .reload(&self.device, &source)
+                    .and_then(|()| self.material_previews.reload(&self.device, &source)),
+                "shader.wgsl" => self.render_pipeline.reload(&self.device, &source),
+                _ => continue,
+            };
+            if result.is_ok() {
+                info!("Reloaded {name}");
+                self.clear_renderer();
+            }
+            self.shader_watcher.report(name, result);
+        }
+    }
+    /// True if the sample budget is reached and no more samples are accumulated
+    pub fn is_paused(&self) -> bool {
+        self.budget.is_paused()
     }
     pub fn window(&self) -> &Window {
         &self.window
     }
-
-    pub fn handle_keyboard_input(&mut self, input: &KeyboardInput) {
-        let moved = self.scene.camera.on_keyboard_event(input, self.timer.dt());
-        if moved {
-            self.clear_renderer();
-        }
-    }
-}
-
-trait BufferSet {
-    fn write_buffer<T: Pod>(self, item: T, destination: &Buffer, device: &Device) -> Self;
-    fn write_slice_buffer<T: Pod>(self, item: &[T], destination: &Buffer, device: &Device) -> Self;
 }
-impl BufferSet for CommandEncoder {
-    fn write_buffer<T: Pod>(mut self, item: T, destination: &Buffer, device: &Device) -> Self {
-        let t_size = std::mem::size_of::<T>();
-        let name = type_name::<T>();
-        let source = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: format!("{name} buffer").as_str().into(),
-            contents: bytemuck::cast_slice(&[item]),
-            usage: wgpu::BufferUsages::COPY_SRC,
-        });
 
-        self.copy_buffer_to_buffer(&source, 0, &destination, 0, t_size as BufferAddress);
-        self
+/// Starts timing `pass`, on the gpu if timestamp queries are supported
+fn begin_pass(timer: &mut Option<GpuTimer>, encoder: &mut CommandEncoder, pass: Pass) -> Instant {
+    if let Some(timer) = timer {
+        timer.begin(encoder, pass);
     }
-    fn write_slice_buffer<T: Pod>(
-        mut self,
-        item: &[T],
-        destination: &Buffer,
-        device: &Device,
-    ) -> Self {
-        let t_size = std::mem::size_of::<T>() * item.len();
-        let name = type_name::<T>();
-        let source = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: format!("{name} buffer").as_str().into(),
-            contents: bytemuck::cast_slice(item),
-            usage: wgpu::BufferUsages::COPY_SRC,
-        });
+    Instant::now()
+}
 
-        self.copy_buffer_to_buffer(&source, 0, &destination, 0, t_size as BufferAddress);
-        self
+/// Stops timing `pass` and records the cpu time spent recording it
+fn end_pass(
+    timer: &mut Option<GpuTimer>,
+    encoder: &mut CommandEncoder,
+    pass: Pass,
+    start: Instant,
+    cpu_times: &mut PassTimes,
+) {
+    cpu_times[pass as usize] = Some(start.elapsed());
+    if let Some(timer) = timer {
+        timer.end(encoder, pass);
     }
 }
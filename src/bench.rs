@@ -0,0 +1,405 @@
+//! `rays bench`: renders the built-in scenes headlessly and reports the performance.
+//!
+//! The reports are written as json or csv, so runs on different drivers and hardware
+//! can be compared. A previous csv report can be passed as baseline to print the changes.
+use std::{
+    collections::HashMap,
+    fs,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use log::info;
+use winit::dpi::PhysicalSize;
+
+use crate::{
+    renderer::headless::Headless,
+    scenes::{self, SceneFn},
+};
+
+pub const USAGE: &str = "usage: rays bench [options]
+    --samples <n>             samples per pixel of every image (default 64)
+    --resolutions <list>      comma separated, e.g. 640x360,1920x1080
+    --scenes <list>           comma separated names of built-in scenes (default all)
+    --json <path>             write the report as json
+    --csv <path>              write the report as csv
+    --baseline <path>         csv report of an earlier run to compare against";
+
+const CSV_HEADER: [&str; 12] = [
+    "adapter",
+    "backend",
+    "driver",
+    "scene",
+    "width",
+    "height",
+    "samples",
+    "seconds",
+    "ms_per_sample",
+    "paths_per_second",
+    "gpu_memory_bytes",
+    "process_peak_rss_bytes",
+];
+
+pub struct BenchConfig {
+    pub samples: u32,
+    pub resolutions: Vec<PhysicalSize<u32>>,
+    /// Names of the scenes in [`scenes::ALL`]
+    pub scenes: Vec<String>,
+    pub json: Option<PathBuf>,
+    pub csv: Option<PathBuf>,
+    pub baseline: Option<PathBuf>,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        Self {
+            samples: 64,
+            resolutions: vec![
+                PhysicalSize::new(640, 360),
+                PhysicalSize::new(1280, 720),
+                PhysicalSize::new(1920, 1080),
+            ],
+            scenes: scenes::ALL
+                .iter()
+                .map(|(name, _)| name.to_string())
+                .collect(),
+            json: None,
+            csv: None,
+            baseline: None,
+        }
+    }
+}
+
+impl BenchConfig {
+    /// Parses the arguments following `bench`. Returns None if `--help` asks for the usage
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Result<Option<Self>, String> {
+        let mut config = BenchConfig::default();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("{arg} needs a value"));
+            match arg.as_str() {
+                "--samples" => {
+                    config.samples = value()?
+                        .parse()
+                        .map_err(|e| format!("invalid sample count: {e}"))?;
+                }
+                "--resolutions" => {
+                    config.resolutions = value()?
+                        .split(',')
+                        .map(parse_resolution)
+                        .collect::<Result<_, _>>()?;
+                }
+                "--scenes" => {
+                    let scenes: Vec<String> = value()?.split(',').map(str::to_owned).collect();
+                    if let Some(unknown) = scenes.iter().find(|name| find_scene(name).is_none()) {
+                        return Err(format!("unknown scene {unknown}"));
+                    }
+                    config.scenes = scenes;
+                }
+                "--json" => config.json = Some(value()?.into()),
+                "--csv" => config.csv = Some(value()?.into()),
+                "--baseline" => config.baseline = Some(value()?.into()),
+                "--help" => return Ok(None),
+                _ => return Err(format!("unknown argument {arg}\n{USAGE}")),
+            }
+        }
+        if config.samples == 0 {
+            return Err("at least one sample is needed".to_owned());
+        }
+        Ok(Some(config))
+    }
+}
+
+pub(crate) fn parse_resolution(resolution: &str) -> Result<PhysicalSize<u32>, String> {
+    let invalid = || format!("invalid resolution {resolution}, expected e.g. 1920x1080");
+    let (width, height) = resolution.split_once('x').ok_or_else(invalid)?;
+    let width: u32 = width.parse().map_err(|_| invalid())?;
+    let height: u32 = height.parse().map_err(|_| invalid())?;
+    if width == 0 || height == 0 {
+        return Err(invalid());
+    }
+    Ok(PhysicalSize::new(width, height))
+}
+
+fn find_scene(name: &str) -> Option<SceneFn> {
+    scenes::ALL
+        .iter()
+        .find(|(scene, _)| *scene == name)
+        .map(|(_, create)| *create)
+}
+
+/// Measurement of a single scene at a single resolution
+pub struct BenchResult {
+    pub scene: String,
+    pub size: PhysicalSize<u32>,
+    pub samples: u32,
+    pub render_time: Duration,
+    pub gpu_memory: u64,
+    /// Peak resident memory of the whole process up to this result, which includes the
+    /// results before it. Not the memory of this render alone. Only known on linux
+    pub process_peak_rss: Option<u64>,
+}
+
+impl BenchResult {
+    pub fn ms_per_sample(&self) -> f64 {
+        self.render_time.as_secs_f64() * 1000. / self.samples as f64
+    }
+    /// One path per sample of every pixel, like the stats window
+    pub fn paths_per_second(&self) -> f64 {
+        let paths = self.samples as f64 * self.size.width as f64 * self.size.height as f64;
+        paths / self.render_time.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+pub struct Report {
+    pub adapter: String,
+    pub backend: String,
+    pub driver: String,
+    /// Seconds since the unix epoch at which the benchmark ran
+    pub unix_time: u64,
+    pub results: Vec<BenchResult>,
+}
+
+impl Report {
+    pub fn to_json(&self) -> String {
+        let results: Vec<String> = self
+            .results
+            .iter()
+            .map(|r| {
+                format!(
+                    "    {{\"scene\": {}, \"width\": {}, \"height\": {}, \"samples\": {}, \
+                     \"seconds\": {:.6}, \"ms_per_sample\": {:.6}, \"paths_per_second\": {:.1}, \
+                     \"gpu_memory_bytes\": {}, \"process_peak_rss_bytes\": {}}}",
+                    json_string(&r.scene),
+                    r.size.width,
+                    r.size.height,
+                    r.samples,
+                    r.render_time.as_secs_f64(),
+                    r.ms_per_sample(),
+                    r.paths_per_second(),
+                    r.gpu_memory,
+                    r.process_peak_rss
+                        .map_or("null".to_owned(), |rss| rss.to_string()),
+                )
+            })
+            .collect();
+        format!(
+            "{{\n  \"version\": {},\n  \"adapter\": {},\n  \"backend\": {},\n  \"driver\": {},\n  \
+             \"unix_time\": {},\n  \"results\": [\n{}\n  ]\n}}\n",
+            json_string(env!("CARGO_PKG_VERSION")),
+            json_string(&self.adapter),
+            json_string(&self.backend),
+            json_string(&self.driver),
+            self.unix_time,
+            results.join(",\n"),
+        )
+    }
+    /// One row per result. The adapter is repeated in every row,
+    /// so reports of several machines can simply be concatenated
+    pub fn to_csv(&self) -> String {
+        let mut csv = CSV_HEADER.join(",") + "\n";
+        for r in &self.results {
+            let row = [
+                csv_field(&self.adapter),
+                csv_field(&self.backend),
+                csv_field(&self.driver),
+                csv_field(&r.scene),
+                r.size.width.to_string(),
+                r.size.height.to_string(),
+                r.samples.to_string(),
+                format!("{:.6}", r.render_time.as_secs_f64()),
+                format!("{:.6}", r.ms_per_sample()),
+                format!("{:.1}", r.paths_per_second()),
+                r.gpu_memory.to_string(),
+                r.process_peak_rss
+                    .map_or(String::new(), |rss| rss.to_string()),
+            ];
+            csv += &row.join(",");
+            csv += "\n";
+        }
+        csv
+    }
+}
+
+fn json_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_owned()
+    }
+}
+
+/// Splits a csv line, respecting quoted fields
+fn csv_split(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                fields.last_mut().unwrap().push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(String::new()),
+            c => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields
+}
+
+/// Paths per second of every scene and resolution in a csv report
+pub fn read_baseline(csv: &str) -> Result<HashMap<(String, u32, u32), f64>, String> {
+    let mut lines = csv.lines();
+    let header = csv_split(lines.next().ok_or("the baseline is empty")?);
+    let column = |name: &str| {
+        header
+            .iter()
+            .position(|h| h == name)
+            .ok_or(format!("the baseline has no {name} column"))
+    };
+    let (scene, width, height, paths) = (
+        column("scene")?,
+        column("width")?,
+        column("height")?,
+        column("paths_per_second")?,
+    );
+    let mut baseline = HashMap::new();
+    for line in lines.filter(|line| !line.is_empty()) {
+        let fields = csv_split(line);
+        let field = |i: usize| fields.get(i).map(String::as_str).unwrap_or_default();
+        let invalid = || format!("invalid baseline row {line}");
+        baseline.insert(
+            (
+                field(scene).to_owned(),
+                field(width).parse().map_err(|_| invalid())?,
+                field(height).parse().map_err(|_| invalid())?,
+            ),
+            field(paths).parse().map_err(|_| invalid())?,
+        );
+    }
+    Ok(baseline)
+}
+
+/// Peak resident set size of the process since it started, in bytes
+fn process_peak_rss() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kilobytes: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kilobytes * 1024)
+}
+
+/// Renders every configured scene at every resolution
+pub fn run(config: &BenchConfig) -> Result<Report, String> {
+    let headless = Headless::new().ok_or("no suitable gpu adapter found")?;
+    let adapter = headless.adapter_info();
+    let mut results = Vec::new();
+    for name in &config.scenes {
+        let create = find_scene(name).ok_or(format!("unknown scene {name}"))?;
+        // the first dispatch may include driver side shader compilation
+        let (mut scene, globals) = create();
+        headless.render(&mut scene, globals, PhysicalSize::new(16, 16), 1);
+
+        for &size in &config.resolutions {
+            let (mut scene, globals) = create();
+            scene.camera.resize(size);
+            let rendered = headless
+                .render(&mut scene, globals, size, config.samples)
+                .ok_or(format!("could not read back {name}"))?;
+            let result = BenchResult {
+                scene: name.clone(),
+                size,
+                samples: config.samples,
+                render_time: rendered.render_time,
+                gpu_memory: rendered.gpu_memory,
+                process_peak_rss: process_peak_rss(),
+            };
+            info!(
+                "{name} {}x{}: {:.3} ms per sample, {:.1} Mpaths/s",
+                size.width,
+                size.height,
+                result.ms_per_sample(),
+                result.paths_per_second() / 1e6
+            );
+            results.push(result);
+        }
+    }
+    Ok(Report {
+        adapter: adapter.name.clone(),
+        backend: format!("{:?}", adapter.backend),
+        driver: format!("{} {}", adapter.driver, adapter.driver_info),
+        unix_time: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs()),
+        results,
+    })
+}
+
+/// Entry point of `rays bench`, `args` are the arguments following `bench`
+pub fn main(args: impl Iterator<Item = String>) -> Result<(), String> {
+    let Some(config) = BenchConfig::from_args(args)? else {
+        println!("{USAGE}");
+        return Ok(());
+    };
+    let baseline = match &config.baseline {
+        Some(path) => {
+            let csv = fs::read_to_string(path)
+                .map_err(|e| format!("could not read {}: {e}", path.display()))?;
+            Some(read_baseline(&csv)?)
+        }
+        None => None,
+    };
+    let report = run(&config)?;
+
+    if let Some(path) = &config.json {
+        fs::write(path, report.to_json())
+            .map_err(|e| format!("could not write {}: {e}", path.display()))?;
+    }
+    if let Some(path) = &config.csv {
+        fs::write(path, report.to_csv())
+            .map_err(|e| format!("could not write {}: {e}", path.display()))?;
+    }
+    if config.json.is_none() && config.csv.is_none() {
+        print!("{}", report.to_json());
+    }
+    if let Some(baseline) = baseline {
+        println!("scene, resolution: paths/s (baseline) change");
+        for r in &report.results {
+            let key = (r.scene.clone(), r.size.width, r.size.height);
+            let paths = r.paths_per_second();
+            match baseline.get(&key) {
+                Some(&before) => println!(
+                    "{}, {}x{}: {:.1} M ({:.1} M) {:+.1}%",
+                    r.scene,
+                    r.size.width,
+                    r.size.height,
+                    paths / 1e6,
+                    before / 1e6,
+                    (paths / before - 1.) * 100.
+                ),
+                None => println!(
+                    "{}, {}x{}: {:.1} M (not in baseline)",
+                    r.scene,
+                    r.size.width,
+                    r.size.height,
+                    paths / 1e6
+                ),
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,192 @@
+use std::time::{Duration, Instant};
+
+/// Condition after which the accumulation pauses
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SampleTarget {
+    /// Accumulate forever
+    Unlimited,
+    /// Stop after the given amount of samples per pixel
+    SamplesPerPixel(u32),
+    /// Stop after rendering for the given amount of seconds
+    TimeLimit(f32),
+    /// Stop once the given fraction of pixels is below the noise threshold.
+    /// The threshold itself is `Globals::convergence_threshold`
+    Converged(f32),
+}
+
+/// Keeps track of how much work went into the current image and decides when to stop.
+pub struct SampleBudget {
+    pub target: SampleTarget,
+    /// Samples per pixel added when continuing a finished render
+    pub continue_samples: u32,
+    /// If set, the target was reached and the user asked for more samples up to this amount
+    extended_until: Option<usize>,
+    /// Samples and elapsed time at which the render was continued
+    extended_from: (usize, Duration),
+    /// Time spent rendering since the last reset. Paused time is not counted
+    elapsed: Duration,
+    last_update: Option<Instant>,
+    /// Fraction of converged pixels as reported by the gpu
+    converged_fraction: Option<f32>,
+    /// Incremented on every reset, so stale gpu reports can be detected
+    epoch: u32,
+    finished: bool,
+}
+
+impl SampleBudget {
+    pub fn new(target: SampleTarget) -> Self {
+        Self {
+            target,
+            continue_samples: 256,
+            extended_until: None,
+            extended_from: (0, Duration::ZERO),
+            elapsed: Duration::ZERO,
+            last_update: None,
+            converged_fraction: None,
+            epoch: 0,
+            finished: false,
+        }
+    }
+    /// Has to be called whenever the accumulation restarts
+    pub fn reset(&mut self) {
+        self.extended_until = None;
+        self.elapsed = Duration::ZERO;
+        self.last_update = None;
+        self.converged_fraction = None;
+        self.epoch = self.epoch.wrapping_add(1);
+        self.finished = false;
+    }
+    pub fn epoch(&self) -> u32 {
+        self.epoch
+    }
+    /// Updates the converged fraction if the report belongs to the current accumulation
+    pub fn report_converged(&mut self, epoch: u32, converged_fraction: f32) {
+        if epoch == self.epoch {
+            self.converged_fraction = Some(converged_fraction);
+        }
+    }
+    /// True if no more samples should be accumulated
+    pub fn is_paused(&self) -> bool {
+        self.finished
+    }
+    /// Should be called once per frame, with the amount of samples per pixel accumulated so far.
+    pub fn update(&mut self, acc_samples: usize) {
+        let now = Instant::now();
+        if self.finished {
+            self.last_update = None;
+            return;
+        }
+        if let Some(last) = self.last_update {
+            self.elapsed += now.duration_since(last);
+        }
+        self.last_update = Some(now);
+        self.finished = match self.extended_until {
+            Some(until) => acc_samples >= until,
+            None => self.progress(acc_samples) >= 1.,
+        };
+    }
+    /// Drops a previous continuation and checks the target again on the next update.
+    /// Used after the target got changed
+    pub fn retarget(&mut self) {
+        self.extended_until = None;
+        self.finished = false;
+    }
+    /// Adds `continue_samples` more samples to a paused render
+    pub fn continue_render(&mut self, acc_samples: usize) {
+        self.extended_from = (acc_samples, self.elapsed);
+        self.extended_until = Some(acc_samples + self.continue_samples as usize);
+        self.finished = false;
+    }
+    /// Samples per pixel left until the target is reached, if the target is sample based
+    pub fn remaining_samples(&self, acc_samples: usize) -> Option<usize> {
+        match (self.extended_until, self.target) {
+            (Some(until), _) => Some(until.saturating_sub(acc_samples)),
+            (None, SampleTarget::SamplesPerPixel(spp)) => {
+                Some((spp as usize).saturating_sub(acc_samples))
+            }
+            _ => None,
+        }
+    }
+    /// Progress towards the current target between 0 and 1
+    pub fn progress(&self, acc_samples: usize) -> f32 {
+        if let Some(until) = self.extended_until {
+            let total = until.saturating_sub(self.extended_from.0).max(1);
+            let done = acc_samples.saturating_sub(self.extended_from.0);
+            return (done as f32 / total as f32).min(1.);
+        }
+        let progress = match self.target {
+            SampleTarget::Unlimited => 0.,
+            SampleTarget::SamplesPerPixel(spp) => acc_samples as f32 / spp.max(1) as f32,
+            SampleTarget::TimeLimit(seconds) => self.elapsed.as_secs_f32() / seconds.max(0.001),
+            SampleTarget::Converged(fraction) => {
+                self.converged_fraction.unwrap_or(0.) / fraction.clamp(0.001, 1.)
+            }
+        };
+        progress.min(1.)
+    }
+    /// Estimated time until the target is reached.
+    /// Extrapolates linearly from the progress made so far
+    pub fn eta(&self, acc_samples: usize) -> Option<Duration> {
+        if self.finished
+            || (self.target == SampleTarget::Unlimited && self.extended_until.is_none())
+        {
+            return None;
+        }
+        if let (SampleTarget::TimeLimit(seconds), None) = (self.target, self.extended_until) {
+            return Some(Duration::from_secs_f32(seconds).saturating_sub(self.elapsed));
+        }
+        let progress = self.progress(acc_samples);
+        if progress <= 0. {
+            return None;
+        }
+        let elapsed = match self.extended_until {
+            Some(_) => self.elapsed.saturating_sub(self.extended_from.1),
+            None => self.elapsed,
+        }
+        .as_secs_f32();
+        Some(Duration::from_secs_f32(
+            elapsed * (1. - progress) / progress,
+        ))
+    }
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+}
+
+/// Adjusts the amount of samples per frame so that a frame takes roughly the target time.
+/// Tracing more paths per dispatch keeps fast gpus busy instead of waiting on presentation
+pub struct SampleTuner {
+    pub enabled: bool,
+    /// Desired frame time in milliseconds
+    pub target_frame_time: f32,
+    /// Upper bound for the amount of samples per frame
+    pub max_samples_per_frame: u32,
+}
+
+impl SampleTuner {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            target_frame_time: 16.,
+            max_samples_per_frame: 64,
+        }
+    }
+    /// Returns the new amount of samples per frame, given the current one and the measured frame time in milliseconds
+    pub fn tune(&self, samples_per_frame: u32, frame_time: f32) -> u32 {
+        let samples = samples_per_frame.max(1);
+        let tuned = if frame_time > self.target_frame_time * 1.1 {
+            samples - samples / 4
+        } else if frame_time < self.target_frame_time * 0.8 {
+            samples + samples / 4 + 1
+        } else {
+            samples
+        };
+        tuned.clamp(1, self.max_samples_per_frame.max(1))
+    }
+}
+
+impl Default for SampleTuner {
+    fn default() -> Self {
+        SampleTuner::new()
+    }
+}
@@ -1,10 +1,123 @@
-use glam::{Mat4, Quat, Vec2, Vec3};
-use winit::{
-    dpi::{PhysicalPosition, PhysicalSize},
-    event::{KeyboardInput, VirtualKeyCode},
-};
+use glam::{Mat3, Mat4, UVec2, Vec2, Vec3, Vec4, Vec4Swizzles};
+use winit::dpi::PhysicalSize;
 
-use crate::math;
+use std::f32::consts::PI;
+
+use crate::layout::shader_layout;
+
+/// How the rays are distributed over the image
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Projection {
+    #[default]
+    Perspective,
+    /// Parallel rays, e.g. for architectural elevations.
+    /// `width` is the width of the visible area in world units
+    Orthographic { width: f32 },
+    /// Equidistant fisheye. The image circle spans the width of the image and covers `fov` degrees
+    Fisheye { fov: f32 },
+    /// Full 360° panorama with the longitude along x and the latitude along y
+    Equirectangular,
+}
+
+impl Projection {
+    pub const ALL: [Projection; 4] = [
+        Projection::Perspective,
+        Projection::Orthographic { width: 10. },
+        Projection::Fisheye { fov: 180. },
+        Projection::Equirectangular,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Projection::Perspective => "perspective",
+            Projection::Orthographic { .. } => "orthographic",
+            Projection::Fisheye { .. } => "fisheye",
+            Projection::Equirectangular => "equirectangular 360°",
+        }
+    }
+    /// Index used by the shader
+    pub fn index(self) -> u32 {
+        match self {
+            Projection::Perspective => 0,
+            Projection::Orthographic { .. } => 1,
+            Projection::Fisheye { .. } => 2,
+            Projection::Equirectangular => 3,
+        }
+    }
+}
+
+/// How the two eyes of a stereo image are arranged in the output
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StereoLayout {
+    #[default]
+    Mono,
+    /// Left eye in the left half, right eye in the right half
+    SideBySide,
+    /// Left eye in the top half, right eye in the bottom half.
+    /// The usual layout of stereo 360° panoramas
+    TopBottom,
+}
+
+impl StereoLayout {
+    pub const ALL: [StereoLayout; 3] = [
+        StereoLayout::Mono,
+        StereoLayout::SideBySide,
+        StereoLayout::TopBottom,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            StereoLayout::Mono => "mono",
+            StereoLayout::SideBySide => "side by side",
+            StereoLayout::TopBottom => "top bottom",
+        }
+    }
+}
+
+/// Stereoscopic rendering with two horizontally offset eyes.
+/// With the equirectangular projection the eyes rotate with the viewing direction,
+/// which results in an omni-directional stereo panorama
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stereo {
+    pub layout: StereoLayout,
+    /// Interpupillary distance in world units
+    pub eye_distance: f32,
+    /// Distance at which both eyes see the same point.
+    /// Objects at this distance appear at the depth of the screen
+    pub convergence: f32,
+}
+
+impl Default for Stereo {
+    fn default() -> Self {
+        Self {
+            layout: StereoLayout::Mono,
+            eye_distance: 0.064,
+            convergence: 10.,
+        }
+    }
+}
+
+impl Stereo {
+    /// Splits `pixel` of an image with the given size into the eye it belongs to
+    /// (-1 left, 1 right, 0 mono), the pixel within the eye and the size of the eye
+    pub fn split(&self, pixel: UVec2, size: UVec2) -> (f32, UVec2, UVec2) {
+        let axis = match self.layout {
+            StereoLayout::Mono => return (0., pixel, size),
+            StereoLayout::SideBySide => 0,
+            StereoLayout::TopBottom => 1,
+        };
+        let (mut pixel, mut size) = (pixel, size);
+        let half = size[axis] / 2;
+        if pixel[axis] < half {
+            size[axis] = half;
+            (-1., pixel, size)
+        } else {
+            pixel[axis] -= half;
+            size[axis] -= half;
+            (1., pixel, size)
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct Camera {
@@ -13,6 +126,15 @@ pub struct Camera {
     /// Field of view corresponds to how much we can see.
     /// In this implementation the horizontal fov is used
     pub fov: f32,
+    pub projection: Projection,
+    pub stereo: Stereo,
+    /// Interval in seconds, relative to the frame, in which the shutter is open.
+    /// Moving spheres are blurred over it. An empty interval disables motion blur
+    pub shutter: [f32; 2],
+    /// Radius of the lens in world units. Zero keeps everything sharp
+    pub aperture: f32,
+    /// Distance along the view rays which is in focus
+    pub focus_distance: f32,
     /// forward view direction of the camera
     forward: Vec3,
     // perspective projection
@@ -22,8 +144,6 @@ pub struct Camera {
     near_clip: f32,
     /// farest object distance that is still visible
     far_clip: f32,
-
-    pub last_mouse_position: Option<PhysicalPosition<f64>>,
 }
 
 impl Camera {
@@ -35,20 +155,22 @@ impl Camera {
         viewport_height: f32,
     ) -> Camera {
         let forward = -Vec3::X;
-        // #TODO no reason to hardcode a position
-        let position = Vec3::new(77.7, -7.4, 10.);
+        let position = Vec3::ZERO;
 
-        let camera = Camera {
+        Camera {
             forward,
+            projection: Projection::default(),
+            stereo: Stereo::default(),
+            shutter: [0.; 2],
+            aperture: 0.,
+            focus_distance: 10.,
             position,
             fov,
             near_clip,
             far_clip,
             viewport_height,
             viewport_width,
-            last_mouse_position: None,
-        };
-        camera
+        }
     }
     /// In case of a resize event of the viewport.
     /// This can happen whenever the window itself get resized
@@ -61,55 +183,119 @@ impl Camera {
         self.viewport_width = width as f32;
         self.viewport_height = height as f32;
     }
-    /// Handles keyboard events that let the camera move
-    pub fn on_keyboard_event(&mut self, input: &KeyboardInput, dt: f32) -> bool {
-        let speed = 30. * dt;
-        let up = Vec3::Y;
-        let right_direction = self.forward.cross(up);
-        match input.virtual_keycode {
-            Some(VirtualKeyCode::W) => self.position += self.forward * speed,
-            Some(VirtualKeyCode::S) => self.position -= self.forward * speed,
-            Some(VirtualKeyCode::A) => self.position -= right_direction * speed,
-            Some(VirtualKeyCode::D) => self.position += right_direction * speed,
-            Some(VirtualKeyCode::Q) => self.position -= up * speed,
-            Some(VirtualKeyCode::E) => self.position += up * speed,
-            _ => return false,
-        }
-        true
-    }
-    /// Handles mouse drags, which are used to rotate the camera
-    pub fn on_rotate(&mut self, mouse_position: &PhysicalPosition<f64>) {
-        let right_direction = self.forward.cross(Vec3::Y);
-        match self.last_mouse_position {
-            Some(last) => {
-                let last = Vec2::new(last.x as f32, last.y as f32);
-                let current = Vec2::new(mouse_position.x as f32, mouse_position.y as f32);
-                let delta = 0.006 * (current - last);
-
-                let q1 = Quat::from_axis_angle(right_direction, -delta.y);
-                let q2 = Quat::from_axis_angle(Vec3::Y, -delta.x);
-                let q = math::cross(q1, q2).normalize();
-                self.forward = q * self.forward;
-
-                self.last_mouse_position = Some(*mouse_position);
-            }
-            None => self.last_mouse_position = Some(*mouse_position),
-        }
+    /// Turns the camera towards `target`
+    pub fn look_at(&mut self, target: Vec3) {
+        self.forward = (target - self.position).normalize();
+    }
+    pub fn forward(&self) -> Vec3 {
+        self.forward
+    }
+    pub fn set_forward(&mut self, forward: Vec3) {
+        self.forward = forward.normalize();
     }
-
     fn inverse_view_matrix(&self) -> Mat4 {
         Mat4::look_to_rh(self.position, self.forward, Vec3::Y).inverse()
     }
     fn inverse_projection_matrix(&self) -> Mat4 {
         let fov = self.fov.to_radians();
+        // every eye of a stereo image only covers half of the viewport
+        let (width, height) = match self.stereo.layout {
+            StereoLayout::Mono => (self.viewport_width, self.viewport_height),
+            StereoLayout::SideBySide => (self.viewport_width * 0.5, self.viewport_height),
+            StereoLayout::TopBottom => (self.viewport_width, self.viewport_height * 0.5),
+        };
 
-        Mat4::perspective_rh(
-            fov,
-            self.viewport_width / self.viewport_height,
-            self.near_clip,
-            self.far_clip,
-        )
-        .inverse()
+        Mat4::perspective_rh(fov, width / height, self.near_clip, self.far_clip).inverse()
+    }
+    /// Origin and direction of the ray through `pixel` of an image with the given size.
+    /// None for pixels outside of the image circle of a fisheye.
+    /// Mirrors `calc_ray` in the compute shader
+    pub fn ray(&self, pixel: UVec2, size: UVec2) -> Option<(Vec3, Vec3)> {
+        let (eye, pixel, size) = self.stereo.split(pixel, size);
+        let size = size.as_vec2();
+        let coord = pixel.as_vec2() / size * 2. - Vec2::ONE;
+        let mut view_origin = Vec3::ZERO;
+        let mut view_direction = match self.projection {
+            Projection::Perspective => {
+                let target1 = self.inverse_projection_matrix().transpose()
+                    * Vec4::new(coord.x, coord.y, 1., 1.);
+                (target1.xyz() / target1.w).normalize()
+            }
+            Projection::Orthographic { width } => {
+                let half_size = Vec2::new(width, width * size.y / size.x) * 0.5;
+                view_origin = (coord * half_size).extend(0.);
+                Vec3::new(0., 0., -1.)
+            }
+            Projection::Fisheye { fov } => {
+                let p = Vec2::new(coord.x, coord.y * size.y / size.x);
+                let r = p.length();
+                if r > 1. {
+                    return None;
+                }
+                let theta = r * fov.to_radians() * 0.5;
+                let xy = if r > 0. {
+                    p / r * theta.sin()
+                } else {
+                    Vec2::ZERO
+                };
+                xy.extend(-theta.cos())
+            }
+            Projection::Equirectangular => {
+                let longitude = coord.x * PI;
+                let latitude = coord.y * PI * 0.5;
+                Vec3::new(
+                    latitude.cos() * longitude.sin(),
+                    latitude.sin(),
+                    -latitude.cos() * longitude.cos(),
+                )
+            }
+        };
+        if eye != 0. {
+            let axis = if self.projection == Projection::Equirectangular {
+                // omni-directional stereo: the eyes rotate with the viewing direction
+                let longitude = coord.x * PI;
+                Vec3::new(longitude.cos(), 0., longitude.sin())
+            } else {
+                Vec3::X
+            };
+            let offset = axis * eye * self.stereo.eye_distance * 0.5;
+            view_direction = (view_direction * self.stereo.convergence - offset).normalize();
+            view_origin += offset;
+        }
+        let inverse_view = self.inverse_view_matrix();
+        Some((
+            self.position + (inverse_view * view_origin.extend(0.)).xyz(),
+            (inverse_view * view_direction.extend(0.)).xyz(),
+        ))
+    }
+    /// Moves the origin of a ray from [`Camera::ray`] to a point on the lens, given by a
+    /// sample `u` in [0, 1)², keeping the point at the focus distance.
+    /// The lens lies in the image plane for every projection.
+    /// Mirrors `thin_lens` in the compute shader
+    pub fn lens_ray(&self, origin: Vec3, direction: Vec3, u: Vec2) -> (Vec3, Vec3) {
+        let r = self.aperture * u.x.sqrt();
+        let phi = 2. * PI * u.y;
+        let offset = self.inverse_view_matrix() * Vec4::new(r * phi.cos(), r * phi.sin(), 0., 0.);
+        let focus = origin + direction * self.focus_distance;
+        let origin = origin + offset.xyz();
+        (origin, (focus - origin).normalize())
+    }
+    /// Maps world space positions to homogeneous screen coordinates.
+    ///
+    /// This is the inverse of the perspective projection in `calc_ray`:
+    /// for `h = M * p` the screen coordinate in [-1, 1] is `h.xy / h.z`,
+    /// and the position is in front of the camera if `h.z > 0`.
+    pub fn reprojection_matrix(&self) -> Mat4 {
+        // the shader computes the view direction as A * (x, y, 1, 1)
+        let a = self.inverse_projection_matrix().transpose();
+        let w = a.col(2).w + a.col(3).w;
+        let screen_to_view = Mat3::from_cols(
+            a.col(0).xyz(),
+            a.col(1).xyz(),
+            a.col(2).xyz() + a.col(3).xyz(),
+        ) * (1. / w);
+        let view = Mat4::look_to_rh(self.position, self.forward, Vec3::Y);
+        Mat4::from_mat3(screen_to_view.inverse()) * view
     }
 }
 
@@ -122,11 +308,61 @@ pub struct CameraUniform {
     fov: [f32; 2],
     viewport: [f32; 2],
     camera_position: [f32; 4],
+    /// Position of the camera which rendered the accumulated history
+    previous_position: [f32; 4],
+    /// [`Projection::index`]
+    projection: u32,
+    ortho_width: f32,
+    /// In degrees
+    fisheye_fov: f32,
     // offset needed for padding restrictions
-    _offset1: [f32; 8],
+    _offset1: f32,
+    /// [`StereoLayout`] as index
+    stereo_layout: u32,
+    eye_distance: f32,
+    convergence: f32,
+    _offset2: f32,
+    /// [`Camera::shutter`]
+    shutter: [f32; 2],
+    /// [`Camera::aperture`]
+    aperture: f32,
+    /// [`Camera::focus_distance`]
+    focus_distance: f32,
     inverse_projection: [f32; 16],
     inverse_view: [f32; 16],
-    _offset2: [f32; 16],
+    /// [`Camera::reprojection_matrix`] of the camera which rendered the accumulated history
+    previous_reprojection: [f32; 16],
+}
+
+shader_layout!(CameraUniform => "Camera" {
+    fov,
+    viewport,
+    camera_position,
+    previous_position,
+    projection,
+    ortho_width,
+    fisheye_fov,
+    _offset1,
+    stereo_layout,
+    eye_distance,
+    convergence,
+    _offset2,
+    shutter,
+    aperture,
+    focus_distance,
+    inverse_projection,
+    inverse_view,
+    previous_reprojection,
+});
+
+impl CameraUniform {
+    /// Sets the camera the accumulated history was rendered with.
+    /// Used for temporal reprojection
+    pub fn with_history(mut self, position: Vec3, reprojection: Mat4) -> Self {
+        self.previous_position = position.extend(0.).to_array();
+        self.previous_reprojection = reprojection.to_cols_array();
+        self
+    }
 }
 
 impl From<&Camera> for CameraUniform {
@@ -135,14 +371,30 @@ impl From<&Camera> for CameraUniform {
         let camera_position = cam.position.extend(0.).to_array();
         let inverse_projection = cam.inverse_projection_matrix().to_cols_array();
         let inverse_view = cam.inverse_view_matrix().to_cols_array();
+        let (ortho_width, fisheye_fov) = match cam.projection {
+            Projection::Orthographic { width } => (width, 0.),
+            Projection::Fisheye { fov } => (0., fov),
+            _ => (0., 0.),
+        };
         Self {
             fov: [cam.fov; 2],
             viewport,
-            _offset1: [0.; 8],
+            projection: cam.projection.index(),
+            ortho_width,
+            fisheye_fov,
+            _offset1: 0.,
+            stereo_layout: cam.stereo.layout as u32,
+            eye_distance: cam.stereo.eye_distance,
+            convergence: cam.stereo.convergence,
+            _offset2: 0.,
+            shutter: cam.shutter,
+            aperture: cam.aperture,
+            focus_distance: cam.focus_distance,
             camera_position,
+            previous_position: camera_position,
             inverse_projection,
             inverse_view,
-            _offset2: [0.; 16],
+            previous_reprojection: cam.reprojection_matrix().to_cols_array(),
         }
     }
 }
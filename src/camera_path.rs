@@ -0,0 +1,216 @@
+//! Camera bookmarks and keyframed camera paths for flythroughs.
+use std::ops::{Add, Mul, Sub};
+
+use glam::Vec3;
+
+use crate::{camera::Camera, scene::Scene};
+
+/// Everything needed to return to a view
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraPose {
+    pub position: Vec3,
+    /// Normalized view direction
+    pub forward: Vec3,
+    pub fov: f32,
+    /// [`Camera::focus_distance`]
+    pub focus: f32,
+}
+
+impl Camera {
+    pub fn pose(&self) -> CameraPose {
+        CameraPose {
+            position: self.position,
+            forward: self.forward(),
+            fov: self.fov,
+            focus: self.focus_distance,
+        }
+    }
+    pub fn set_pose(&mut self, pose: CameraPose) {
+        self.position = pose.position;
+        self.set_forward(pose.forward);
+        self.fov = pose.fov;
+        self.focus_distance = pose.focus;
+    }
+}
+
+/// A named view, stored in the scene file
+#[derive(Clone, Debug)]
+pub struct Bookmark {
+    pub name: String,
+    pub pose: CameraPose,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Keyframe {
+    /// In seconds since the start of the path
+    pub time: f32,
+    pub pose: CameraPose,
+}
+
+/// Camera poses at points in time, interpolated with a Catmull-Rom spline
+#[derive(Clone, Debug, Default)]
+pub struct CameraPath {
+    pub name: String,
+    /// Sorted by time
+    keyframes: Vec<Keyframe>,
+}
+
+impl CameraPath {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            keyframes: Vec::new(),
+        }
+    }
+    pub fn keyframes(&self) -> &[Keyframe] {
+        &self.keyframes
+    }
+    /// Adds a keyframe, replacing the one at the same time
+    pub fn insert(&mut self, keyframe: Keyframe) {
+        match self
+            .keyframes
+            .binary_search_by(|k| k.time.total_cmp(&keyframe.time))
+        {
+            Ok(i) => self.keyframes[i] = keyframe,
+            Err(i) => self.keyframes.insert(i, keyframe),
+        }
+    }
+    pub fn remove(&mut self, index: usize) -> Keyframe {
+        self.keyframes.remove(index)
+    }
+    /// Moves the keyframe at `index` to another time
+    pub fn set_time(&mut self, index: usize, time: f32) {
+        let mut keyframe = self.keyframes.remove(index);
+        keyframe.time = time;
+        self.insert(keyframe);
+    }
+    /// Time of the last keyframe
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0., |k| k.time)
+    }
+    /// Interpolated pose at `time`. Clamped to the first and last keyframe
+    pub fn sample(&self, time: f32) -> Option<CameraPose> {
+        let keys = &self.keyframes;
+        let last = keys.len().checked_sub(1)?;
+        // index of the keyframe which starts the segment containing `time`
+        let i = keys.partition_point(|k| k.time <= time).saturating_sub(1);
+        if i == last || time <= keys[0].time {
+            return Some(keys[i].pose);
+        }
+        let (k0, k1, k2, k3) = (
+            &keys[i.saturating_sub(1)],
+            &keys[i],
+            &keys[i + 1],
+            &keys[(i + 2).min(last)],
+        );
+        let u = (time - k1.time) / (k2.time - k1.time);
+        let spline = |f: fn(&CameraPose) -> Vec3| {
+            catmull_rom(f(&k0.pose), f(&k1.pose), f(&k2.pose), f(&k3.pose), u)
+        };
+        Some(CameraPose {
+            position: spline(|p| p.position),
+            // keys looking in opposite directions can cancel out, then the first one is kept
+            forward: spline(|p| p.forward)
+                .try_normalize()
+                .unwrap_or(k1.pose.forward),
+            fov: catmull_rom(k0.pose.fov, k1.pose.fov, k2.pose.fov, k3.pose.fov, u),
+            // the spline may overshoot, but the focus has to stay in front of the camera
+            focus: catmull_rom(
+                k0.pose.focus,
+                k1.pose.focus,
+                k2.pose.focus,
+                k3.pose.focus,
+                u,
+            )
+            .max(k1.pose.focus.min(k2.pose.focus)),
+        })
+    }
+}
+
+/// Uniform Catmull-Rom spline between `p1` and `p2`, `u` in [0, 1]
+fn catmull_rom<T>(p0: T, p1: T, p2: T, p3: T, u: f32) -> T
+where
+    T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f32, Output = T>,
+{
+    let u2 = u * u;
+    let u3 = u2 * u;
+    (p1 * 2.
+        + (p2 - p0) * u
+        + (p0 * 2. - p1 * 5. + p2 * 4. - p3) * u2
+        + (p1 * 3. - p0 - p2 * 3. + p3) * u3)
+        * 0.5
+}
+
+impl Scene {
+    /// Removes a camera path. The timeline loses it if it followed the path,
+    /// and is updated to the new index if it followed a later one
+    pub fn remove_camera_path(&mut self, index: usize) -> CameraPath {
+        self.timeline.camera_path = removed_path_index(self.timeline.camera_path, index);
+        self.camera_paths.remove(index)
+    }
+}
+
+/// Index of a camera path after the one at `removed` was removed
+fn removed_path_index(path: Option<usize>, removed: usize) -> Option<usize> {
+    match path? {
+        i if i == removed => None,
+        i if i > removed => Some(i - 1),
+        i => Some(i),
+    }
+}
+
+/// Plays a camera path of the scene in the editor
+#[derive(Debug, Default)]
+pub struct PathPlayer {
+    /// Index into `Scene::camera_paths`
+    pub path: Option<usize>,
+    pub time: f32,
+    pub playing: bool,
+    /// Start again at the beginning after the last keyframe
+    pub looping: bool,
+    /// The time was changed from outside, so the camera has to follow even if paused
+    pub seeked: bool,
+}
+
+impl PathPlayer {
+    pub fn play(&mut self, path: usize) {
+        self.path = Some(path);
+        self.time = 0.;
+        self.playing = true;
+    }
+    /// Follows the removal of a camera path, stopping if it was the played one
+    pub fn remove_path(&mut self, index: usize) {
+        self.path = removed_path_index(self.path, index);
+        self.playing &= self.path.is_some();
+    }
+    /// Advances the playback and moves the camera along the path.
+    /// Returns true if the camera moved
+    pub fn update(&mut self, scene: &mut Scene, dt: f32) -> bool {
+        let Some(path) = self.path.and_then(|i| scene.camera_paths.get(i)) else {
+            self.playing = false;
+            return false;
+        };
+        if !self.playing && !std::mem::take(&mut self.seeked) {
+            return false;
+        }
+        if self.playing {
+            self.time += dt;
+            let duration = path.duration();
+            if self.time > duration {
+                if self.looping && duration > 0. {
+                    self.time %= duration;
+                } else {
+                    self.time = duration;
+                    self.playing = false;
+                }
+            }
+        }
+        match path.sample(self.time) {
+            Some(pose) => {
+                scene.camera.set_pose(pose);
+                true
+            }
+            None => false,
+        }
+    }
+}
@@ -0,0 +1,251 @@
+//! Camera controllers, which turn mouse and keyboard input into camera movement.
+//!
+//! Held keys are tracked as state and applied once per frame with the frame time,
+//! so the movement does not depend on the key repeat rate of the os.
+use std::collections::HashSet;
+
+use glam::{Quat, Vec2, Vec3};
+use winit::{
+    dpi::PhysicalPosition,
+    event::{ElementState, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent},
+};
+
+use crate::{camera::Camera, math, scene::Scene};
+
+/// Steepest angle the orbiting camera can look at the target, to not flip over the poles
+const MAX_PITCH: f32 = 89f32 * std::f32::consts::PI / 180.;
+/// Radians per pixel of mouse movement
+const ROTATE_SPEED: f32 = 0.006;
+/// Distance to the orbit target, if the controller is switched to orbiting without a target
+const DEFAULT_ORBIT_DISTANCE: f32 = 10.;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ControllerMode {
+    /// Free flight with WASD, Q and E. Shift moves faster
+    #[default]
+    Fly,
+    /// Rotates around a target by dragging, scrolling zooms
+    Orbit,
+    /// Like orbit, but also spins around the target on its own
+    Turntable,
+}
+
+impl ControllerMode {
+    pub const ALL: [ControllerMode; 3] = [
+        ControllerMode::Fly,
+        ControllerMode::Orbit,
+        ControllerMode::Turntable,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ControllerMode::Fly => "fly",
+            ControllerMode::Orbit => "orbit",
+            ControllerMode::Turntable => "turntable",
+        }
+    }
+}
+
+pub struct CameraController {
+    mode: ControllerMode,
+    /// Point the orbit and turntable modes rotate around.
+    /// Follows the center of `target_sphere` if it is set
+    pub target: Vec3,
+    pub target_sphere: Option<usize>,
+    /// Fly speed in units per second
+    pub speed: f32,
+    /// Fly acceleration in units per second squared
+    pub acceleration: f32,
+    /// Factor applied to the speed while shift is held
+    pub fast_multiplier: f32,
+    /// Turntable rotation in degrees per second
+    pub turntable_speed: f32,
+    velocity: Vec3,
+    /// Target the camera was last turned towards, None if it has to be turned again
+    aligned_target: Option<Vec3>,
+    pressed_keys: HashSet<VirtualKeyCode>,
+    rotating: bool,
+    last_mouse_position: Option<PhysicalPosition<f64>>,
+    /// Mouse movement since the last update
+    mouse_delta: Vec2,
+    /// Scrolled lines since the last update
+    scroll: f32,
+}
+
+impl Default for CameraController {
+    fn default() -> Self {
+        Self {
+            mode: ControllerMode::default(),
+            target: Vec3::ZERO,
+            target_sphere: None,
+            speed: 30.,
+            acceleration: 120.,
+            fast_multiplier: 4.,
+            turntable_speed: 20.,
+            velocity: Vec3::ZERO,
+            aligned_target: None,
+            pressed_keys: HashSet::new(),
+            rotating: false,
+            last_mouse_position: None,
+            mouse_delta: Vec2::ZERO,
+            scroll: 0.,
+        }
+    }
+}
+
+impl CameraController {
+    pub fn mode(&self) -> ControllerMode {
+        self.mode
+    }
+    /// Switches the mode. Orbiting without a target sphere starts around the point in front of the camera
+    pub fn set_mode(&mut self, mode: ControllerMode, camera: &Camera) {
+        if self.mode == ControllerMode::Fly && mode != ControllerMode::Fly {
+            self.target = camera.position + camera.forward() * DEFAULT_ORBIT_DISTANCE;
+        }
+        self.mode = mode;
+        self.velocity = Vec3::ZERO;
+        self.aligned_target = None;
+    }
+    /// Records the input of a window event.
+    /// Presses which were `consumed` by the ui are ignored, releases never are
+    pub fn handle_window_event(&mut self, event: &WindowEvent, consumed: bool) {
+        match event {
+            WindowEvent::KeyboardInput { input, .. } => {
+                let Some(key) = input.virtual_keycode else {
+                    return;
+                };
+                match input.state {
+                    ElementState::Pressed if !consumed => self.set_key(key, true),
+                    ElementState::Pressed => {}
+                    ElementState::Released => self.set_key(key, false),
+                }
+            }
+            WindowEvent::MouseInput {
+                button: MouseButton::Right,
+                state,
+                ..
+            } => {
+                self.rotating = *state == ElementState::Pressed && !consumed;
+                self.last_mouse_position = None;
+            }
+            WindowEvent::CursorMoved { position, .. } if self.rotating => {
+                if let Some(last) = self.last_mouse_position {
+                    self.mouse_delta +=
+                        Vec2::new((position.x - last.x) as f32, (position.y - last.y) as f32);
+                }
+                self.last_mouse_position = Some(*position);
+            }
+            WindowEvent::MouseWheel { delta, .. } if !consumed => {
+                self.add_scroll(match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 50.,
+                });
+            }
+            WindowEvent::Focused(false) => {
+                self.pressed_keys.clear();
+                self.rotating = false;
+            }
+            _ => {}
+        }
+    }
+    /// Records a held or released key, applied by the next updates
+    pub fn set_key(&mut self, key: VirtualKeyCode, pressed: bool) {
+        if pressed {
+            self.pressed_keys.insert(key);
+        } else {
+            self.pressed_keys.remove(&key);
+        }
+    }
+    /// Records scrolled lines, applied by the next update
+    pub fn add_scroll(&mut self, lines: f32) {
+        self.scroll += lines;
+    }
+    /// Applies the input since the last call to the camera.
+    /// Returns true if the camera moved
+    pub fn update(&mut self, scene: &mut Scene, dt: f32) -> bool {
+        let mouse_delta = std::mem::take(&mut self.mouse_delta) * ROTATE_SPEED;
+        let scroll = std::mem::take(&mut self.scroll);
+        match self.mode {
+            ControllerMode::Fly => self.fly(&mut scene.camera, mouse_delta, scroll, dt),
+            ControllerMode::Orbit | ControllerMode::Turntable => {
+                if let Some(center) = self
+                    .target_sphere
+                    .and_then(|i| scene.sphere_world_center(i))
+                {
+                    self.target = center;
+                }
+                let mut yaw = -mouse_delta.x;
+                if self.mode == ControllerMode::Turntable {
+                    yaw += self.turntable_speed.to_radians() * dt;
+                }
+                self.orbit(&mut scene.camera, yaw, -mouse_delta.y, scroll)
+            }
+        }
+    }
+    fn fly(&mut self, camera: &mut Camera, mouse_delta: Vec2, scroll: f32, dt: f32) -> bool {
+        let mut moved = false;
+        if mouse_delta != Vec2::ZERO {
+            let right = camera.forward().cross(Vec3::Y).normalize();
+            let q1 = Quat::from_axis_angle(right, -mouse_delta.y);
+            let q2 = Quat::from_axis_angle(Vec3::Y, -mouse_delta.x);
+            let q = math::cross(q1, q2).normalize();
+            camera.set_forward(q * camera.forward());
+            moved = true;
+        }
+        // scrolling while flying changes the speed
+        self.speed = (self.speed * 1.1f32.powf(scroll)).max(0.01);
+
+        let forward = camera.forward();
+        let right = forward.cross(Vec3::Y).normalize_or_zero();
+        let key_axis = |positive, negative| {
+            self.pressed_keys.contains(&positive) as i32 as f32
+                - self.pressed_keys.contains(&negative) as i32 as f32
+        };
+        let direction = forward * key_axis(VirtualKeyCode::W, VirtualKeyCode::S)
+            + right * key_axis(VirtualKeyCode::D, VirtualKeyCode::A)
+            + Vec3::Y * key_axis(VirtualKeyCode::E, VirtualKeyCode::Q);
+        let fast = self.pressed_keys.contains(&VirtualKeyCode::LShift)
+            || self.pressed_keys.contains(&VirtualKeyCode::RShift);
+        let multiplier = if fast { self.fast_multiplier } else { 1. };
+        let target_velocity = direction.normalize_or_zero() * self.speed * multiplier;
+
+        // accelerate towards the target velocity, which also brakes once the keys are released
+        let change = target_velocity - self.velocity;
+        let max_change = self.acceleration * multiplier * dt;
+        self.velocity += if change.length() > max_change {
+            change.normalize() * max_change
+        } else {
+            change
+        };
+        if self.velocity != Vec3::ZERO {
+            camera.position += self.velocity * dt;
+            moved = true;
+        }
+        moved
+    }
+    fn orbit(&mut self, camera: &mut Camera, yaw: f32, pitch: f32, scroll: f32) -> bool {
+        let idle = yaw == 0. && pitch == 0. && scroll == 0.;
+        if idle && self.aligned_target == Some(self.target) {
+            return false;
+        }
+        let offset = camera.position - self.target;
+        let distance = offset.length().max(0.01);
+        let current_pitch = (offset.y / distance).clamp(-1., 1.).asin();
+        let current_yaw = offset.x.atan2(offset.z);
+
+        let new_pitch = (current_pitch + pitch).clamp(-MAX_PITCH, MAX_PITCH);
+        let new_yaw = current_yaw + yaw;
+        let new_distance = (distance * 0.9f32.powf(scroll)).max(0.01);
+        let position = self.target
+            + new_distance
+                * Vec3::new(
+                    new_pitch.cos() * new_yaw.sin(),
+                    new_pitch.sin(),
+                    new_pitch.cos() * new_yaw.cos(),
+                );
+        camera.position = position;
+        camera.look_at(self.target);
+        self.aligned_target = Some(self.target);
+        true
+    }
+}
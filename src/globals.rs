@@ -1,27 +1,79 @@
-use crate::math;
+use crate::{layout::shader_layout, math, sampler::SamplerKind};
 
 /// Global parameter that can be configured by the user
 #[repr(C)]
 #[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Globals {
     /// Seed for the random generation.
-    /// random numbers are e.g. used for sampling on the accumodation buffer
+    /// Combined with the pixel and the sample index, so a fixed seed renders the same image every time
     pub seed: u32,
     /// Amount of bounces a ray is able to do.
     /// Can drasticely increase the amount  of work the tracer has to do.
     pub bounces: u32,
-    /// padding offset
-    pub _offset: [f32; 2],
+    /// If not 0, pixels whose estimated noise is below `convergence_threshold` stop being traced
+    pub adaptive_sampling: u32,
+    /// Relative standard error of a pixels luminance at which the pixel counts as converged
+    pub convergence_threshold: f32,
     /// Color of the sky. Since the sky gives ambient light to the objects, it also influences the scene feel overall
     pub sky_color: [f32; 4],
+    /// Amount of samples a pixel needs before it is checked for convergence
+    pub min_samples: u32,
+    /// Every `recheck_interval` frames converged pixels are traced again,
+    /// in case the variance estimation was too optimistic
+    pub recheck_interval: u32,
+    /// Amount of frames accumulated so far. Updated by the app every frame
+    pub acc_frame: u32,
+    /// Amount of paths traced per pixel in a single dispatch
+    pub samples_per_frame: u32,
+    /// If not 0, camera movements reproject the accumulated image instead of discarding it
+    pub temporal: u32,
+    /// If not 0, this frame reprojects the history. Set by the app after the camera moved
+    pub reproject: u32,
+    /// Maximum amount of samples kept from the history when reprojecting.
+    /// Lower values adapt faster, higher values keep less noise
+    pub history_limit: f32,
+    /// Relative difference in first hit distance at which reprojected history is rejected
+    pub depth_tolerance: f32,
+    /// Which [`SamplerKind`] generates the random numbers of the paths
+    pub sampler_kind: u32,
+    /// padding offset
+    pub _offset: [u32; 3],
 }
 impl Default for Globals {
     fn default() -> Self {
         Self {
             seed: 0x5748,
             bounces: 8,
-            _offset: [0.; 2],
+            adaptive_sampling: 0,
+            convergence_threshold: 0.01,
             sky_color: math::as_rgbaf32(crate::COLORS[0]),
+            min_samples: 32,
+            recheck_interval: 64,
+            acc_frame: 0,
+            samples_per_frame: 1,
+            temporal: 0,
+            reproject: 0,
+            history_limit: 64.,
+            depth_tolerance: 0.05,
+            sampler_kind: SamplerKind::default() as u32,
+            _offset: [0; 3],
         }
     }
 }
+
+shader_layout!(Globals => "Globals" {
+    seed,
+    bounces,
+    adaptive_sampling,
+    convergence_threshold,
+    sky_color,
+    min_samples,
+    recheck_interval,
+    acc_frame,
+    samples_per_frame,
+    temporal,
+    reproject,
+    history_limit,
+    depth_tolerance,
+    sampler_kind,
+});
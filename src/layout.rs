@@ -0,0 +1,43 @@
+//! Layout description of the types shared with the shaders.
+//!
+//! The rust structs are padded by hand to match their wgsl counterparts.
+//! [`ShaderLayout`] records where every wgsl member lives in the rust struct,
+//! so `tests/layout.rs` can compare them against the parsed shaders.
+
+/// A `Pod` type that is uploaded to a struct of the same layout in a shader
+pub trait ShaderLayout: bytemuck::Pod {
+    /// Name of the wgsl struct
+    const WGSL_NAME: &'static str;
+    /// Name of every wgsl member in declaration order,
+    /// together with the offset of the rust field it is stored in
+    fn members() -> Vec<(&'static str, usize)>;
+}
+
+/// Implements [`ShaderLayout`] by listing the wgsl members.
+/// Members whose rust field is named differently are written as `member = field`,
+/// members stored inside of a rust field as `member = field + offset`
+macro_rules! shader_layout {
+    ($ty:ty => $wgsl:literal {
+        $($member:ident $(= $field:ident $(+ $extra:literal)?)?),* $(,)?
+    }) => {
+        impl $crate::layout::ShaderLayout for $ty {
+            const WGSL_NAME: &'static str = $wgsl;
+            fn members() -> Vec<(&'static str, usize)> {
+                vec![$((
+                    stringify!($member),
+                    $crate::layout::shader_layout!(@offset $ty, $member $(, $field $(, $extra)?)?),
+                )),*]
+            }
+        }
+    };
+    (@offset $ty:ty, $member:ident) => {
+        std::mem::offset_of!($ty, $member)
+    };
+    (@offset $ty:ty, $member:ident, $field:ident) => {
+        std::mem::offset_of!($ty, $field)
+    };
+    (@offset $ty:ty, $member:ident, $field:ident, $extra:literal) => {
+        std::mem::offset_of!($ty, $field) + $extra
+    };
+}
+pub(crate) use shader_layout;
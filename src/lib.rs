@@ -0,0 +1,36 @@
+pub mod animation;
+pub mod app;
+pub mod bench;
+pub mod budget;
+pub mod camera;
+pub mod camera_path;
+pub mod controller;
+pub mod globals;
+pub mod layout;
+pub mod material;
+pub mod material_library;
+pub mod math;
+pub mod physics;
+pub mod procedural;
+pub mod renderer;
+pub mod sampler;
+pub mod scene;
+pub mod scene_file;
+pub mod scene_graph;
+pub mod scenes;
+pub mod sequence;
+pub mod sphere;
+pub mod stats;
+pub mod texture;
+pub mod timer;
+pub mod ui;
+
+pub const COLORS: [[u8; 3]; 7] = [
+    [244, 244, 244], // sky
+    [121, 135, 119], // ground
+    [255, 217, 102], // light
+    [189, 210, 182],
+    [162, 178, 159],
+    [244, 177, 131],
+    [223, 166, 123],
+];
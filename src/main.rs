@@ -1,45 +1,56 @@
-mod app;
-mod camera;
-mod globals;
-pub mod material;
-mod math;
-mod renderer;
-mod scene;
-pub mod sphere;
-mod timer;
-mod ui;
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
 
-use app::App;
+use log::{info, warn};
+use rays::{app::App, bench, globals::Globals, renderer::Renderer, scene::Scene, sequence};
 use simple_logger::SimpleLogger;
+use winit::dpi::PhysicalSize;
 use winit::{
     event::*,
     event_loop::{ControlFlow, EventLoop},
     window::WindowBuilder,
 };
-const COLORS: [[u8; 3]; 7] = [
-    [244, 244, 244], // sky
-    [121, 135, 119], // ground
-    [255, 217, 102], // light
-    [189, 210, 182],
-    [162, 178, 159],
-    [244, 177, 131],
-    [223, 166, 123],
-];
 fn main() {
-    pollster::block_on(run());
-}
-pub async fn run() {
     SimpleLogger::default()
         .with_level(log::LevelFilter::Info)
         .with_module_level("wgpu_core", log::LevelFilter::Warn)
         .with_module_level("wgpu_hal", log::LevelFilter::Warn)
         .init()
         .unwrap();
-
+    let mut args = std::env::args().skip(1);
+    let scene_path = match args.next() {
+        Some(command) if command == "bench" || command == "render" => {
+            let result = match command.as_str() {
+                "bench" => bench::main(args),
+                _ => sequence::main(args),
+            };
+            if let Err(e) = result {
+                eprintln!("{e}");
+                std::process::exit(1);
+            }
+            return;
+        }
+        scene_path => scene_path.map(PathBuf::from),
+    };
+    pollster::block_on(run(scene_path));
+}
+/// Opens the editor, with the scene file at `scene_path` if given
+pub async fn run(scene_path: Option<PathBuf>) {
     let event_loop = EventLoop::new();
     let window = WindowBuilder::new().build(&event_loop).unwrap();
-    let mut app = App::new(window, &event_loop).await;
-    let mut mouse_pressed = false;
+    let Some(mut app) = App::new(window, &event_loop).await else {
+        warn!("No suitable gpu adapter found, rendering on the cpu instead");
+        render_cpu_fallback();
+        return;
+    };
+    if let Some(path) = scene_path {
+        if let Err(e) = app.open_scene(path) {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    }
     event_loop.run(move |event, _, control_flow| match event {
         Event::RedrawRequested(window_id) if window_id == app.window().id() => {
             match app.prepare() {
@@ -55,6 +66,13 @@ pub async fn run() {
         }
         Event::MainEventsCleared => {
             app.update();
+            // once the sample budget is reached there is no need to spin the gpu,
+            // only the ui has to stay responsive
+            *control_flow = if app.is_paused() {
+                ControlFlow::WaitUntil(Instant::now() + Duration::from_millis(33))
+            } else {
+                ControlFlow::Poll
+            };
             // RedrawRequested will only trigger once, unless we manually
             // request it.
             app.window().request_redraw();
@@ -65,22 +83,6 @@ pub async fn run() {
         } if window_id == app.window().id() => {
             app.handle_window_event(event);
             match event {
-                WindowEvent::MouseInput { button, state, .. } => {
-                    if &MouseButton::Right == button {
-                        match state {
-                            ElementState::Pressed => mouse_pressed = true,
-                            ElementState::Released => mouse_pressed = false,
-                        }
-                    }
-                }
-                WindowEvent::CursorMoved { position, .. } => {
-                    if mouse_pressed {
-                        app.scene.camera.on_rotate(position);
-                        app.clear_renderer();
-                    } else {
-                        app.scene.camera.last_mouse_position = None;
-                    }
-                }
                 WindowEvent::Resized(physical_size) => {
                     app.resize(*physical_size, None);
                 }
@@ -100,12 +102,29 @@ pub async fn run() {
                         },
                     ..
                 } => *control_flow = ControlFlow::Exit,
-                WindowEvent::KeyboardInput { input, .. } => {
-                    app.handle_keyboard_input(input);
-                }
                 _ => {}
             }
         }
         _ => {}
     });
 }
+
+/// Renders the example scene with the cpu path tracer and writes it to `render.png`
+fn render_cpu_fallback() {
+    const SAMPLES: u32 = 32;
+    let size = PhysicalSize::new(640, 360);
+    let mut scene = Scene::example_scene();
+    scene.camera.resize(size);
+    let globals = Globals::default();
+    let mut renderer = Renderer::new(size);
+    let start = Instant::now();
+    renderer.render_cpu(&scene, &globals, SAMPLES);
+    info!(
+        "Rendered {SAMPLES} samples per pixel in {:.1}s",
+        start.elapsed().as_secs_f32()
+    );
+    match renderer.resolved_image().save("render.png") {
+        Ok(()) => info!("Saved render.png"),
+        Err(e) => warn!("Could not save render.png: {e}"),
+    }
+}
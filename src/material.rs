@@ -1,4 +1,10 @@
 use image::Rgba;
+
+use crate::{
+    layout::shader_layout,
+    texture::{TextureChannel, TextureRef, NO_TEXTURE},
+};
+
 /// Represents the material of a rendered sphere.
 /// Multiple Spheres can use the same material without a problem.
 #[repr(C)]
@@ -15,6 +21,14 @@ pub struct Material {
     /// Strength of the emmision is encoded in alpha.
     /// Therefore if alpha is set to 0, no emission is added to the material
     pub emission: [f32; 4],
+    /// Texture slot of every [`TextureChannel`], see [`TextureRef`]
+    pub textures: [u32; 4],
+    /// Fraction of the light passing into the surface instead of reflecting off it, like glass
+    pub transmission: f32,
+    /// Index of refraction of transmissive materials
+    pub ior: f32,
+    /// padding offset
+    pub _offset: [f32; 2],
 }
 
 /// We need to make this claim to be able to send the material to the shader.
@@ -22,6 +36,28 @@ pub struct Material {
 unsafe impl bytemuck::Pod for Material {}
 unsafe impl bytemuck::Zeroable for Material {}
 
+shader_layout!(Material => "Material" {
+    metallic,
+    specular_intensity,
+    roughness,
+    fog,
+    albedo,
+    emission_color = emission,
+    emission_strength = emission + 12,
+    albedo_texture = textures,
+    roughness_texture = textures + 4,
+    metallic_texture = textures + 8,
+    emission_texture = textures + 12,
+    transmission,
+    ior,
+});
+
+impl Default for Material {
+    fn default() -> Self {
+        Material::new()
+    }
+}
+
 impl Material {
     /// Creates a default material
     pub fn new() -> Material {
@@ -32,6 +68,10 @@ impl Material {
             specular_intensity: 1.,
             roughness: 0.1,
             fog: 0.,
+            textures: [NO_TEXTURE; 4],
+            transmission: 0.,
+            ior: 1.5,
+            _offset: [0.; 2],
         }
     }
     /// Defaults to white
@@ -54,9 +94,34 @@ impl Material {
         self.specular_intensity = specular_intensity;
         self
     }
+    /// Defaults to 0.
+    pub fn with_metallic(mut self, metallic: f32) -> Self {
+        self.metallic = metallic;
+        self
+    }
     /// Defaults to 0.1
     pub fn with_roughness(mut self, roughness: f32) -> Self {
         self.roughness = roughness;
         self
     }
+    /// Defaults to 0. and an index of refraction of 1.5
+    pub fn with_transmission(mut self, transmission: f32, ior: f32) -> Self {
+        self.transmission = transmission;
+        self.ior = ior;
+        self
+    }
+    /// Defaults to no texture for every channel
+    pub fn with_texture(mut self, channel: TextureChannel, texture: TextureRef) -> Self {
+        self.textures[channel as usize] = texture.to_slot();
+        self
+    }
+    /// The texture of the channel, if it has one
+    pub fn texture(&self, channel: TextureChannel) -> Option<TextureRef> {
+        TextureRef::from_slot(self.textures[channel as usize])
+    }
+    /// The same material without any texture
+    pub fn without_textures(mut self) -> Self {
+        self.textures = [NO_TEXTURE; 4];
+        self
+    }
 }
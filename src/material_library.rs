@@ -0,0 +1,165 @@
+//! Named material presets which can be imported into any scene.
+//!
+//! Library files hold one `material` line per preset, in the syntax of scene files,
+//! so materials can be copied between both. Like in scene files, a `transmission` line
+//! belongs to the material above it.
+use std::path::Path;
+
+use crate::{material::Material, scene::Scene, scene_file};
+
+/// File the library is saved to, if no other path is given
+pub const DEFAULT_PATH: &str = "materials.rays";
+
+/// A material with the name shown in the ui, in libraries as well as in scenes
+#[derive(Clone, Debug)]
+pub struct NamedMaterial {
+    pub name: String,
+    pub material: Material,
+}
+
+impl NamedMaterial {
+    pub fn new(name: impl Into<String>, material: Material) -> Self {
+        Self {
+            name: name.into(),
+            material,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct MaterialLibrary {
+    pub materials: Vec<NamedMaterial>,
+}
+
+impl MaterialLibrary {
+    /// The built-in presets
+    pub fn presets() -> Self {
+        let materials = vec![
+            NamedMaterial::new(
+                "Gold",
+                Material::new()
+                    .with_color([1., 0.766, 0.336])
+                    .with_metallic(1.)
+                    .with_roughness(0.2),
+            ),
+            NamedMaterial::new(
+                "Copper",
+                Material::new()
+                    .with_color([0.955, 0.638, 0.538])
+                    .with_metallic(1.)
+                    .with_roughness(0.3),
+            ),
+            NamedMaterial::new(
+                "Plastic",
+                Material::new()
+                    .with_color([0.8, 0.1, 0.1])
+                    .with_specular_intensity(0.6)
+                    .with_roughness(0.4),
+            ),
+            NamedMaterial::new(
+                "Rubber",
+                Material::new()
+                    .with_color([0.08, 0.08, 0.08])
+                    .with_specular_intensity(0.3)
+                    .with_roughness(0.9),
+            ),
+            NamedMaterial::new(
+                "Glass",
+                Material::new()
+                    .with_color([0.95, 0.97, 1.])
+                    .with_roughness(0.)
+                    .with_transmission(1., 1.5),
+            ),
+            NamedMaterial::new(
+                "Emissive panel",
+                Material::new()
+                    .with_emission([1., 0.95, 0.9], 10.)
+                    .with_roughness(0.5),
+            ),
+        ];
+        Self { materials }
+    }
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("could not read {}: {e}", path.display()))?;
+        Self::parse(&text).map_err(|e| format!("{}: {e}", path.display()))
+    }
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let path = path.as_ref();
+        std::fs::write(path, self.write())
+            .map_err(|e| format!("could not write {}: {e}", path.display()))
+    }
+    /// Parses `material` and `transmission` lines, ignoring empty lines and comments
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut materials: Vec<NamedMaterial> = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: &str| format!("line {}: {message}", number + 1);
+            let (kind, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            match kind {
+                "material" => {
+                    let (material, name) =
+                        scene_file::parse_material(rest).map_err(|e| error(&e))?;
+                    materials.push(NamedMaterial::new(name.trim(), material));
+                }
+                "transmission" => {
+                    let named = materials
+                        .last_mut()
+                        .ok_or_else(|| error("transmission without a material"))?;
+                    scene_file::parse_transmission(rest, &mut named.material)
+                        .map_err(|e| error(&e))?;
+                }
+                _ => return Err(error(&format!("expected a material, found {kind:?}"))),
+            }
+        }
+        Ok(Self { materials })
+    }
+    pub fn write(&self) -> String {
+        let mut out = String::from("# rays material library\n");
+        for named in &self.materials {
+            scene_file::write_material(&mut out, &named.material, &named.name);
+        }
+        out
+    }
+    /// Adds the material, replacing the one with the same name
+    pub fn insert(&mut self, named: NamedMaterial) {
+        match self.materials.iter_mut().find(|m| m.name == named.name) {
+            Some(existing) => *existing = named,
+            None => self.materials.push(named),
+        }
+    }
+}
+
+impl Scene {
+    /// Adds a material and returns its index
+    pub fn add_material(&mut self, name: impl Into<String>, material: Material) -> usize {
+        self.materials.push(NamedMaterial::new(name, material));
+        self.dirty_materials.mark(self.materials.len() - 1);
+        self.materials.len() - 1
+    }
+    /// Copies a library material into the scene. A material with the same name is overwritten,
+    /// so importing again updates it. Returns the index of the material
+    pub fn import_material(&mut self, named: &NamedMaterial) -> usize {
+        match self.materials.iter().position(|m| m.name == named.name) {
+            Some(i) => {
+                self.materials[i].material = named.material;
+                self.dirty_materials.mark(i);
+                i
+            }
+            None => self.add_material(named.name.clone(), named.material),
+        }
+    }
+    /// The material at `index` with its name, e.g. to add it to a library.
+    /// Texture indices only make sense within this scene, so the textures are left out
+    pub fn named_material(&self, index: usize) -> Option<NamedMaterial> {
+        let named = self.materials.get(index)?;
+        Some(NamedMaterial::new(
+            named.name.clone(),
+            named.material.without_textures(),
+        ))
+    }
+}
@@ -11,8 +11,9 @@ pub(crate) fn rand_vec(mut seed: u32) -> Vec3 {
     Vec3::new(v1, v2, v3)
 }
 
+/// Same hash as `pcg_hash` in the compute shader
 pub(crate) fn pcg_hash(seed: u32) -> u32 {
-    let state = seed.wrapping_mul(747779605).wrapping_add(2891336453);
+    let state = seed.wrapping_mul(747796405).wrapping_add(2891336453);
     let word = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277803737);
 
     (word >> 22) ^ word
@@ -35,6 +36,23 @@ fn linear_f32_from_gamma_u8(s: u8) -> f32 {
         ((s as f32 + 14.025) / 269.025).powf(2.4)
     }
 }
+fn gamma_u8_from_linear_f32(l: f32) -> u8 {
+    let l = l.clamp(0., 1.);
+    let s = if l <= 0.0031308 {
+        l * 12.92
+    } else {
+        1.055 * l.powf(1. / 2.4) - 0.055
+    };
+    (s * 255. + 0.5) as u8
+}
+/// Inverse of [`as_rgbf32`]
+pub fn as_srgb_u8(rgb: [f32; 3]) -> [u8; 3] {
+    [
+        gamma_u8_from_linear_f32(rgb[0]),
+        gamma_u8_from_linear_f32(rgb[1]),
+        gamma_u8_from_linear_f32(rgb[2]),
+    ]
+}
 pub fn as_rgbf32(rgb8: [u8; 3]) -> [f32; 3] {
     let r = linear_f32_from_gamma_u8(rgb8[0]);
     let g = linear_f32_from_gamma_u8(rgb8[1]);
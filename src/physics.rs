@@ -0,0 +1,248 @@
+//! Rigid body simulation of the spheres, for sphere packing and particle experiments.
+//!
+//! Spheres fall with gravity and collide with each other and with an optional ground plane.
+//! The velocity of a sphere is stored in [`Sphere::velocity`](crate::sphere::Sphere::velocity),
+//! so simulated spheres are also motion blurred.
+//! The spheres are simulated in the local space of their nodes, which is the world
+//! for spheres placed by untransformed root nodes.
+use std::collections::BTreeSet;
+
+use glam::Vec3;
+
+use crate::{animation::Property, scene::Scene, sphere::Sphere};
+
+/// Length of a simulation step in seconds. Frames are split into steps of this length
+const STEP: f32 = 1. / 240.;
+/// Upper bound of steps per frame, so a slow frame does not stall the app
+const MAX_STEPS_PER_FRAME: u32 = 16;
+
+pub struct Simulation {
+    pub running: bool,
+    /// The ground lies in +y, so gravity points along +y
+    pub gravity: Vec3,
+    /// Share of the velocity kept when bouncing off, between 0 and 1
+    pub restitution: f32,
+    /// Coulomb friction coefficient of the contacts
+    pub friction: f32,
+    /// Height of an infinite ground plane. Spheres can not move below it
+    pub ground: Option<f32>,
+    /// Spheres which do not move, e.g. a large sphere used as ground.
+    /// Spheres without a positive radius have no mass and stay in place as well
+    pub pinned: BTreeSet<usize>,
+    /// Centers and velocities before the simulation started, to reset it
+    initial: Option<Vec<(Vec3, Vec3)>>,
+    /// Simulation time not yet stepped
+    remainder: f32,
+}
+
+impl Default for Simulation {
+    fn default() -> Self {
+        Self {
+            running: false,
+            gravity: Vec3::new(0., 9.81, 0.),
+            restitution: 0.5,
+            friction: 0.3,
+            ground: None,
+            pinned: BTreeSet::new(),
+            initial: None,
+            remainder: 0.,
+        }
+    }
+}
+
+impl Simulation {
+    /// Pins every sphere with a radius larger than `radius`
+    pub fn pin_larger_than(&mut self, scene: &Scene, radius: f32) {
+        self.pinned = scene
+            .spheres
+            .iter()
+            .enumerate()
+            .filter(|(_, sphere)| sphere.radius > radius)
+            .map(|(i, _)| i)
+            .collect();
+    }
+    /// True if the sphere does not move, see [`Simulation::pinned`]
+    fn is_pinned(&self, index: usize, sphere: &Sphere) -> bool {
+        self.pinned.contains(&index) || sphere.radius <= 0.
+    }
+    /// True if the simulation was started and can be reset
+    pub fn has_started(&self) -> bool {
+        self.initial.is_some()
+    }
+    /// Remembers the current state for [`Simulation::reset`], if not done yet
+    fn start(&mut self, scene: &Scene) {
+        if self.initial.is_none() {
+            self.initial = Some(
+                scene
+                    .spheres
+                    .iter()
+                    .map(|s| (s.center, s.velocity))
+                    .collect(),
+            );
+        }
+    }
+    /// Moves the spheres back to where they were when the simulation started
+    pub fn reset(&mut self, scene: &mut Scene) {
+        self.running = false;
+        self.remainder = 0.;
+        let Some(initial) = self.initial.take() else {
+            return;
+        };
+        for (sphere, (center, velocity)) in scene.spheres.iter_mut().zip(initial) {
+            sphere.center = center;
+            sphere.velocity = velocity;
+        }
+        scene.dirty_spheres.mark_all(scene.spheres.len());
+    }
+    /// Advances a running simulation by the frame time.
+    /// Returns true if the spheres moved
+    pub fn update(&mut self, scene: &mut Scene, dt: f32) -> bool {
+        if !self.running {
+            return false;
+        }
+        self.remainder = (self.remainder + dt).min(STEP * MAX_STEPS_PER_FRAME as f32);
+        let steps = (self.remainder / STEP) as u32;
+        self.remainder -= steps as f32 * STEP;
+        self.advance(scene, steps);
+        steps > 0
+    }
+    /// Advances the simulation by `duration` seconds, regardless of whether it is running
+    pub fn step(&mut self, scene: &mut Scene, duration: f32) {
+        self.advance(scene, (duration / STEP).round().max(1.) as u32);
+    }
+    fn advance(&mut self, scene: &mut Scene, steps: u32) {
+        if steps == 0 {
+            return;
+        }
+        self.start(scene);
+        for _ in 0..steps {
+            self.substep(scene);
+        }
+        scene.dirty_spheres.mark_all(scene.spheres.len());
+    }
+    /// Simulates the timeline range from the current state and stores the sphere centers
+    /// as keyframes of every frame. Afterwards the spheres are moved back
+    pub fn bake(&mut self, scene: &mut Scene) {
+        let was_running = std::mem::replace(&mut self.running, false);
+        let initial = self.initial.take();
+        self.start(scene);
+        let (first, last) = (scene.timeline.start_frame, scene.timeline.end_frame);
+        let frame_duration = 1. / scene.timeline.fps;
+        for frame in first..=last {
+            if frame > first {
+                self.step(scene, frame_duration);
+            }
+            let time = scene.timeline.frame_time(frame);
+            for (i, sphere) in scene.spheres.iter().enumerate() {
+                if !self.is_pinned(i, sphere) {
+                    let track = scene.timeline.track(Property::SphereCenter(i));
+                    track.insert(time, sphere.center.extend(0.));
+                }
+            }
+        }
+        self.reset(scene);
+        self.initial = initial;
+        self.running = was_running;
+    }
+    fn substep(&self, scene: &mut Scene) {
+        let spheres = &mut scene.spheres;
+        for (i, sphere) in spheres.iter_mut().enumerate() {
+            if self.is_pinned(i, sphere) {
+                sphere.velocity = Vec3::ZERO;
+                continue;
+            }
+            sphere.velocity += self.gravity * STEP;
+            sphere.center += sphere.velocity * STEP;
+        }
+
+        // sweep and prune along x, pinned spheres are tested against every other sphere
+        // since they are usually large
+        let (pinned, mut dynamic): (Vec<usize>, Vec<usize>) =
+            (0..spheres.len()).partition(|&i| self.is_pinned(i, &spheres[i]));
+        dynamic.sort_by(|&a, &b| {
+            let min_x = |i: usize| spheres[i].center.x - spheres[i].radius;
+            min_x(a).total_cmp(&min_x(b))
+        });
+        for (n, &a) in dynamic.iter().enumerate() {
+            let max_x = spheres[a].center.x + spheres[a].radius;
+            for &b in &dynamic[n + 1..] {
+                if spheres[b].center.x - spheres[b].radius > max_x {
+                    break;
+                }
+                self.collide(spheres, a, Some(b));
+            }
+            for &b in &pinned {
+                self.collide(spheres, a, Some(b));
+            }
+            if self.ground.is_some() {
+                self.collide(spheres, a, None);
+            }
+        }
+    }
+    /// Resolves the contact between the dynamic sphere `a` and the sphere `b`, or the ground if None.
+    /// The spheres are treated as solid balls of equal density, `a` has a positive radius
+    fn collide(&self, spheres: &mut [Sphere], a: usize, b: Option<usize>) {
+        let sphere_a = spheres[a];
+        let inverse_mass = |radius: f32| 1. / radius.powi(3);
+        let inverse_mass_a = inverse_mass(sphere_a.radius);
+        let (normal, penetration, velocity_b, inverse_mass_b) = match b {
+            Some(b) => {
+                let sphere_b = spheres[b];
+                let offset = sphere_b.center - sphere_a.center;
+                let distance = offset.length();
+                let penetration = sphere_a.radius + sphere_b.radius - distance;
+                if penetration <= 0. || distance == 0. {
+                    return;
+                }
+                let inverse_mass_b = if self.is_pinned(b, &sphere_b) {
+                    0.
+                } else {
+                    inverse_mass(sphere_b.radius)
+                };
+                (
+                    offset / distance,
+                    penetration,
+                    sphere_b.velocity,
+                    inverse_mass_b,
+                )
+            }
+            None => {
+                let ground = self.ground.unwrap_or(f32::MAX);
+                let penetration = sphere_a.center.y + sphere_a.radius - ground;
+                if penetration <= 0. {
+                    return;
+                }
+                (Vec3::Y, penetration, Vec3::ZERO, 0.)
+            }
+        };
+        let total_inverse_mass = inverse_mass_a + inverse_mass_b;
+
+        // push the spheres apart
+        let correction = normal * penetration / total_inverse_mass;
+        spheres[a].center -= correction * inverse_mass_a;
+        if let Some(b) = b {
+            spheres[b].center += correction * inverse_mass_b;
+        }
+
+        let relative_velocity = velocity_b - sphere_a.velocity;
+        let normal_velocity = relative_velocity.dot(normal);
+        if normal_velocity >= 0. {
+            // already separating
+            return;
+        }
+        let impulse = -(1. + self.restitution) * normal_velocity / total_inverse_mass;
+        let tangent_velocity = relative_velocity - normal_velocity * normal;
+        let tangent_speed = tangent_velocity.length();
+        let friction = if tangent_speed > 0. {
+            let stop = tangent_speed / total_inverse_mass;
+            -tangent_velocity / tangent_speed * stop.min(self.friction * impulse)
+        } else {
+            Vec3::ZERO
+        };
+        let total = normal * impulse + friction;
+        spheres[a].velocity -= total * inverse_mass_a;
+        if let Some(b) = b {
+            spheres[b].velocity += total * inverse_mass_b;
+        }
+    }
+}
@@ -0,0 +1,257 @@
+//! Procedural textures which vary the channels of a material without an image.
+//!
+//! Every texture computes a pattern between 0 and 1 at the hit position, in object or world
+//! space, and blends between two colors by it. The patterns are evaluated by
+//! `procedural_texture` in the compute shader, which this module mirrors for the cpu renderer.
+use std::f32::consts::PI;
+
+use glam::{IVec3, Vec3, Vec4};
+
+use crate::{layout::shader_layout, math::pcg_hash, sampler::u32_to_unit};
+
+/// Upper limit of `ProceduralTexture::octaves`, also enforced by the shader
+pub const MAX_OCTAVES: u32 = 8;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ProceduralKind {
+    /// Alternating cubes of edge length 1
+    #[default]
+    Checker = 0,
+    /// Fractal sum of perlin noise
+    Noise = 1,
+    /// Distance to the closest of randomly scattered points
+    Voronoi = 2,
+    /// Stripes along x, distorted by noise
+    Marble = 3,
+    /// Blends upwards from -1 to 1 along the up direction
+    Gradient = 4,
+}
+
+impl ProceduralKind {
+    pub const ALL: [ProceduralKind; 5] = [
+        ProceduralKind::Checker,
+        ProceduralKind::Noise,
+        ProceduralKind::Voronoi,
+        ProceduralKind::Marble,
+        ProceduralKind::Gradient,
+    ];
+
+    /// Shown in the ui and used in scene files
+    pub fn name(self) -> &'static str {
+        match self {
+            ProceduralKind::Checker => "checker",
+            ProceduralKind::Noise => "noise",
+            ProceduralKind::Voronoi => "voronoi",
+            ProceduralKind::Marble => "marble",
+            ProceduralKind::Gradient => "gradient",
+        }
+    }
+    pub fn parse(name: &str) -> Option<ProceduralKind> {
+        Self::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+    /// Converts the value stored in `ProceduralTexture::kind`.
+    /// Unknown values fall back to the checkerboard, like in the shader
+    pub fn from_u32(kind: u32) -> ProceduralKind {
+        match kind {
+            1 => ProceduralKind::Noise,
+            2 => ProceduralKind::Voronoi,
+            3 => ProceduralKind::Marble,
+            4 => ProceduralKind::Gradient,
+            _ => ProceduralKind::Checker,
+        }
+    }
+}
+
+/// Coordinates the pattern is evaluated in
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TextureSpace {
+    /// Relative to the center of the sphere, so the pattern follows its transform
+    #[default]
+    Object = 0,
+    World = 1,
+}
+
+impl TextureSpace {
+    pub const ALL: [TextureSpace; 2] = [TextureSpace::Object, TextureSpace::World];
+
+    /// Shown in the ui and used in scene files
+    pub fn name(self) -> &'static str {
+        match self {
+            TextureSpace::Object => "object",
+            TextureSpace::World => "world",
+        }
+    }
+    pub fn parse(name: &str) -> Option<TextureSpace> {
+        Self::ALL.into_iter().find(|space| space.name() == name)
+    }
+    /// Converts the value stored in `ProceduralTexture::space`
+    pub fn from_u32(space: u32) -> TextureSpace {
+        match space {
+            1 => TextureSpace::World,
+            _ => TextureSpace::Object,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ProceduralTexture {
+    /// Color where the pattern is 0. Alpha is unused
+    pub color_a: [f32; 4],
+    /// Color where the pattern is 1. Alpha is unused
+    pub color_b: [f32; 4],
+    /// Which [`ProceduralKind`] the pattern is
+    pub kind: u32,
+    /// Which [`TextureSpace`] the pattern is evaluated in
+    pub space: u32,
+    /// Positions are multiplied by it, so larger values give a finer pattern
+    pub scale: f32,
+    /// Layers of noise summed up by noise and marble, at most [`MAX_OCTAVES`]
+    pub octaves: u32,
+}
+
+shader_layout!(ProceduralTexture => "ProceduralTexture" {
+    color_a,
+    color_b,
+    kind,
+    space,
+    scale,
+    octaves,
+});
+
+impl Default for ProceduralTexture {
+    fn default() -> Self {
+        ProceduralTexture::new(ProceduralKind::default())
+    }
+}
+
+impl ProceduralTexture {
+    /// Blends from white to black in object space
+    pub const fn new(kind: ProceduralKind) -> Self {
+        Self {
+            color_a: [1., 1., 1., 1.],
+            color_b: [0., 0., 0., 1.],
+            kind: kind as u32,
+            space: TextureSpace::Object as u32,
+            scale: 1.,
+            octaves: 4,
+        }
+    }
+    /// Defaults to white and black
+    pub fn with_colors(mut self, a: [f32; 3], b: [f32; 3]) -> Self {
+        self.color_a = [a[0], a[1], a[2], 1.];
+        self.color_b = [b[0], b[1], b[2], 1.];
+        self
+    }
+    /// Defaults to object space
+    pub fn with_space(mut self, space: TextureSpace) -> Self {
+        self.space = space as u32;
+        self
+    }
+    /// Defaults to 1
+    pub fn with_scale(mut self, scale: f32) -> Self {
+        self.scale = scale;
+        self
+    }
+    pub fn kind(&self) -> ProceduralKind {
+        ProceduralKind::from_u32(self.kind)
+    }
+    pub fn space(&self) -> TextureSpace {
+        TextureSpace::from_u32(self.space)
+    }
+    /// Color of the texture at a hit. Mirrors `procedural_texture` in the compute shader
+    pub fn evaluate(&self, object_position: Vec3, world_position: Vec3) -> Vec4 {
+        let p = match self.space() {
+            TextureSpace::Object => object_position,
+            TextureSpace::World => world_position,
+        } * self.scale;
+        let octaves = self.octaves.clamp(1, MAX_OCTAVES);
+        let t = match self.kind() {
+            ProceduralKind::Checker => {
+                let cell = p.floor();
+                (cell.x + cell.y + cell.z).rem_euclid(2.)
+            }
+            ProceduralKind::Noise => fbm(p, octaves),
+            ProceduralKind::Voronoi => voronoi(p),
+            ProceduralKind::Marble => 0.5 + 0.5 * ((p.x + 4. * fbm(p, octaves)) * PI).sin(),
+            ProceduralKind::Gradient => 0.5 - 0.5 * p.y,
+        };
+        Vec4::from(self.color_a).lerp(Vec4::from(self.color_b), t.clamp(0., 1.))
+    }
+}
+
+fn hash3(cell: IVec3) -> u32 {
+    let [x, y, z] = cell.to_array().map(|c| c as u32);
+    pcg_hash(x.wrapping_add(pcg_hash(y.wrapping_add(pcg_hash(z)))))
+}
+
+/// Dot product with one of the 12 edge directions of a cube, source: Perlin, Improving Noise
+fn gradient(hash: u32, p: Vec3) -> f32 {
+    let h = hash & 15;
+    let u = if h < 8 { p.x } else { p.y };
+    let v = if h < 4 {
+        p.y
+    } else if h == 12 || h == 14 {
+        p.x
+    } else {
+        p.z
+    };
+    let u = if h & 1 == 0 { u } else { -u };
+    let v = if h & 2 == 0 { v } else { -v };
+    u + v
+}
+
+/// Perlin noise between about -1 and 1
+fn perlin(p: Vec3) -> f32 {
+    let cell = p.floor();
+    let f = p - cell;
+    let i = cell.as_ivec3();
+    let w = f * f * f * (f * (f * 6. - 15.) + 10.);
+    let corner = |x: i32, y: i32, z: i32| {
+        let offset = IVec3::new(x, y, z);
+        gradient(hash3(i + offset), f - offset.as_vec3())
+    };
+    let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+    let x00 = lerp(corner(0, 0, 0), corner(1, 0, 0), w.x);
+    let x10 = lerp(corner(0, 1, 0), corner(1, 1, 0), w.x);
+    let x01 = lerp(corner(0, 0, 1), corner(1, 0, 1), w.x);
+    let x11 = lerp(corner(0, 1, 1), corner(1, 1, 1), w.x);
+    lerp(lerp(x00, x10, w.y), lerp(x01, x11, w.y), w.z)
+}
+
+/// Sum of `octaves` layers of perlin noise, each with double the frequency and half the
+/// amplitude of the last one. Mapped to about 0 to 1
+fn fbm(p: Vec3, octaves: u32) -> f32 {
+    let mut sum = 0.;
+    let mut amplitude = 0.5;
+    let mut total = 0.;
+    let mut frequency = 1.;
+    for _ in 0..octaves {
+        sum += amplitude * perlin(p * frequency);
+        total += amplitude;
+        amplitude *= 0.5;
+        frequency *= 2.;
+    }
+    0.5 + 0.5 * sum / total
+}
+
+/// Distance to the closest feature point. Every cell holds one point
+fn voronoi(p: Vec3) -> f32 {
+    let cell = p.floor().as_ivec3();
+    let mut closest = f32::MAX;
+    for z in -1..=1 {
+        for y in -1..=1 {
+            for x in -1..=1 {
+                let neighbor = cell + IVec3::new(x, y, z);
+                let h = hash3(neighbor);
+                let offset = Vec3::new(
+                    u32_to_unit(h),
+                    u32_to_unit(pcg_hash(h)),
+                    u32_to_unit(pcg_hash(pcg_hash(h))),
+                );
+                closest = closest.min(p.distance(neighbor.as_vec3() + offset));
+            }
+        }
+    }
+    closest
+}
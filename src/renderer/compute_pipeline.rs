@@ -1,7 +1,21 @@
-use wgpu::{BindGroup, Buffer, BufferDescriptor, BufferUsages, Device, TextureView};
+use std::borrow::Cow;
+
+use bytemuck::Pod;
+use wgpu::{
+    BindGroup, Buffer, BufferDescriptor, BufferUsages, Device, Queue, Sampler, Texture, TextureView,
+};
 
 use crate::{
-    camera::CameraUniform, globals::Globals, material::Material, scene::Scene, sphere::Sphere,
+    camera::CameraUniform,
+    globals::Globals,
+    scene::Scene,
+    texture::{ImageTexture, TEXTURE_SIZE},
+};
+
+use super::{
+    hot_reload,
+    upload::{self, DirtyRange, UniformUpload},
+    PixelResources,
 };
 
 pub struct ComputePipeline {
@@ -12,18 +26,23 @@ pub struct ComputePipeline {
     pub camera_buffer: Buffer,
     pub sphere_buffer: Buffer,
     pub material_buffer: Buffer,
+    pub instance_buffer: Buffer,
+    /// One layer for every texture of the scene
+    pub texture_array: Texture,
+    pub texture_view: TextureView,
+    pub texture_sampler: Sampler,
+    pub procedural_buffer: Buffer,
+    /// Has to be recreated together with the output texture
+    pub pixels: PixelResources,
+    /// Amount of converged pixels of the last dispatch.
+    /// Has to be cleared before every dispatch
+    pub converged_buffer: Buffer,
+    globals_upload: UniformUpload<Globals>,
+    camera_upload: UniformUpload<CameraUniform>,
 }
 
 impl ComputePipeline {
-    pub fn new(device: &wgpu::Device, scene: &Scene) -> Self {
-        // Load shader
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Compute Shader"),
-            source: wgpu::ShaderSource::Wgsl(
-                include_str!("../../assets/shaders/compute.wgsl").into(),
-            ),
-        });
-
+    pub fn new(device: &wgpu::Device, scene: &Scene, pixels: PixelResources) -> Self {
         // Bind Groups
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: Some("Compute"),
@@ -84,21 +103,108 @@ impl ComputePipeline {
                     },
                     count: None,
                 },
+                // pixel statistics for adaptive sampling
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // converged pixel counter
+                wgpu::BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // history of the output texture
+                wgpu::BindGroupLayoutEntry {
+                    binding: 7,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                // first hit depth
+                wgpu::BindGroupLayoutEntry {
+                    binding: 8,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // history of the first hit depth
+                wgpu::BindGroupLayoutEntry {
+                    binding: 9,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // instances placing the spheres in the world
+                wgpu::BindGroupLayoutEntry {
+                    binding: 10,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // textures of the materials
+                wgpu::BindGroupLayoutEntry {
+                    binding: 11,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 12,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                // procedural textures of the materials
+                wgpu::BindGroupLayoutEntry {
+                    binding: 13,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
             ],
         });
 
-        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: None,
-            bind_group_layouts: &[&bind_group_layout],
-            push_constant_ranges: &[],
-        });
-
-        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-            label: None,
-            layout: Some(&pipeline_layout),
-            module: &shader,
-            entry_point: "main",
-        });
+        let pipeline = create_pipeline(
+            device,
+            &bind_group_layout,
+            include_str!("../../assets/shaders/compute.wgsl"),
+        );
         let globals_buffer = device.create_buffer(&BufferDescriptor {
             label: "Globals buffer".into(),
             size: std::mem::size_of::<Globals>() as u64,
@@ -111,20 +217,34 @@ impl ComputePipeline {
             usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
-        let size = std::mem::size_of::<Sphere>() * scene.spheres.len();
-        let sphere_buffer = device.create_buffer(&BufferDescriptor {
-            label: "Spheres buffer".into(),
-            size: size as u64,
-            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+        let converged_buffer = device.create_buffer(&BufferDescriptor {
+            label: "Converged pixels buffer".into(),
+            size: std::mem::size_of::<u32>() as u64,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
-        let size = std::mem::size_of::<Material>() * scene.materials.len();
-
-        let material_buffer = device.create_buffer(&BufferDescriptor {
-            label: "Material buffer".into(),
-            size: size as u64,
-            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
-            mapped_at_creation: false,
+        let sphere_buffer =
+            create_storage_buffer(device, "Spheres buffer", &or_placeholder(&scene.spheres));
+        let material_buffer = create_storage_buffer(
+            device,
+            "Material buffer",
+            &or_placeholder(&scene.gpu_materials()),
+        );
+        let instance_buffer = create_storage_buffer(device, "Instance buffer", &scene.instances);
+        let procedural_buffer = create_storage_buffer(
+            device,
+            "Procedural texture buffer",
+            &or_placeholder(&scene.procedurals),
+        );
+        let texture_array = create_texture_array(device, &scene.textures);
+        let texture_view = create_texture_array_view(&texture_array);
+        let texture_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Texture sampler"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
         });
         Self {
             pipeline,
@@ -134,52 +254,308 @@ impl ComputePipeline {
             camera_buffer,
             globals_buffer,
             material_buffer,
+            instance_buffer,
+            texture_array,
+            texture_view,
+            texture_sampler,
+            procedural_buffer,
+            pixels,
+            converged_buffer,
+            globals_upload: UniformUpload::default(),
+            camera_upload: UniformUpload::default(),
+        }
+    }
+    /// Recompiles the pipeline from `source`.
+    /// On failure the current pipeline is kept and the error is returned
+    pub fn reload(&mut self, device: &Device, source: &str) -> Result<(), String> {
+        self.pipeline = hot_reload::catch_validation(device, || {
+            create_pipeline(device, &self.bind_group_layout, source)
+        })?;
+        Ok(())
+    }
+    /// Writes everything that changed since the last call to the gpu buffers.
+    ///
+    /// Storage buffers and the texture array are recreated if the amount of spheres, materials,
+    /// instances or (procedural) textures changed. In that case the bind group gets invalidated as well
+    pub fn upload(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        scene: &mut Scene,
+        globals: Globals,
+        camera_uniform: CameraUniform,
+    ) {
+        self.globals_upload
+            .write(queue, &self.globals_buffer, globals);
+        self.camera_upload
+            .write(queue, &self.camera_buffer, camera_uniform);
+
+        scene.update_instances();
+        let recreated = upload_storage(
+            device,
+            queue,
+            &mut self.sphere_buffer,
+            "Spheres buffer",
+            &or_placeholder(&scene.spheres),
+            &mut scene.dirty_spheres,
+        ) | upload_storage(
+            device,
+            queue,
+            &mut self.material_buffer,
+            "Material buffer",
+            &or_placeholder(&scene.gpu_materials()),
+            &mut scene.dirty_materials,
+        ) | upload_storage(
+            device,
+            queue,
+            &mut self.instance_buffer,
+            "Instance buffer",
+            &scene.instances,
+            &mut scene.dirty_instances,
+        ) | upload_storage(
+            device,
+            queue,
+            &mut self.procedural_buffer,
+            "Procedural texture buffer",
+            &or_placeholder(&scene.procedurals),
+            &mut scene.dirty_procedurals,
+        ) | self.upload_textures(device, queue, scene);
+        if recreated {
+            self.bind_group = None;
+        }
+    }
+    /// Writes the changed textures into their layers.
+    /// Returns true if the texture array had to be recreated because the amount changed
+    fn upload_textures(&mut self, device: &Device, queue: &Queue, scene: &mut Scene) -> bool {
+        let textures = &scene.textures;
+        let recreate = self.texture_array.depth_or_array_layers() != textures.len().max(1) as u32;
+        if recreate {
+            self.texture_array = create_texture_array(device, textures);
+            self.texture_view = create_texture_array_view(&self.texture_array);
+            scene.dirty_textures.mark_all(textures.len());
+        }
+        if let Some(range) = scene.dirty_textures.take() {
+            let range = range.start.min(textures.len())..range.end.min(textures.len());
+            for (layer, texture) in range.clone().zip(&textures[range]) {
+                write_texture_layer(queue, &self.texture_array, layer, texture);
+            }
         }
+        recreate
     }
+    /// Replaces the per pixel resources, e.g. after the output texture got resized
+    pub fn set_pixel_resources(&mut self, pixels: PixelResources) {
+        self.pixels = pixels;
+        self.bind_group = None;
+    }
+    /// Creates the bind group if it got invalidated
     pub fn prepare_bind_group(&mut self, device: &Device, output_texture_view: &TextureView) {
-         self.bind_group = Some(device.create_bind_group(&wgpu::BindGroupDescriptor {
+        if self.bind_group.is_some() {
+            return;
+        }
+        self.bind_group = Some(device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("Compute bind group"),
             layout: &self.bind_group_layout,
             entries: &[
                 wgpu::BindGroupEntry {
                     binding: 0,
                     resource: wgpu::BindingResource::Buffer(
-                        self
-                            .globals_buffer
-                            .as_entire_buffer_binding(),
+                        self.globals_buffer.as_entire_buffer_binding(),
                     ),
                 },
                 wgpu::BindGroupEntry {
                     binding: 1,
                     resource: wgpu::BindingResource::Buffer(
-                        self
-                            .camera_buffer
-                            .as_entire_buffer_binding(),
+                        self.camera_buffer.as_entire_buffer_binding(),
                     ),
                 },
                 wgpu::BindGroupEntry {
                     binding: 2,
-                    resource: wgpu::BindingResource::TextureView(
-                        &output_texture_view,
-                    ),
+                    resource: wgpu::BindingResource::TextureView(output_texture_view),
                 },
                 wgpu::BindGroupEntry {
                     binding: 3,
                     resource: wgpu::BindingResource::Buffer(
-                        self
-                            .sphere_buffer
-                            .as_entire_buffer_binding(),
+                        self.sphere_buffer.as_entire_buffer_binding(),
                     ),
                 },
                 wgpu::BindGroupEntry {
                     binding: 4,
                     resource: wgpu::BindingResource::Buffer(
-                        self
-                            .material_buffer
-                            .as_entire_buffer_binding(),
+                        self.material_buffer.as_entire_buffer_binding(),
+                    ),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::Buffer(
+                        self.pixels.pixel_stats.as_entire_buffer_binding(),
+                    ),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: wgpu::BindingResource::Buffer(
+                        self.converged_buffer.as_entire_buffer_binding(),
+                    ),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: wgpu::BindingResource::TextureView(&self.pixels.history_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 8,
+                    resource: wgpu::BindingResource::Buffer(
+                        self.pixels.depth.as_entire_buffer_binding(),
+                    ),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 9,
+                    resource: wgpu::BindingResource::Buffer(
+                        self.pixels.history_depth.as_entire_buffer_binding(),
+                    ),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 10,
+                    resource: wgpu::BindingResource::Buffer(
+                        self.instance_buffer.as_entire_buffer_binding(),
+                    ),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 11,
+                    resource: wgpu::BindingResource::TextureView(&self.texture_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 12,
+                    resource: wgpu::BindingResource::Sampler(&self.texture_sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 13,
+                    resource: wgpu::BindingResource::Buffer(
+                        self.procedural_buffer.as_entire_buffer_binding(),
                     ),
                 },
             ],
         }));
     }
 }
+
+fn create_pipeline(
+    device: &Device,
+    bind_group_layout: &wgpu::BindGroupLayout,
+    source: &str,
+) -> wgpu::ComputePipeline {
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Compute Shader"),
+        source: wgpu::ShaderSource::Wgsl(source.into()),
+    });
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: None,
+        bind_group_layouts: &[bind_group_layout],
+        push_constant_ranges: &[],
+    });
+    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: None,
+        layout: Some(&pipeline_layout),
+        module: &shader,
+        entry_point: "main",
+    })
+}
+
+/// Storage buffers can not be empty, so a scene without spheres, materials or procedural
+/// textures uploads a single zeroed one, which nothing refers to.
+/// Instances have their own placeholder, [`Instance::NONE`](crate::scene_graph::Instance::NONE)
+fn or_placeholder<T: Pod>(items: &[T]) -> Cow<'_, [T]> {
+    if items.is_empty() {
+        Cow::Owned(vec![T::zeroed()])
+    } else {
+        Cow::Borrowed(items)
+    }
+}
+
+fn create_storage_buffer<T: Pod>(device: &Device, label: &str, items: &[T]) -> Buffer {
+    device.create_buffer(&BufferDescriptor {
+        label: label.into(),
+        size: std::mem::size_of_val(items) as u64,
+        usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+/// Uploads the dirty part of `items`.
+/// Returns true if the buffer had to be recreated because the size changed
+fn upload_storage<T: Pod>(
+    device: &Device,
+    queue: &Queue,
+    buffer: &mut Buffer,
+    label: &str,
+    items: &[T],
+    dirty: &mut DirtyRange,
+) -> bool {
+    let recreate = buffer.size() != std::mem::size_of_val(items) as u64;
+    if recreate {
+        *buffer = create_storage_buffer(device, label, items);
+        *dirty = DirtyRange::all(items.len());
+    }
+    if let Some(range) = dirty.take() {
+        let range = range.start.min(items.len())..range.end.min(items.len());
+        upload::write_slice_range(queue, buffer, items, range);
+    }
+    recreate
+}
+
+/// Texture array with one layer per texture.
+/// Textures can not be empty, so there is at least one layer, which no material refers to
+fn create_texture_array(device: &Device, textures: &[ImageTexture]) -> Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Texture array"),
+        size: wgpu::Extent3d {
+            width: TEXTURE_SIZE,
+            height: TEXTURE_SIZE,
+            depth_or_array_layers: textures.len().max(1) as u32,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        // srgb is decoded in the shader, since roughness and metallic are stored linear
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    })
+}
+
+fn create_texture_array_view(texture_array: &Texture) -> TextureView {
+    // a single layer would be viewed as a plain 2d texture by default
+    texture_array.create_view(&wgpu::TextureViewDescriptor {
+        dimension: Some(wgpu::TextureViewDimension::D2Array),
+        ..Default::default()
+    })
+}
+
+fn write_texture_layer(
+    queue: &Queue,
+    texture_array: &Texture,
+    layer: usize,
+    texture: &ImageTexture,
+) {
+    queue.write_texture(
+        wgpu::ImageCopyTexture {
+            texture: texture_array,
+            mip_level: 0,
+            origin: wgpu::Origin3d {
+                x: 0,
+                y: 0,
+                z: layer as u32,
+            },
+            aspect: wgpu::TextureAspect::All,
+        },
+        texture.image.as_raw(),
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(4 * TEXTURE_SIZE),
+            rows_per_image: Some(TEXTURE_SIZE),
+        },
+        wgpu::Extent3d {
+            width: TEXTURE_SIZE,
+            height: TEXTURE_SIZE,
+            depth_or_array_layers: 1,
+        },
+    );
+}
@@ -0,0 +1,316 @@
+//! Reference path tracer running on the cpu.
+//!
+//! Implements the same integrator as `compute.wgsl` on the same scene model.
+//! It is much slower than the gpu, but needs no adapter and serves as ground truth for the gpu image.
+use std::{f32::consts::PI, sync::Mutex, thread};
+
+use glam::{UVec2, Vec2, Vec3, Vec4, Vec4Swizzles};
+use image::Rgba32FImage;
+
+use crate::{
+    globals::Globals,
+    sampler::{Sampler, SamplerKind},
+    scene::Scene,
+    texture::{self, TextureChannel, TextureRef},
+};
+
+use super::Renderer;
+
+const MIN_HIT_DISTANCE: f32 = 0.000000001;
+/// Distance new rays start off the surface, so they don't hit it again right away
+const SURFACE_OFFSET: f32 = 0.0001;
+/// Reflectance of non metallic surfaces at normal incidence
+const DIELECTRIC_REFLECTANCE: f32 = 0.04;
+/// Rows of pixels rendered by a thread at once
+const TILE_HEIGHT: u32 = 8;
+
+struct HitPayload {
+    normal: Vec3,
+    hit_position: Vec3,
+    sphere_index: usize,
+    uv: Vec2,
+    /// Hit position relative to the center of the sphere, before its transform
+    object_position: Vec3,
+}
+
+impl Renderer {
+    /// Traces `samples` more paths for every pixel of `image_buffer` on the cpu.
+    ///
+    /// Like the output texture of the compute pipeline, the image holds the sum of all samples
+    /// and the amount of samples in the alpha channel.
+    pub fn render_cpu(&mut self, scene: &Scene, globals: &Globals, samples: u32) {
+        render_image(&mut self.image_buffer, scene, globals, samples);
+        self.acc_frame += 1;
+        self.acc_samples += samples as usize;
+        self.dispatched_samples += samples as usize;
+    }
+}
+
+/// Adds `samples` paths to every pixel of `image`, multithreaded over tiles of rows
+pub fn render_image(image: &mut Rgba32FImage, scene: &Scene, globals: &Globals, samples: u32) {
+    let (width, height) = image.dimensions();
+    let size = UVec2::new(width, height);
+    let tile_len = (width * TILE_HEIGHT * 4) as usize;
+    let tiles = Mutex::new(image.chunks_mut(tile_len).enumerate());
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    thread::scope(|s| {
+        for _ in 0..threads {
+            s.spawn(|| loop {
+                let Some((tile, pixels)) = tiles.lock().unwrap().next() else {
+                    break;
+                };
+                for (i, pixel) in pixels.chunks_exact_mut(4).enumerate() {
+                    let position = UVec2::new(
+                        i as u32 % width,
+                        tile as u32 * TILE_HEIGHT + i as u32 / width,
+                    );
+                    render_pixel(pixel, position, size, scene, globals, samples);
+                }
+            });
+        }
+    });
+}
+
+fn render_pixel(
+    pixel: &mut [f32],
+    position: UVec2,
+    size: UVec2,
+    scene: &Scene,
+    globals: &Globals,
+    samples: u32,
+) {
+    let kind = SamplerKind::from_u32(globals.sampler_kind);
+    let mut color_sum = Vec3::ZERO;
+    for s in 0..samples {
+        // the sample index continues the sequence of the pixel
+        let index = pixel[3] as u32 + s;
+        let mut sampler = Sampler::new(kind, globals.seed, position, index);
+        color_sum += trace_path(position, size, scene, globals, &mut sampler);
+    }
+    pixel[0] += color_sum.x;
+    pixel[1] += color_sum.y;
+    pixel[2] += color_sum.z;
+    pixel[3] += samples as f32;
+}
+
+/// Traces a single path through the scene and returns the gathered light.
+/// Mirrors `trace_path` in the compute shader
+pub fn trace_path(
+    position: UVec2,
+    size: UVec2,
+    scene: &Scene,
+    globals: &Globals,
+    sampler: &mut Sampler,
+) -> Vec3 {
+    let Some((mut ray_origin, mut ray_direction)) = scene.camera.ray(position, size) else {
+        return Vec3::ZERO;
+    };
+    let [open, close] = scene.camera.shutter;
+    // the time is only sampled for motion blur, to keep the sample sequence otherwise
+    let time = if open != close {
+        open + (close - open) * sampler.next_2d().x
+    } else {
+        open
+    };
+    // like the time, the lens is only sampled for depth of field
+    if scene.camera.aperture > 0. {
+        (ray_origin, ray_direction) =
+            scene
+                .camera
+                .lens_ray(ray_origin, ray_direction, sampler.next_2d());
+    }
+    let mut light = Vec3::ZERO;
+    let mut contribution = Vec3::ONE;
+    let mut bounced = globals.bounces;
+    let sky_color = Vec3::from_slice(&globals.sky_color);
+    for i in 0..globals.bounces {
+        let Some(payload) = trace_ray(scene, ray_origin, ray_direction, time) else {
+            light += sky_color * contribution;
+            bounced = 1 + i;
+            break;
+        };
+        let sphere = &scene.spheres[payload.sphere_index];
+        let material = &scene.materials[sphere.material_index as usize].material;
+        // rays leaving a sphere hit its inside, where the surface faces the other way
+        let front_face = ray_direction.dot(payload.normal) < 0.;
+        let normal = if front_face {
+            payload.normal
+        } else {
+            -payload.normal
+        };
+
+        let sample =
+            |channel, color| sample_texture(scene, material.texture(channel), &payload, color);
+        let roughness = material.roughness * sample(TextureChannel::Roughness, false).x;
+        let metallic = material.metallic * sample(TextureChannel::Metallic, false).x;
+        let albedo =
+            Vec3::from_slice(&material.albedo.0) * sample(TextureChannel::Albedo, true).xyz();
+        let computed_roughness = roughness.powf(2.);
+        let unit_direction = ray_direction.normalize();
+        // metals reflect all light tinted by their albedo. Other materials reflect a small,
+        // untinted part of it specularly and scatter the rest diffusely
+        let cos_theta = (-unit_direction.dot(normal)).clamp(0., 1.);
+        let reflectance = schlick(cos_theta, DIELECTRIC_REFLECTANCE);
+        let specular = reflectance + (1. - reflectance) * metallic;
+        let choice = sampler.next_2d();
+        let mut side = normal;
+        let tint = if choice.y < material.transmission {
+            // glass reflects by the fresnel term and refracts the rest, tinted by its albedo
+            let n = (normal + computed_roughness * in_unit_sphere(sampler)).normalize();
+            let eta = if front_face {
+                1. / material.ior
+            } else {
+                material.ior
+            };
+            let cos_i = (-unit_direction.dot(n)).clamp(0., 1.);
+            let sin_t = eta * (1. - cos_i * cos_i).sqrt();
+            let r0 = ((1. - material.ior) / (1. + material.ior)).powf(2.);
+            // beyond the critical angle all light is reflected
+            if sin_t > 1. || choice.x < schlick(cos_i, r0) {
+                ray_direction = reflect(unit_direction, n);
+                Vec3::ONE
+            } else {
+                ray_direction = refract(unit_direction, n, eta);
+                side = -normal;
+                albedo
+            }
+        } else if choice.x < specular {
+            let n = (normal + computed_roughness * in_unit_sphere(sampler)).normalize();
+            ray_direction = reflect(ray_direction, n);
+            Vec3::ONE.lerp(albedo, metallic)
+        } else {
+            let scattered = normal + unit_vector(sampler);
+            ray_direction = if scattered.length_squared() > 1e-8 {
+                scattered
+            } else {
+                normal
+            };
+            albedo
+        };
+        ray_origin = payload.hit_position + side * SURFACE_OFFSET;
+        contribution *= tint * material.specular_intensity;
+        let emission =
+            Vec3::from_slice(&material.emission) * sample(TextureChannel::Emission, true).xyz();
+        light += emission * material.emission[3] * contribution;
+    }
+    light / bounced as f32
+}
+
+/// Closest sphere hit along the ray. Mirrors `trace_ray` in the compute shader
+fn trace_ray(
+    scene: &Scene,
+    ray_origin: Vec3,
+    ray_direction: Vec3,
+    time: f32,
+) -> Option<HitPayload> {
+    let mut closest_hit_distance = f32::MAX;
+    let mut closest_index = None;
+    for (i, instance) in scene.instances.iter().enumerate() {
+        let Some(sphere) = scene.spheres.get(instance.sphere_index as usize) else {
+            continue;
+        };
+        // intersect in object space. The direction is not normalized,
+        // so the hit distance is the same as in world space
+        let direction = instance.world_to_object.transform_vector3(ray_direction);
+        let origin = instance.world_to_object.transform_point3(ray_origin) - sphere.center_at(time);
+        let a = direction.dot(direction);
+        let b = 2. * origin.dot(direction);
+        let c = origin.dot(origin) - sphere.radius * sphere.radius;
+        let discriminant = b * b - 4. * a * c;
+        if discriminant < 0. {
+            continue;
+        }
+        let mut hit_distance = (-b - discriminant.sqrt()) / (2. * a);
+        if hit_distance <= MIN_HIT_DISTANCE {
+            // the ray starts inside of the sphere
+            hit_distance = (-b + discriminant.sqrt()) / (2. * a);
+        }
+        if hit_distance > MIN_HIT_DISTANCE && closest_hit_distance > hit_distance {
+            closest_hit_distance = hit_distance;
+            closest_index = Some(i);
+        }
+    }
+    let instance = &scene.instances[closest_index?];
+    let sphere_index = instance.sphere_index as usize;
+    let center = scene.spheres[sphere_index].center_at(time);
+    let origin = instance.world_to_object.transform_point3(ray_origin) - center;
+    let direction = instance.world_to_object.transform_vector3(ray_direction);
+    let hit_point = origin + direction * closest_hit_distance;
+    // normals transform with the inverse transpose
+    let normal = instance
+        .world_to_object
+        .transpose()
+        .transform_vector3(hit_point);
+    Some(HitPayload {
+        normal: normal.normalize(),
+        hit_position: instance
+            .object_to_world
+            .transform_point3(hit_point + center),
+        sphere_index,
+        uv: texture::sphere_uv(hit_point.normalize()),
+        object_position: hit_point,
+    })
+}
+
+/// Value of a material texture at the hit, one without texture.
+/// Colors of images are decoded from srgb if `color` is true.
+/// Mirrors `sample_texture` in the compute shader
+fn sample_texture(
+    scene: &Scene,
+    texture: Option<TextureRef>,
+    payload: &HitPayload,
+    color: bool,
+) -> Vec4 {
+    match texture {
+        Some(TextureRef::Image(i)) => {
+            let value = scene.textures[i].sample(payload.uv);
+            if color {
+                texture::srgb_to_linear(value.xyz()).extend(value.w)
+            } else {
+                value
+            }
+        }
+        Some(TextureRef::Procedural(i)) => {
+            scene.procedurals[i].evaluate(payload.object_position, payload.hit_position)
+        }
+        None => Vec4::ONE,
+    }
+}
+
+fn hemisphere_sample_uniform(u: f32, v: f32) -> Vec3 {
+    let phi = v * 2.0 * PI;
+    let cos_theta = 1.0 - u;
+    let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+    Vec3::new(phi.cos() * sin_theta, phi.sin() * sin_theta, cos_theta)
+}
+
+fn in_unit_sphere(sampler: &mut Sampler) -> Vec3 {
+    let u = sampler.next_2d();
+    hemisphere_sample_uniform(u.x, u.y)
+}
+
+/// Uniformly distributed direction
+fn unit_vector(sampler: &mut Sampler) -> Vec3 {
+    let u = sampler.next_2d();
+    let z = 1. - 2. * u.x;
+    let r = (1. - z * z).max(0.).sqrt();
+    let phi = 2. * PI * u.y;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+/// Schlick's approximation of the fraction of light reflected at the given angle
+fn schlick(cos_theta: f32, reflectance: f32) -> f32 {
+    reflectance + (1. - reflectance) * (1. - cos_theta).powf(5.)
+}
+
+fn reflect(direction: Vec3, normal: Vec3) -> Vec3 {
+    direction - 2. * normal.dot(direction) * normal
+}
+
+/// Bends a unit direction through a surface, `eta` is the ratio of the refraction indices
+fn refract(direction: Vec3, normal: Vec3, eta: f32) -> Vec3 {
+    let cos_theta = (-direction).dot(normal).min(1.);
+    let perpendicular = eta * (direction + cos_theta * normal);
+    let parallel = -(1. - perpendicular.dot(perpendicular)).abs().sqrt() * normal;
+    parallel + perpendicular
+}
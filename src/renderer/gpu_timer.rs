@@ -0,0 +1,154 @@
+//! Measures how long the passes of a frame take on the gpu with timestamp queries.
+//!
+//! Like [`super::readback::U32Readback`], the timestamps are mapped asynchronously
+//! after submission, so the results arrive with a delay of a few frames.
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use wgpu::{
+    Buffer, BufferDescriptor, BufferUsages, CommandEncoder, Device, Features, Maintain, MapMode,
+    QuerySet, QuerySetDescriptor, QueryType, Queue,
+};
+
+/// The passes of a frame which are timed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pass {
+    Compute = 0,
+    Display = 1,
+    Ui = 2,
+}
+
+impl Pass {
+    pub const ALL: [Pass; 3] = [Pass::Compute, Pass::Display, Pass::Ui];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Pass::Compute => "compute",
+            Pass::Display => "display",
+            Pass::Ui => "ui",
+        }
+    }
+}
+
+/// Duration of every [`Pass`], indexed by the pass. None if the pass did not run
+pub type PassTimes = [Option<Duration>; 3];
+
+/// A start and an end timestamp for every pass
+const QUERY_COUNT: u32 = 2 * Pass::ALL.len() as u32;
+const QUERY_BUFFER_SIZE: u64 = QUERY_COUNT as u64 * std::mem::size_of::<u64>() as u64;
+
+enum State {
+    Idle,
+    /// Timestamps are written into the current encoder. Holds a bit for every finished pass
+    Recording(u8),
+    /// The queries were resolved, but the encoder is not yet submitted
+    Recorded(u8),
+    /// The buffer is being mapped. The mutex is set by the map callback
+    Mapping(u8, Arc<Mutex<Option<bool>>>),
+}
+
+pub struct GpuTimer {
+    query_set: QuerySet,
+    resolve_buffer: Buffer,
+    staging: Buffer,
+    /// Nanoseconds per timestamp tick
+    period: f32,
+    state: State,
+}
+
+impl GpuTimer {
+    /// Returns None if the device was not created with [`Features::TIMESTAMP_QUERY`]
+    pub fn new(device: &Device, queue: &Queue) -> Option<Self> {
+        if !device.features().contains(Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+        let query_set = device.create_query_set(&QuerySetDescriptor {
+            label: Some("Pass timestamps"),
+            ty: QueryType::Timestamp,
+            count: QUERY_COUNT,
+        });
+        let resolve_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Timestamp resolve buffer"),
+            size: QUERY_BUFFER_SIZE,
+            usage: BufferUsages::QUERY_RESOLVE | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let staging = device.create_buffer(&BufferDescriptor {
+            label: Some("Timestamp readback"),
+            size: QUERY_BUFFER_SIZE,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Some(Self {
+            query_set,
+            resolve_buffer,
+            staging,
+            period: queue.get_timestamp_period(),
+            state: State::Idle,
+        })
+    }
+    /// Starts timing a new frame, if the timestamps of the last one are not in flight anymore
+    pub fn begin_frame(&mut self) {
+        if matches!(self.state, State::Idle) {
+            self.state = State::Recording(0);
+        }
+    }
+    pub fn begin(&mut self, encoder: &mut CommandEncoder, pass: Pass) {
+        if let State::Recording(_) = self.state {
+            encoder.write_timestamp(&self.query_set, 2 * pass as u32);
+        }
+    }
+    pub fn end(&mut self, encoder: &mut CommandEncoder, pass: Pass) {
+        if let State::Recording(finished) = &mut self.state {
+            encoder.write_timestamp(&self.query_set, 2 * pass as u32 + 1);
+            *finished |= 1 << pass as u8;
+        }
+    }
+    /// Copies the timestamps of the frame to the cpu. Has to be called after the last pass
+    pub fn resolve(&mut self, encoder: &mut CommandEncoder) {
+        let State::Recording(finished) = self.state else {
+            return;
+        };
+        encoder.resolve_query_set(&self.query_set, 0..QUERY_COUNT, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(&self.resolve_buffer, 0, &self.staging, 0, QUERY_BUFFER_SIZE);
+        self.state = State::Recorded(finished);
+    }
+    /// Has to be called after the encoder containing the resolved queries got submitted
+    pub fn submitted(&mut self) {
+        if let State::Recorded(finished) = self.state {
+            let result = Arc::new(Mutex::new(None));
+            let callback_result = result.clone();
+            self.staging.slice(..).map_async(MapMode::Read, move |r| {
+                *callback_result.lock().unwrap() = Some(r.is_ok());
+            });
+            self.state = State::Mapping(finished, result);
+        }
+    }
+    /// Returns the pass durations of a past frame, once the mapping finished
+    pub fn poll(&mut self, device: &Device) -> Option<PassTimes> {
+        let State::Mapping(finished, result) = &self.state else {
+            return None;
+        };
+        device.poll(Maintain::Poll);
+        let mapped = (*result.lock().unwrap())?;
+        let finished = *finished;
+        let times = mapped.then(|| {
+            let data = self.staging.slice(..).get_mapped_range();
+            let timestamps: &[u64] = bytemuck::cast_slice(&data);
+            let times = Pass::ALL.map(|pass| {
+                let i = pass as usize;
+                (finished & (1 << i) != 0).then(|| {
+                    let ticks = timestamps[2 * i + 1].saturating_sub(timestamps[2 * i]);
+                    Duration::from_nanos((ticks as f64 * self.period as f64) as u64)
+                })
+            });
+            drop(data);
+            self.staging.unmap();
+            times
+        });
+        self.state = State::Idle;
+        times
+    }
+}
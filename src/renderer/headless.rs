@@ -0,0 +1,318 @@
+//! Renders a scene with the compute pipeline without a window or surface.
+//!
+//! Used to compare the gpu image against references in the golden image tests
+//! and to measure the performance in the benchmark.
+use std::{
+    iter,
+    sync::mpsc::{self, TryRecvError},
+    task::Poll,
+    time::{Duration, Instant},
+};
+
+use image::{ImageBuffer, Rgba32FImage};
+use log::info;
+use wgpu::{AdapterInfo, Device, Features, Label, Limits, Queue};
+use winit::dpi::PhysicalSize;
+
+use crate::{camera::CameraUniform, globals::Globals, scene::Scene};
+
+use super::{compute_pipeline::ComputePipeline, Renderer};
+
+/// Samples traced by a single dispatch, to keep the dispatches short
+const SAMPLES_PER_DISPATCH: u32 = 16;
+/// Defined in the shader
+const WORKGROUP_SIZE: u32 = 16;
+
+/// Traces `samples` paths per pixel on the gpu and returns the accumulated image.
+/// Like [`Renderer::image_buffer`], the alpha channel holds the amount of samples.
+///
+/// Returns None if no suitable gpu adapter is available
+pub fn render(
+    scene: &mut Scene,
+    globals: Globals,
+    size: PhysicalSize<u32>,
+    samples: u32,
+) -> Option<Rgba32FImage> {
+    let headless = Headless::new()?;
+    headless
+        .render(scene, globals, size, samples)
+        .map(|rendered| rendered.image)
+}
+
+/// Result of [`Headless::render`]
+pub struct Rendered {
+    pub image: Rgba32FImage,
+    /// Time from the first dispatch until the gpu finished the last one
+    pub render_time: Duration,
+    /// Size of all gpu buffers and textures used for the image
+    pub gpu_memory: u64,
+}
+
+/// A gpu device without a surface, which can render several images
+pub struct Headless {
+    device: Device,
+    queue: Queue,
+    adapter_info: AdapterInfo,
+}
+
+impl Headless {
+    /// Returns None if no suitable gpu adapter is available
+    pub fn new() -> Option<Self> {
+        pollster::block_on(Self::new_async())
+    }
+    async fn new_async() -> Option<Self> {
+        // the gl backend can not translate the compute shader
+        let wgpu_instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::PRIMARY,
+            dx12_shader_compiler: Default::default(),
+        });
+        let adapter = wgpu_instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::default(),
+                compatible_surface: None,
+                force_fallback_adapter: false,
+            })
+            .await?;
+        // the output texture is read and written by the compute shader
+        let format_features = adapter.get_texture_format_features(wgpu::TextureFormat::Rgba32Float);
+        if !format_features
+            .flags
+            .contains(wgpu::TextureFormatFeatureFlags::STORAGE_READ_WRITE)
+        {
+            info!(
+                "Gpu {:?} can not read and write rgba32float storage textures",
+                adapter.get_info().name
+            );
+            return None;
+        }
+        let adapter_info = adapter.get_info();
+        info!("Gpu used: {adapter_info:?}");
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    features: Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES,
+                    limits: Limits::default(),
+                    label: Label::Some("headless gpu device"),
+                },
+                None,
+            )
+            .await
+            .ok()?;
+        Some(Self {
+            device,
+            queue,
+            adapter_info,
+        })
+    }
+    pub fn adapter_info(&self) -> &AdapterInfo {
+        &self.adapter_info
+    }
+    pub fn device(&self) -> &Device {
+        &self.device
+    }
+    pub fn queue(&self) -> &Queue {
+        &self.queue
+    }
+    /// Traces `samples` paths per pixel and returns the accumulated image.
+    /// Returns None if the image could not be read back
+    pub fn render(
+        &self,
+        scene: &mut Scene,
+        globals: Globals,
+        size: PhysicalSize<u32>,
+        samples: u32,
+    ) -> Option<Rendered> {
+        let device = &self.device;
+        let queue = &self.queue;
+        let renderer = Renderer::new(size);
+        let output_texture = renderer.create_input_texture(device);
+        let pixel_resources = renderer.create_pixel_resources(device);
+        let mut compute_pipeline = ComputePipeline::new(device, scene, pixel_resources);
+
+        let start = Instant::now();
+        trace(
+            device,
+            queue,
+            &mut compute_pipeline,
+            &output_texture,
+            scene,
+            globals,
+            samples,
+        );
+        device.poll(wgpu::Maintain::Wait);
+        let render_time = start.elapsed();
+
+        let texture_bytes = |texture: &wgpu::Texture| {
+            let size = texture.size();
+            size.width as u64 * size.height as u64 * 4 * std::mem::size_of::<f32>() as u64
+        };
+        let pixels = &compute_pipeline.pixels;
+        let layers = compute_pipeline.texture_array.size();
+        let gpu_memory = texture_bytes(&output_texture)
+            + texture_bytes(&pixels.history_texture)
+            // one byte per channel
+            + layers.width as u64 * layers.height as u64 * layers.depth_or_array_layers as u64 * 4
+            + [
+                &pixels.pixel_stats,
+                &pixels.depth,
+                &pixels.history_depth,
+                &compute_pipeline.globals_buffer,
+                &compute_pipeline.camera_buffer,
+                &compute_pipeline.sphere_buffer,
+                &compute_pipeline.material_buffer,
+                &compute_pipeline.instance_buffer,
+                &compute_pipeline.procedural_buffer,
+                &compute_pipeline.converged_buffer,
+            ]
+            .iter()
+            .map(|buffer| buffer.size())
+            .sum::<u64>();
+        let image = read_texture(device, queue, &output_texture, size)?;
+        Some(Rendered {
+            image,
+            render_time,
+            gpu_memory,
+        })
+    }
+}
+
+/// Submits dispatches until `samples` paths per pixel are added to `output_texture`,
+/// which has to be empty beforehand. Does not wait for the gpu to finish
+pub(super) fn trace(
+    device: &Device,
+    queue: &Queue,
+    compute_pipeline: &mut ComputePipeline,
+    output_texture: &wgpu::Texture,
+    scene: &mut Scene,
+    globals: Globals,
+    samples: u32,
+) {
+    let output_view = output_texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let wgpu::Extent3d { width, height, .. } = output_texture.size();
+    let camera_uniform = CameraUniform::from(&scene.camera);
+    let mut traced = 0;
+    let mut acc_frame = 0;
+    while traced < samples {
+        let samples_per_frame = SAMPLES_PER_DISPATCH.min(samples - traced);
+        let frame_globals = Globals {
+            samples_per_frame,
+            acc_frame,
+            ..globals
+        };
+        compute_pipeline.upload(device, queue, scene, frame_globals, camera_uniform);
+        compute_pipeline.prepare_bind_group(device, &output_view);
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Headless encoder"),
+        });
+        encoder.clear_buffer(&compute_pipeline.converged_buffer, 0, None);
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: "Compute Pass".into(),
+        });
+        compute_pass.set_pipeline(&compute_pipeline.pipeline);
+        compute_pass.set_bind_group(0, compute_pipeline.bind_group.as_ref().unwrap(), &[]);
+        compute_pass.dispatch_workgroups(
+            width.div_ceil(WORKGROUP_SIZE),
+            height.div_ceil(WORKGROUP_SIZE),
+            1,
+        );
+        drop(compute_pass);
+        queue.submit(iter::once(encoder.finish()));
+
+        traced += samples_per_frame;
+        acc_frame += 1;
+    }
+}
+
+/// Copies an rgba32float texture back to the cpu, blocking until the copy finished
+pub(super) fn read_texture(
+    device: &Device,
+    queue: &Queue,
+    texture: &wgpu::Texture,
+    size: PhysicalSize<u32>,
+) -> Option<Rgba32FImage> {
+    let readback = Readback::new(device, queue, texture, size);
+    device.poll(wgpu::Maintain::Wait);
+    match readback.poll(device) {
+        Poll::Ready(image) => image,
+        Poll::Pending => None,
+    }
+}
+
+/// An rgba32float texture being copied back to the cpu
+pub(super) struct Readback {
+    buffer: wgpu::Buffer,
+    size: PhysicalSize<u32>,
+    padded_row: u32,
+    mapped: mpsc::Receiver<Result<(), wgpu::BufferAsyncError>>,
+}
+
+impl Readback {
+    /// Submits the copy of `texture`, without waiting for the gpu
+    pub(super) fn new(
+        device: &Device,
+        queue: &Queue,
+        texture: &wgpu::Texture,
+        size: PhysicalSize<u32>,
+    ) -> Self {
+        let pixel_size = 4 * std::mem::size_of::<f32>() as u32;
+        let padded_row =
+            (size.width * pixel_size).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Headless readback buffer"),
+            size: padded_row as u64 * size.height as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Headless readback encoder"),
+        });
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row),
+                    rows_per_image: None,
+                },
+            },
+            texture.size(),
+        );
+        queue.submit(iter::once(encoder.finish()));
+
+        let (sender, mapped) = mpsc::channel();
+        buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+        Self {
+            buffer,
+            size,
+            padded_row,
+            mapped,
+        }
+    }
+    /// Polls the device without blocking. The image is None if the copy failed
+    pub(super) fn poll(&self, device: &Device) -> Poll<Option<Rgba32FImage>> {
+        device.poll(wgpu::Maintain::Poll);
+        match self.mapped.try_recv() {
+            Ok(Ok(())) => {}
+            Ok(Err(_)) | Err(TryRecvError::Disconnected) => return Poll::Ready(None),
+            Err(TryRecvError::Empty) => return Poll::Pending,
+        }
+        let unpadded_row = self.size.width as usize * 4 * std::mem::size_of::<f32>();
+        let data = self.buffer.slice(..).get_mapped_range();
+        let pixels = data
+            .chunks_exact(self.padded_row as usize)
+            .flat_map(|row| bytemuck::cast_slice::<u8, f32>(&row[..unpadded_row]))
+            .copied()
+            .collect();
+        Poll::Ready(ImageBuffer::from_raw(
+            self.size.width,
+            self.size.height,
+            pixels,
+        ))
+    }
+}
@@ -0,0 +1,105 @@
+//! Development mode which recompiles the pipelines when a shader file changes.
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::PathBuf,
+    time::{Duration, Instant, SystemTime},
+};
+
+use log::warn;
+use wgpu::Device;
+
+/// How often the shader directory is checked for changes
+const CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Watches the shader directory by polling the modification times of the files
+pub struct ShaderWatcher {
+    pub enabled: bool,
+    directory: PathBuf,
+    modified: HashMap<String, SystemTime>,
+    last_check: Instant,
+    /// Error message of every shader whose last reload failed, by file name
+    pub errors: BTreeMap<String, String>,
+}
+
+impl ShaderWatcher {
+    /// Watches `assets/shaders` of the source tree. Enabled by default in debug builds
+    pub fn new() -> Self {
+        let mut watcher = Self {
+            enabled: cfg!(debug_assertions),
+            directory: PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/shaders")),
+            modified: HashMap::new(),
+            last_check: Instant::now(),
+            errors: BTreeMap::new(),
+        };
+        // the shaders baked into the binary are the current ones
+        watcher.changed_files();
+        watcher
+    }
+    /// Returns the file name and source of every shader that changed since the last call
+    pub fn poll(&mut self) -> Vec<(String, String)> {
+        if self.last_check.elapsed() < CHECK_INTERVAL {
+            return Vec::new();
+        }
+        self.last_check = Instant::now();
+        self.changed_files()
+            .into_iter()
+            .filter_map(|(name, path)| match fs::read_to_string(&path) {
+                Ok(source) => Some((name, source)),
+                Err(e) => {
+                    warn!("Could not read {}: {e}", path.display());
+                    None
+                }
+            })
+            .collect()
+    }
+    /// Records the result of reloading the shader `name`
+    pub fn report(&mut self, name: String, result: Result<(), String>) {
+        match result {
+            Ok(()) => {
+                self.errors.remove(&name);
+            }
+            Err(e) => {
+                warn!("Could not reload {name}, keeping the last working pipeline");
+                self.errors.insert(name, e);
+            }
+        }
+    }
+    fn changed_files(&mut self) -> Vec<(String, PathBuf)> {
+        let Ok(entries) = fs::read_dir(&self.directory) else {
+            return Vec::new();
+        };
+        let mut changed = Vec::new();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_none_or(|extension| extension != "wgsl") {
+                continue;
+            }
+            let Ok(modified) = entry.metadata().and_then(|metadata| metadata.modified()) else {
+                continue;
+            };
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if self.modified.insert(name.clone(), modified) != Some(modified) {
+                changed.push((name, path));
+            }
+        }
+        changed
+    }
+}
+
+impl Default for ShaderWatcher {
+    fn default() -> Self {
+        ShaderWatcher::new()
+    }
+}
+
+/// Runs `create` and returns the validation error instead of panicking on it.
+/// Used to create pipelines from shaders which may not compile
+pub fn catch_validation<T>(device: &Device, create: impl FnOnce() -> T) -> Result<T, String> {
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let value = create();
+    match pollster::block_on(device.pop_error_scope()) {
+        Some(error) => Err(error.to_string()),
+        None => Ok(value),
+    }
+}
@@ -0,0 +1,239 @@
+//! Thumbnails of the materials, shown next to their settings in the editor.
+//!
+//! Every thumbnail is a sphere of the material on a checkerboard floor, rendered by a
+//! small compute pipeline of its own. Only materials which changed are rendered again.
+//! The thumbnails are read back from the gpu asynchronously, so the editor never waits for them.
+use std::task::Poll;
+
+use egui::{ColorImage, Context, TextureHandle, TextureOptions};
+use glam::Vec3;
+use wgpu::{Device, Queue, Texture};
+use winit::dpi::PhysicalSize;
+
+use crate::{
+    camera::Camera,
+    camera_path::CameraPose,
+    globals::Globals,
+    material::Material,
+    procedural::{ProceduralKind, ProceduralTexture, TextureSpace},
+    scene::Scene,
+    sphere::Sphere,
+    texture::{TextureChannel, TextureRef},
+};
+
+use super::{
+    compute_pipeline::ComputePipeline,
+    headless::{self, Readback},
+    Renderer,
+};
+
+/// Edge length of a thumbnail in pixels
+pub const PREVIEW_SIZE: u32 = 64;
+const SAMPLES: u32 = 32;
+/// Index of the previewed material in the preview scene
+const PREVIEWED: usize = 0;
+/// Index of the floor material in the preview scene
+const FLOOR: usize = 1;
+
+pub struct MaterialPreviews {
+    scene: Scene,
+    renderer: Renderer,
+    compute_pipeline: ComputePipeline,
+    output_texture: Texture,
+    /// Material and sky color of every thumbnail when it was rendered
+    rendered: Vec<Option<(Material, [f32; 4])>>,
+    textures: Vec<Option<TextureHandle>>,
+    /// Thumbnail which is still being rendered or read back
+    pending: Option<Pending>,
+}
+
+struct Pending {
+    index: usize,
+    material: Material,
+    sky_color: [f32; 4],
+    readback: Readback,
+}
+
+impl MaterialPreviews {
+    pub fn new(device: &Device) -> Self {
+        let scene = preview_scene();
+        let renderer = Renderer::new(PhysicalSize::new(PREVIEW_SIZE, PREVIEW_SIZE));
+        let output_texture = renderer.create_input_texture(device);
+        let compute_pipeline =
+            ComputePipeline::new(device, &scene, renderer.create_pixel_resources(device));
+        Self {
+            scene,
+            renderer,
+            compute_pipeline,
+            output_texture,
+            rendered: Vec::new(),
+            textures: Vec::new(),
+            pending: None,
+        }
+    }
+    /// Recompiles the compute shader and renders every thumbnail again
+    pub fn reload(&mut self, device: &Device, source: &str) -> Result<(), String> {
+        self.compute_pipeline.reload(device, source)?;
+        self.rendered.fill(None);
+        self.pending = None;
+        Ok(())
+    }
+    /// Thumbnail of the material at `index`, None until it was rendered once
+    pub fn texture(&self, index: usize) -> Option<&TextureHandle> {
+        self.textures.get(index)?.as_ref()
+    }
+    /// Shows the thumbnail which finished rendering and starts rendering the one of the
+    /// first material which changed since its last thumbnail.
+    /// A single thumbnail at a time keeps the frame time low while dragging a value
+    pub fn update(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        context: &Context,
+        scene: &Scene,
+        globals: &Globals,
+    ) {
+        self.copy_textures(scene);
+        let materials = scene.gpu_materials();
+        self.rendered.resize(materials.len(), None);
+        self.textures.resize(materials.len(), None);
+        if let Some(pending) = &self.pending {
+            let Poll::Ready(image) = pending.readback.poll(device) else {
+                return;
+            };
+            let pending = self.pending.take().unwrap();
+            // the material may have been removed in the meantime
+            if let Some(image) = image.filter(|_| pending.index < materials.len()) {
+                self.show(context, pending.index, &super::resolve(&image));
+                self.rendered[pending.index] = Some((pending.material, pending.sky_color));
+            }
+        }
+        let sky_color = globals.sky_color;
+        let outdated = materials
+            .iter()
+            .zip(&self.rendered)
+            .position(|(m, rendered)| {
+                rendered.is_none_or(|(material, sky)| {
+                    sky != sky_color || bytemuck::bytes_of(&material) != bytemuck::bytes_of(m)
+                })
+            });
+        let Some(index) = outdated else {
+            return;
+        };
+        let readback = self.render(device, queue, materials[index], globals);
+        self.pending = Some(Pending {
+            index,
+            material: materials[index],
+            sky_color,
+            readback,
+        });
+    }
+    fn show(&mut self, context: &Context, index: usize, image: &image::RgbaImage) {
+        let size = [image.width() as usize, image.height() as usize];
+        let image = ColorImage::from_rgba_unmultiplied(size, image.as_raw());
+        match &mut self.textures[index] {
+            Some(texture) => texture.set(image, TextureOptions::LINEAR),
+            texture => {
+                let name = format!("material preview {index}");
+                *texture = Some(context.load_texture(name, image, TextureOptions::LINEAR));
+            }
+        }
+    }
+    /// The preview scene keeps a copy of the textures, since it has its own gpu resources.
+    /// Every thumbnail is rendered again if they changed, dropping the one in flight
+    fn copy_textures(&mut self, scene: &Scene) {
+        let same_images = (self.scene.textures.iter().map(|t| &t.path))
+            .eq(scene.textures.iter().map(|t| &t.path));
+        if !same_images {
+            self.scene.textures = scene.textures.clone();
+            self.scene.dirty_textures.mark_all(scene.textures.len());
+            self.rendered.fill(None);
+            self.pending = None;
+        }
+        let procedurals = &self.scene.procedurals;
+        if procedurals[..procedurals.len() - 1] != scene.procedurals {
+            self.scene.procedurals = scene.procedurals.clone();
+            let checker = self.scene.add_procedural(floor_checker());
+            self.scene.materials[FLOOR].material = floor_material(checker);
+            self.scene.dirty_materials.mark(FLOOR);
+            self.scene
+                .dirty_procedurals
+                .mark_all(self.scene.procedurals.len());
+            self.rendered.fill(None);
+            self.pending = None;
+        }
+    }
+    fn render(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        material: Material,
+        globals: &Globals,
+    ) -> Readback {
+        self.scene.materials[PREVIEWED].material = material;
+        self.scene.dirty_materials.mark(PREVIEWED);
+        let globals = Globals {
+            sky_color: globals.sky_color,
+            bounces: 4,
+            ..Globals::default()
+        };
+        // a new texture starts without samples
+        self.output_texture = self.renderer.create_input_texture(device);
+        self.compute_pipeline.bind_group = None;
+
+        headless::trace(
+            device,
+            queue,
+            &mut self.compute_pipeline,
+            &self.output_texture,
+            &mut self.scene,
+            globals,
+            SAMPLES,
+        );
+        let size = PhysicalSize::new(PREVIEW_SIZE, PREVIEW_SIZE);
+        Readback::new(device, queue, &self.output_texture, size)
+    }
+}
+
+/// Squares with an edge length of 0.5 on the floor
+fn floor_checker() -> ProceduralTexture {
+    ProceduralTexture::new(ProceduralKind::Checker)
+        .with_colors([1., 1., 1.], [0.2, 0.2, 0.2])
+        .with_space(TextureSpace::World)
+        .with_scale(2.)
+}
+
+fn floor_material(checker: usize) -> Material {
+    Material::new()
+        .with_color([0.8, 0.8, 0.8])
+        .with_roughness(0.6)
+        .with_texture(TextureChannel::Albedo, TextureRef::Procedural(checker))
+}
+
+/// A sphere lit by a small lamp, standing on a checkerboard.
+/// The floor lies between two layers of checker cubes, to keep the squares clean
+fn preview_scene() -> Scene {
+    let spheres = vec![
+        Sphere::new(Vec3::new(0., 0.25, 0.), 1., PREVIEWED),
+        Sphere::new(Vec3::new(0., 1001.25, 0.), 1000., FLOOR),
+        Sphere::new(Vec3::new(-4., -4.75, -3.), 1.5, 2),
+    ];
+    let materials = vec![
+        Material::new(),
+        floor_material(0),
+        Material::new().with_emission([1., 1., 1.], 20.),
+    ];
+    // far away with a narrow fov, which keeps the perspective distortion low
+    let mut camera = Camera::new(60., 0.1, 100., 1., 1.);
+    let position = Vec3::new(0., -9.25, -28.5);
+    camera.set_pose(CameraPose {
+        position,
+        forward: Vec3::new(0., 0.35, 0.) - position,
+        fov: 60.,
+        ..camera.pose()
+    });
+    camera.resize(PhysicalSize::new(PREVIEW_SIZE, PREVIEW_SIZE));
+    let mut scene = Scene::new(spheres, materials, camera);
+    scene.add_procedural(floor_checker());
+    scene
+}
@@ -1,17 +1,47 @@
 #![allow(dead_code)]
 pub mod compute_pipeline;
 
+pub mod cpu;
+
+pub mod gpu_timer;
+
+pub mod headless;
+
+pub mod hot_reload;
+
+pub mod material_preview;
+
+pub mod preview;
+
+pub mod readback;
+
 pub mod render_pipeline;
 
-use image::{Rgba, Rgba32FImage};
+pub mod upload;
+
+use image::{Rgba, Rgba32FImage, RgbaImage};
 use log::warn;
-use wgpu::{Device, Extent3d, Texture};
+
+use crate::math;
+use wgpu::{
+    Buffer, BufferDescriptor, BufferUsages, Device, Extent3d, Texture, TextureView,
+    TextureViewDescriptor,
+};
 use winit::dpi::PhysicalSize;
 
 pub struct Renderer {
     /// This buffer can be used to draw on
     pub image_buffer: Rgba32FImage,
     pub acc_frame: usize,
+    /// Samples per pixel dispatched since the last reset.
+    /// Pixels skipped by adaptive sampling have less samples
+    pub acc_samples: usize,
+    /// Samples per pixel dispatched since the per pixel resources got recreated.
+    /// Unlike `acc_samples` it keeps counting when the camera moves and the history is reprojected
+    pub dispatched_samples: usize,
+    /// Fraction of the image resolution that is actually rendered.
+    /// Lower than 1 while previewing
+    pub render_scale: f32,
 }
 
 impl Renderer {
@@ -21,13 +51,20 @@ impl Renderer {
             Rgba32FImage::from_pixel(size.width, size.height, Rgba([0., 0., 0., 0.]));
         Self {
             image_buffer,
-            acc_frame: 1,
+            acc_frame: 0,
+            acc_samples: 0,
+            dispatched_samples: 0,
+            render_scale: 1.,
         }
     }
 
     pub fn get_image(&self) -> &Rgba32FImage {
         &self.image_buffer
     }
+    /// Averages the accumulated samples of `image_buffer` and converts them to srgb
+    pub fn resolved_image(&self) -> RgbaImage {
+        resolve(&self.image_buffer)
+    }
 
     pub fn resize(&mut self, new_size: PhysicalSize<u32>) {
         warn!(
@@ -35,17 +72,28 @@ impl Renderer {
             self.image_buffer.pixels().len()
         );
         self.acc_frame = 0;
+        self.acc_samples = 0;
+        self.dispatched_samples = 0;
 
         self.image_buffer =
             Rgba32FImage::from_pixel(new_size.width, new_size.height, Rgba([0., 0., 0., 0.]));
     }
     pub fn reset_acc(&mut self) {
         self.acc_frame = 0;
+        self.acc_samples = 0;
+        self.dispatched_samples = 0;
         self.image_buffer.fill(0.);
     }
 
+    /// Resolution the compute pipeline renders at
+    pub fn render_size(&self) -> (u32, u32) {
+        let (width, height) = self.image_buffer.dimensions();
+        let scale = |x: u32| ((x as f32 * self.render_scale).round() as u32).max(1);
+        (scale(width), scale(height))
+    }
+
     pub fn create_input_texture(&self, device: &Device) -> Texture {
-        let size = self.image_buffer.dimensions();
+        let size = self.render_size();
         let size = Extent3d {
             width: size.0,
             height: size.1,
@@ -60,8 +108,90 @@ impl Renderer {
             format: wgpu::TextureFormat::Rgba32Float,
             usage: wgpu::TextureUsages::TEXTURE_BINDING
                 | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC
                 | wgpu::TextureUsages::STORAGE_BINDING,
             view_formats: &[],
         })
     }
+    /// Creates all per pixel resources of the compute pipeline besides the output texture
+    pub fn create_pixel_resources(&self, device: &Device) -> PixelResources {
+        let (width, height) = self.render_size();
+        let pixels = (width as u64 * height as u64).max(1);
+        let f32_size = std::mem::size_of::<f32>() as u64;
+        let pixel_stats = device.create_buffer(&BufferDescriptor {
+            label: Some("Pixel stats buffer"),
+            // two luminance sums and the sample index, padded to 16 bytes
+            size: pixels * 4 * f32_size,
+            usage: BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let depth = device.create_buffer(&BufferDescriptor {
+            label: Some("Depth buffer"),
+            size: pixels * f32_size,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let history_depth = device.create_buffer(&BufferDescriptor {
+            label: Some("History depth buffer"),
+            size: pixels * f32_size,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let history_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("History texture"),
+            size: Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba32Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let history_view = history_texture.create_view(&TextureViewDescriptor::default());
+        PixelResources {
+            pixel_stats,
+            depth,
+            history_depth,
+            history_texture,
+            history_view,
+        }
+    }
+}
+
+/// Per pixel resources of the compute pipeline besides the output texture.
+/// They depend on the render resolution and are recreated together with the output texture
+pub struct PixelResources {
+    /// Luminance sum and squared luminance sum of every pixel, used by adaptive sampling,
+    /// and the amount of samples the pixel traced so far
+    pub pixel_stats: Buffer,
+    /// Distance to the first hit of every pixel, negative for the sky
+    pub depth: Buffer,
+    /// Copy of `depth` from before the frame, used for temporal reprojection
+    pub history_depth: Buffer,
+    /// Copy of the output texture from before the frame, used for temporal reprojection
+    pub history_texture: Texture,
+    pub history_view: TextureView,
+}
+
+/// Divides every pixel by its amount of samples, stored in alpha. The result stays linear
+pub fn resolve_linear(accumulated: &Rgba32FImage) -> Rgba32FImage {
+    Rgba32FImage::from_fn(accumulated.width(), accumulated.height(), |x, y| {
+        let [r, g, b, samples] = accumulated.get_pixel(x, y).0;
+        let samples = samples.max(1.);
+        Rgba([r / samples, g / samples, b / samples, 1.])
+    })
+}
+
+/// Divides every pixel by its amount of samples, stored in alpha, and converts the result to srgb
+pub fn resolve(accumulated: &Rgba32FImage) -> RgbaImage {
+    RgbaImage::from_fn(accumulated.width(), accumulated.height(), |x, y| {
+        let [r, g, b, samples] = accumulated.get_pixel(x, y).0;
+        let samples = samples.max(1.);
+        let srgb = math::as_srgb_u8([r / samples, g / samples, b / samples]);
+        Rgba([srgb[0], srgb[1], srgb[2], 255])
+    })
 }
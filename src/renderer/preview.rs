@@ -0,0 +1,46 @@
+use std::time::{Duration, Instant};
+
+/// Renders at a reduced internal resolution while the camera or scene is changing.
+///
+/// Every change restarts the accumulation anyway, so a cheap upscaled image
+/// gives quicker feedback than a single noisy sample at full resolution.
+pub struct Preview {
+    pub enabled: bool,
+    /// Fraction of the full resolution used while navigating
+    pub scale: f32,
+    /// Time without any change after which full resolution accumulation is restored
+    pub settle_time: Duration,
+    last_change: Option<Instant>,
+}
+
+impl Preview {
+    pub fn new() -> Self {
+        Self {
+            enabled: true,
+            scale: 0.25,
+            settle_time: Duration::from_millis(250),
+            last_change: None,
+        }
+    }
+    /// Has to be called whenever the camera or the scene changed
+    pub fn changed(&mut self) {
+        self.last_change = Some(Instant::now());
+    }
+    /// The resolution scale the renderer should use right now
+    pub fn render_scale(&self) -> f32 {
+        let navigating = self
+            .last_change
+            .is_some_and(|last| last.elapsed() < self.settle_time);
+        if self.enabled && navigating {
+            self.scale.clamp(0.01, 1.)
+        } else {
+            1.
+        }
+    }
+}
+
+impl Default for Preview {
+    fn default() -> Self {
+        Preview::new()
+    }
+}
@@ -0,0 +1,72 @@
+//! Reads small values computed on the gpu back to the cpu without stalling the frame.
+//!
+//! The copy is recorded into the frame's command encoder and mapped asynchronously after
+//! submission. Results therefore arrive with a delay of a few frames.
+use std::sync::{Arc, Mutex};
+
+use wgpu::{Buffer, BufferDescriptor, BufferUsages, CommandEncoder, Device, Maintain, MapMode};
+
+enum State {
+    Idle,
+    /// The copy was recorded, but the encoder is not yet submitted
+    Recorded(u32),
+    /// The buffer is being mapped. The mutex is set by the map callback
+    Mapping(u32, Arc<Mutex<Option<bool>>>),
+}
+
+/// Reads back a single u32 from a gpu buffer.
+/// Every value is tagged with a user provided epoch to detect stale results
+pub struct U32Readback {
+    staging: Buffer,
+    state: State,
+}
+
+impl U32Readback {
+    pub fn new(device: &Device, label: &str) -> Self {
+        let staging = device.create_buffer(&BufferDescriptor {
+            label: Some(label),
+            size: std::mem::size_of::<u32>() as u64,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Self {
+            staging,
+            state: State::Idle,
+        }
+    }
+    /// Records a copy of the first u32 in `source`, if no other read is in flight.
+    pub fn record(&mut self, encoder: &mut CommandEncoder, source: &Buffer, epoch: u32) {
+        if !matches!(self.state, State::Idle) {
+            return;
+        }
+        encoder.copy_buffer_to_buffer(source, 0, &self.staging, 0, self.staging.size());
+        self.state = State::Recorded(epoch);
+    }
+    /// Has to be called after the encoder containing the recorded copy got submitted
+    pub fn submitted(&mut self) {
+        if let State::Recorded(epoch) = self.state {
+            let result = Arc::new(Mutex::new(None));
+            let callback_result = result.clone();
+            self.staging.slice(..).map_async(MapMode::Read, move |r| {
+                *callback_result.lock().unwrap() = Some(r.is_ok());
+            });
+            self.state = State::Mapping(epoch, result);
+        }
+    }
+    /// Returns the value together with its epoch, once the mapping finished
+    pub fn poll(&mut self, device: &Device) -> Option<(u32, u32)> {
+        let State::Mapping(epoch, result) = &self.state else {
+            return None;
+        };
+        device.poll(Maintain::Poll);
+        let mapped = (*result.lock().unwrap())?;
+        let epoch = *epoch;
+        let value = mapped.then(|| {
+            let value = *bytemuck::from_bytes::<u32>(&self.staging.slice(..).get_mapped_range());
+            self.staging.unmap();
+            value
+        });
+        self.state = State::Idle;
+        value.map(|value| (value, epoch))
+    }
+}
@@ -1,14 +1,62 @@
 use wgpu::{
-    BindGroup, Buffer, BufferDescriptor, BufferUsages, Device, SurfaceConfiguration, Texture, TextureView, TextureViewDescriptor,
+    BindGroup, Buffer, BufferDescriptor, BufferUsages, Device, Queue, SurfaceConfiguration,
+    Texture, TextureFormat, TextureView, TextureViewDescriptor,
 };
 
+use crate::layout::shader_layout;
+
+use super::{hot_reload, upload::UniformUpload, PixelResources};
+
+/// What is shown in the viewport
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ViewMode {
+    /// The accumulated image
+    #[default]
+    Render,
+    /// Amount of samples per pixel, relative to the amount of dispatched samples
+    SampleHeatmap,
+}
+
+/// Uniform of the display shader
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct DisplayUniform {
+    /// Samples per pixel dispatched since the per pixel sample counts were reset
+    pub dispatched_samples: u32,
+    /// [`ViewMode`] as index
+    pub view_mode: u32,
+    /// Resolution of the rendered texture relative to the surface
+    pub render_scale: f32,
+    pub _offset: u32,
+}
+
+shader_layout!(DisplayUniform => "Display" {
+    dispatched_samples,
+    view_mode,
+    render_scale,
+});
+
+impl DisplayUniform {
+    pub fn new(dispatched_samples: usize, view_mode: ViewMode, render_scale: f32) -> Self {
+        Self {
+            dispatched_samples: dispatched_samples as u32,
+            view_mode: view_mode as u32,
+            render_scale,
+            _offset: 0,
+        }
+    }
+}
+
 pub struct RenderPipeline {
     pub pipeline: wgpu::RenderPipeline,
     pub bind_group_layout: wgpu::BindGroupLayout,
     pub bind_group: Option<BindGroup>,
     pub input_texture_view: TextureView,
     pub input_texture: Texture,
-    pub acc_frame_buffer: Buffer,
+    pub display_buffer: Buffer,
+    display_upload: UniformUpload<DisplayUniform>,
+    /// Format of the surface the pipeline renders to
+    format: TextureFormat,
 }
 
 impl RenderPipeline {
@@ -18,12 +66,6 @@ impl RenderPipeline {
         input_texture_view: TextureView,
         input_texture: Texture,
     ) -> RenderPipeline {
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Shader"),
-            source: wgpu::ShaderSource::Wgsl(
-                include_str!("../../assets/shaders/shader.wgsl").into(),
-            ),
-        });
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: Some("Render bind groups"),
             entries: &[
@@ -49,75 +91,67 @@ impl RenderPipeline {
 
                     count: None,
                 },
+                // pixel statistics of the compute pipeline, for the sample heatmap
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
             ],
         });
-        let render_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("Render Pipeline Layout"),
-                bind_group_layouts: &[&bind_group_layout],
-                push_constant_ranges: &[],
-            });
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main", // 1.
-                buffers: &[],           // 2.
-            },
-            fragment: Some(wgpu::FragmentState {
-                // 3.
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    // 4.
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList, // 1.
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw, // 2.
-                cull_mode: None,
-                // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
-                polygon_mode: wgpu::PolygonMode::Fill,
-                // Requires Features::DEPTH_CLIP_CONTROL
-                unclipped_depth: false,
-                // Requires Features::CONSERVATIVE_RASTERIZATION
-                conservative: false,
-            },
-            depth_stencil: None, // 1.
-            multisample: wgpu::MultisampleState {
-                count: 1,                         // 2.
-                mask: !0,                         // 3.
-                alpha_to_coverage_enabled: false, // 4.
-            },
-            multiview: None, // 5.
-        });
-        let acc_frame_buffer = device.create_buffer(&BufferDescriptor {
-            label: "acc frame buffer".into(),
-            size: std::mem::size_of::<usize>() as u64,
+        let pipeline = create_pipeline(
+            device,
+            &bind_group_layout,
+            config.format,
+            include_str!("../../assets/shaders/shader.wgsl"),
+        );
+        let display_buffer = device.create_buffer(&BufferDescriptor {
+            label: "display buffer".into(),
+            size: std::mem::size_of::<DisplayUniform>() as u64,
             usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
         RenderPipeline {
             pipeline,
             bind_group_layout,
+            format: config.format,
             input_texture_view,
-            acc_frame_buffer,
+            display_buffer,
             input_texture,
             bind_group: None,
+            display_upload: UniformUpload::default(),
         }
     }
+    /// Recompiles the pipeline from `source`.
+    /// On failure the current pipeline is kept and the error is returned
+    pub fn reload(&mut self, device: &Device, source: &str) -> Result<(), String> {
+        self.pipeline = hot_reload::catch_validation(device, || {
+            create_pipeline(device, &self.bind_group_layout, self.format, source)
+        })?;
+        Ok(())
+    }
     pub fn set_input_texture(&mut self, texture: Texture) {
         self.input_texture_view = texture.create_view(&TextureViewDescriptor::default());
         self.input_texture = texture;
+        self.bind_group = None;
     }
 
-   
-    pub fn prepare_bind_group(&mut self, device: &Device) {
+    /// Writes the display settings to the gpu if they changed
+    pub fn upload(&mut self, queue: &Queue, display: DisplayUniform) {
+        self.display_upload
+            .write(queue, &self.display_buffer, display);
+    }
+    /// Creates the bind group if it got invalidated.
+    /// `pixels` have to be the ones created together with the input texture
+    pub fn prepare_bind_group(&mut self, device: &Device, pixels: &PixelResources) {
+        if self.bind_group.is_some() {
+            return;
+        }
         self.bind_group = Some(device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("Render bind group"),
             layout: &self.bind_group_layout,
@@ -129,10 +163,72 @@ impl RenderPipeline {
                 wgpu::BindGroupEntry {
                     binding: 1,
                     resource: wgpu::BindingResource::Buffer(
-                        self.acc_frame_buffer.as_entire_buffer_binding(),
+                        self.display_buffer.as_entire_buffer_binding(),
+                    ),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Buffer(
+                        pixels.pixel_stats.as_entire_buffer_binding(),
                     ),
                 },
             ],
         }));
     }
 }
+
+fn create_pipeline(
+    device: &Device,
+    bind_group_layout: &wgpu::BindGroupLayout,
+    format: TextureFormat,
+    source: &str,
+) -> wgpu::RenderPipeline {
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Shader"),
+        source: wgpu::ShaderSource::Wgsl(source.into()),
+    });
+    let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Render Pipeline Layout"),
+        bind_group_layouts: &[bind_group_layout],
+        push_constant_ranges: &[],
+    });
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Render Pipeline"),
+        layout: Some(&render_pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: "vs_main", // 1.
+            buffers: &[],           // 2.
+        },
+        fragment: Some(wgpu::FragmentState {
+            // 3.
+            module: &shader,
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                // 4.
+                format,
+                blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList, // 1.
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw, // 2.
+            cull_mode: None,
+            // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
+            polygon_mode: wgpu::PolygonMode::Fill,
+            // Requires Features::DEPTH_CLIP_CONTROL
+            unclipped_depth: false,
+            // Requires Features::CONSERVATIVE_RASTERIZATION
+            conservative: false,
+        },
+        depth_stencil: None, // 1.
+        multisample: wgpu::MultisampleState {
+            count: 1,                         // 2.
+            mask: !0,                         // 3.
+            alpha_to_coverage_enabled: false, // 4.
+        },
+        multiview: None, // 5.
+    })
+}
@@ -0,0 +1,77 @@
+//! Keeps the gpu buffers in sync with their cpu side counterparts.
+//!
+//! Instead of staging every buffer each frame, only the data that changed since the last upload
+//! is written using [`Queue::write_buffer`].
+use std::ops::Range;
+
+use bytemuck::Pod;
+use wgpu::{Buffer, BufferAddress, Queue};
+
+/// Range of elements in a slice which changed since the last upload.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DirtyRange(Option<Range<usize>>);
+
+impl DirtyRange {
+    /// Creates a range where the first `len` elements are marked as changed
+    pub fn all(len: usize) -> Self {
+        let mut range = DirtyRange::default();
+        range.mark_all(len);
+        range
+    }
+    /// Marks a single element as changed
+    pub fn mark(&mut self, index: usize) {
+        self.extend(index..index + 1);
+    }
+    /// Marks the first `len` elements as changed
+    pub fn mark_all(&mut self, len: usize) {
+        self.extend(0..len);
+    }
+    /// Returns the changed range and resets the tracking
+    pub fn take(&mut self) -> Option<Range<usize>> {
+        self.0.take()
+    }
+    fn extend(&mut self, range: Range<usize>) {
+        if range.is_empty() {
+            return;
+        }
+        self.0 = Some(match self.0.take() {
+            Some(old) => old.start.min(range.start)..old.end.max(range.end),
+            None => range,
+        });
+    }
+}
+
+/// A single value mirrored in a gpu buffer.
+/// The value is only written if it differs from the last uploaded one.
+pub struct UniformUpload<T> {
+    last: Option<T>,
+}
+
+impl<T: Pod> Default for UniformUpload<T> {
+    fn default() -> Self {
+        Self { last: None }
+    }
+}
+
+impl<T: Pod> UniformUpload<T> {
+    /// Writes `value` to the start of `buffer` if it changed since the last call.
+    /// Returns true if the buffer was written
+    pub fn write(&mut self, queue: &Queue, buffer: &Buffer, value: T) -> bool {
+        let unchanged = self
+            .last
+            .as_ref()
+            .is_some_and(|last| bytemuck::bytes_of(last) == bytemuck::bytes_of(&value));
+        if unchanged {
+            return false;
+        }
+        queue.write_buffer(buffer, 0, bytemuck::bytes_of(&value));
+        self.last = Some(value);
+        true
+    }
+}
+
+/// Writes the elements in `range` of `items` to the same location in `buffer`
+pub fn write_slice_range<T: Pod>(queue: &Queue, buffer: &Buffer, items: &[T], range: Range<usize>) {
+    let offset = (range.start * std::mem::size_of::<T>()) as BufferAddress;
+    queue.write_buffer(buffer, offset, bytemuck::cast_slice(&items[range]));
+}
@@ -0,0 +1,154 @@
+//! Cpu mirror of the samplers in `compute.wgsl`.
+//!
+//! Every function produces exactly the same bits as its shader counterpart,
+//! so random sequences used on the gpu can be reproduced on the cpu.
+use glam::{UVec2, Vec2};
+
+use crate::math::pcg_hash;
+
+/// Which sequence is used to draw the random numbers of a path
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(u32)]
+pub enum SamplerKind {
+    /// Independent pcg hashes, decorrelated per pixel and sample
+    Pcg = 0,
+    /// Sobol sequence with Owen scrambling, shuffled per pixel and dimension
+    #[default]
+    Sobol = 1,
+    /// Rank-1 lattice (R2 sequence) with blue noise like offsets over the pixel grid
+    Rank1 = 2,
+}
+
+impl SamplerKind {
+    pub const ALL: [SamplerKind; 3] = [SamplerKind::Pcg, SamplerKind::Sobol, SamplerKind::Rank1];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SamplerKind::Pcg => "independent (pcg)",
+            SamplerKind::Sobol => "sobol (owen scrambled)",
+            SamplerKind::Rank1 => "rank-1 (blue noise)",
+        }
+    }
+    /// Converts the value stored in `Globals::sampler_kind`.
+    /// Unknown values fall back to pcg, like in the shader
+    pub fn from_u32(kind: u32) -> SamplerKind {
+        match kind {
+            1 => SamplerKind::Sobol,
+            2 => SamplerKind::Rank1,
+            _ => SamplerKind::Pcg,
+        }
+    }
+}
+
+/// Generators of the R2 sequence in 0.32 fixed point
+const R2_X: u32 = 3242174889;
+const R2_Y: u32 = 2447445414;
+
+/// Random number stream of a single sample of a pixel
+#[derive(Clone, Debug)]
+pub struct Sampler {
+    kind: SamplerKind,
+    seed: u32,
+    pixel: UVec2,
+    pixel_seed: u32,
+    index: u32,
+    dimension: u32,
+    pcg_state: u32,
+}
+
+impl Sampler {
+    /// Starts the stream of sample `index` of `pixel`. Mirrors `sampler_init`
+    pub fn new(kind: SamplerKind, seed: u32, pixel: UVec2, index: u32) -> Sampler {
+        let pixel_seed = pcg_hash(
+            pixel
+                .x
+                .wrapping_add(pcg_hash(pixel.y.wrapping_add(pcg_hash(seed)))),
+        );
+        Sampler {
+            kind,
+            seed,
+            pixel,
+            pixel_seed,
+            index,
+            dimension: 0,
+            pcg_state: pcg_hash(pixel_seed ^ pcg_hash(index)),
+        }
+    }
+    /// Draws the next two dimensions of the sample
+    pub fn next_2d(&mut self) -> Vec2 {
+        let dimension = self.dimension;
+        self.dimension += 1;
+        match self.kind {
+            SamplerKind::Sobol => self.sobol_2d(dimension),
+            SamplerKind::Rank1 => self.rank1_2d(dimension),
+            SamplerKind::Pcg => {
+                let x = u32_to_unit(self.pcg_next());
+                let y = u32_to_unit(self.pcg_next());
+                Vec2::new(x, y)
+            }
+        }
+    }
+    fn pcg_next(&mut self) -> u32 {
+        self.pcg_state = pcg_hash(self.pcg_state);
+        self.pcg_state
+    }
+    fn sobol_2d(&self, dimension: u32) -> Vec2 {
+        let seed = pcg_hash(self.pixel_seed ^ dimension.wrapping_mul(0x9e3779b9));
+        let index = nested_uniform_scramble(self.index, seed);
+        let x = nested_uniform_scramble(index.reverse_bits(), pcg_hash(seed.wrapping_add(1)));
+        let y = nested_uniform_scramble(sobol_dimension1(index), pcg_hash(seed.wrapping_add(2)));
+        Vec2::new(u32_to_unit(x), u32_to_unit(y))
+    }
+    fn rank1_2d(&self, dimension: u32) -> Vec2 {
+        let dither = self
+            .pixel
+            .x
+            .wrapping_mul(R2_X)
+            .wrapping_add(self.pixel.y.wrapping_mul(R2_Y));
+        let shift = pcg_hash(pcg_hash(self.seed) ^ dimension.wrapping_mul(0x9e3779b9));
+        let x = self
+            .index
+            .wrapping_mul(R2_X)
+            .wrapping_add(dither)
+            .wrapping_add(shift);
+        let y = self
+            .index
+            .wrapping_mul(R2_Y)
+            .wrapping_add(dither)
+            .wrapping_add(pcg_hash(shift));
+        Vec2::new(u32_to_unit(x), u32_to_unit(y))
+    }
+}
+
+/// Maps the upper 24 bits to a float in [0, 1)
+pub fn u32_to_unit(x: u32) -> f32 {
+    (x >> 8) as f32 * (1. / 16777216.)
+}
+
+/// Source: Burley, Practical Hash-based Owen Scrambling
+fn laine_karras_permutation(x: u32, seed: u32) -> u32 {
+    let mut x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x
+}
+
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+/// Second dimension of the sobol sequence, the first one is `index.reverse_bits()`
+fn sobol_dimension1(mut index: u32) -> u32 {
+    let mut result = 0;
+    let mut v = 1 << 31;
+    while index != 0 {
+        if index & 1 != 0 {
+            result ^= v;
+        }
+        index >>= 1;
+        v ^= v >> 1;
+    }
+    result
+}
@@ -1,18 +1,106 @@
 use glam::Vec3;
 
 use crate::{
+    animation::Timeline,
     camera::Camera,
+    camera_path::{Bookmark, CameraPath},
     material::Material,
+    material_library::NamedMaterial,
     math::{self, rand_vec},
+    procedural::ProceduralTexture,
+    renderer::upload::DirtyRange,
+    scene_graph::{Instance, NodeKind, SceneGraph},
     sphere::Sphere,
+    texture::ImageTexture,
 };
 pub struct Scene {
+    /// Geometry placed in the world by the nodes of `graph`
     pub spheres: Vec<Sphere>,
-    pub materials: Vec<Material>,
+    pub graph: SceneGraph,
+    /// `graph` flattened for rendering, see [`Scene::update_instances`]
+    pub instances: Vec<Instance>,
+    /// Materials with the name shown in the ui and stored in scene files.
+    /// Only the [`Material`] part is uploaded to the gpu
+    pub materials: Vec<NamedMaterial>,
+    /// Images referred to by `Material::textures`
+    pub textures: Vec<ImageTexture>,
+    /// Procedural textures referred to by `Material::textures`
+    pub procedurals: Vec<ProceduralTexture>,
     pub camera: Camera,
+    /// Named views the camera can jump to
+    pub bookmarks: Vec<Bookmark>,
+    pub camera_paths: Vec<CameraPath>,
+    pub timeline: Timeline,
+    /// Spheres which changed since the last upload to the gpu
+    pub dirty_spheres: DirtyRange,
+    /// Materials which changed since the last upload to the gpu
+    pub dirty_materials: DirtyRange,
+    /// Instances which changed since the last upload to the gpu
+    pub dirty_instances: DirtyRange,
+    /// Textures which changed since the last upload to the gpu
+    pub dirty_textures: DirtyRange,
+    /// Procedural textures which changed since the last upload to the gpu
+    pub dirty_procedurals: DirtyRange,
 }
 
 impl Scene {
+    /// Every sphere is placed once, by a root node without transform.
+    /// The materials are named after their index
+    pub fn new(spheres: Vec<Sphere>, materials: Vec<Material>, camera: Camera) -> Scene {
+        let mut scene = Scene {
+            dirty_spheres: DirtyRange::all(spheres.len()),
+            dirty_materials: DirtyRange::all(materials.len()),
+            dirty_instances: DirtyRange::default(),
+            dirty_textures: DirtyRange::default(),
+            dirty_procedurals: DirtyRange::default(),
+            graph: SceneGraph::flat(spheres.len()),
+            instances: Vec::new(),
+            spheres,
+            materials: materials
+                .into_iter()
+                .enumerate()
+                .map(|(i, material)| NamedMaterial::new(default_material_name(i), material))
+                .collect(),
+            textures: Vec::new(),
+            procedurals: Vec::new(),
+            camera,
+            bookmarks: Vec::new(),
+            camera_paths: Vec::new(),
+            timeline: Timeline::default(),
+        };
+        scene.update_instances();
+        scene
+    }
+    /// Flattens the scene graph again if it changed.
+    /// Returns true if the instances were rebuilt
+    pub fn update_instances(&mut self) -> bool {
+        if !self.graph.take_changed() {
+            return false;
+        }
+        self.instances = self.graph.instances(self.spheres.len());
+        if self.instances.is_empty() {
+            self.instances.push(Instance::NONE);
+        }
+        self.dirty_instances.mark_all(self.instances.len());
+        true
+    }
+    /// World space center of the first node placing the sphere
+    pub fn sphere_world_center(&self, sphere: usize) -> Option<Vec3> {
+        let center = self.spheres.get(sphere)?.center;
+        let instance = self
+            .instances
+            .iter()
+            .find(|instance| instance.sphere_index as usize == sphere)?;
+        Some(instance.object_to_world.transform_point3(center))
+    }
+    /// Name of the first node placing the sphere
+    pub fn sphere_name(&self, sphere: usize) -> Option<&str> {
+        self.graph
+            .nodes()
+            .iter()
+            .find(|node| node.kind == NodeKind::Sphere(sphere))
+            .map(|node| node.name.as_str())
+    }
     /// Generates an example scene.
     /// This is currently the default.
     pub fn example_scene() -> Scene {
@@ -56,20 +144,29 @@ impl Scene {
             Material::new().with_color(palette[5]).with_roughness(0.5),
             Material::new().with_color(palette[6]).with_roughness(0.8),
         ];
-        let camera = Camera::new(100., 0.1, 100., 1., 1.);
+        let mut camera = Camera::new(100., 0.1, 100., 1., 1.);
+        camera.position = Vec3::new(77.7, -7.4, 10.);
 
-        Scene {
-            spheres,
-            materials,
-            camera,
+        let mut scene = Scene::new(spheres, materials, camera);
+        for (i, name) in ["Ground", "Sun", "Light"].into_iter().enumerate() {
+            scene.materials[i].name = name.to_string();
         }
+        scene.bookmarks.push(Bookmark {
+            name: "Start".to_string(),
+            pose: scene.camera.pose(),
+        });
+        scene
     }
-    pub fn material_name(index: usize) -> Option<&'static str> {
-        match index {
-            0 => Some("Ground"),
-            1 => Some("Sun"),
-            2 => Some("Light"),
-            _ => None,
-        }
+    pub fn material_name(&self, index: usize) -> Option<&str> {
+        self.materials.get(index).map(|m| m.name.as_str())
     }
+    /// The materials as uploaded to the gpu, in the order spheres refer to them
+    pub fn gpu_materials(&self) -> Vec<Material> {
+        self.materials.iter().map(|m| m.material).collect()
+    }
+}
+
+/// Name of materials which were not named by the user
+pub fn default_material_name(index: usize) -> String {
+    format!("Material {index}")
 }
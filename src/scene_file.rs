@@ -0,0 +1,541 @@
+//! Plain text scene files.
+//!
+//! Every line describes one item, starting with its kind followed by numbers
+//! separated by whitespace. Names come last and may contain spaces.
+//! Empty lines and lines starting with `#` are ignored.
+//!
+//! ```text
+//! globals <seed> <bounces> <sky color rgb> <sampler kind> <adaptive 0 or 1> <convergence threshold> <min samples> <recheck interval>
+//! camera <position xyz> <forward xyz> <fov> <focus distance>
+//! shutter <open> <close>
+//! aperture <lens radius>
+//! texture <path>
+//! procedural <kind> <object or world> <scale> <octaves> <color a rgb> <color b rgb>
+//! material <metallic> <specular> <roughness> <fog> <albedo rgba> <emission rgb strength> [name]
+//! textures <albedo> <roughness> <metallic> <emission>
+//! transmission <amount> <index of refraction>
+//! sphere <center xyz> <radius> <material index> [velocity xyz]
+//! node <parent index or -1> <translation xyz> <rotation xyz> <scale xyz> <sphere index or -1> <name>
+//! bookmark <position xyz> <forward xyz> <fov> <focus distance> <name>
+//! path <name>
+//! key <time> <position xyz> <forward xyz> <fov> <focus distance>
+//! timeline <fps> <start frame> <end frame> <camera path index or -1>
+//! track <property name>
+//! value <time> <xyzw>
+//! ```
+//! `key` lines belong to the last `path` above them, `value` lines to the last `track`
+//! and `textures` and `transmission` lines to the last `material`. Textures are referred to by the index
+//! among the texture lines, procedural textures by `p` followed by the index among the
+//! procedural lines, and -1 stands for none. Both have to come before the materials using
+//! them. Texture paths are relative to the scene file.
+//! Kinds of procedural textures are written by [`ProceduralKind::name`].
+//! Nodes refer to their parent by the index among the node lines, so parents come first.
+//! Rotations are euler angles in degrees. Without node lines every sphere gets a root node.
+//! Property names are written by [`Property::name`].
+//! The `globals` line holds the settings of [`Globals`] which change the rendered image,
+//! sampler kinds are stored as their value in `Globals::sampler_kind`.
+//! Settings of the interactive viewport, like temporal accumulation, are not stored.
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use glam::{Vec3, Vec4};
+use image::Rgba;
+
+use crate::{
+    animation::{Property, Timeline, Track},
+    camera::Camera,
+    camera_path::{Bookmark, CameraPath, CameraPose, Keyframe},
+    globals::Globals,
+    material::Material,
+    material_library::NamedMaterial,
+    procedural::{ProceduralKind, ProceduralTexture, TextureSpace},
+    scene::{default_material_name, Scene},
+    scene_graph::{Node, NodeKind, SceneGraph, Transform},
+    sphere::Sphere,
+    texture::{ImageTexture, TextureRef, NO_TEXTURE},
+};
+
+/// File a scene is saved to, if no other path is given
+pub const DEFAULT_PATH: &str = "scene.rays";
+
+impl Scene {
+    /// Loads a scene file. The settings it stores are written to `globals`, see [`parse`]
+    pub fn load(path: impl AsRef<Path>, globals: &mut Globals) -> Result<Scene, String> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("could not read {}: {e}", path.display()))?;
+        let directory = path.parent().map_or_else(PathBuf::new, Path::to_path_buf);
+        parse(&text, &directory, globals).map_err(|e| format!("{}: {e}", path.display()))
+    }
+    pub fn save(&self, globals: &Globals, path: impl AsRef<Path>) -> Result<(), String> {
+        let path = path.as_ref();
+        std::fs::write(path, write(self, globals))
+            .map_err(|e| format!("could not write {}: {e}", path.display()))
+    }
+}
+
+/// Parses the scene file format described in the module documentation.
+/// Textures are loaded relative to `directory`.
+/// The settings of a `globals` line are written to `globals` if the whole file parses,
+/// its other fields are kept
+pub fn parse(text: &str, directory: &Path, globals: &mut Globals) -> Result<Scene, String> {
+    let mut parsed_globals = *globals;
+    let mut camera = Camera::new(100., 0.1, 100., 1., 1.);
+    let mut spheres = Vec::new();
+    let mut materials: Vec<(Material, String)> = Vec::new();
+    let mut textures = Vec::new();
+    let mut procedurals = Vec::new();
+    let mut bookmarks = Vec::new();
+    let mut camera_paths: Vec<CameraPath> = Vec::new();
+    let mut timeline = Timeline::default();
+    let mut graph = SceneGraph::default();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let error = |message: &str| format!("line {}: {message}", number + 1);
+        let (kind, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        match kind {
+            "globals" => parse_globals(rest, &mut parsed_globals).map_err(|e| error(&e))?,
+            "camera" => {
+                let (pose, _) = pose(rest).map_err(|e| error(&e))?;
+                camera.set_pose(pose);
+            }
+            "shutter" => {
+                let (shutter, _) = floats(rest).map_err(|e| error(&e))?;
+                camera.shutter = shutter;
+            }
+            "aperture" => {
+                let ([aperture], _) = floats(rest).map_err(|e| error(&e))?;
+                if aperture < 0. {
+                    return Err(error("the aperture must not be negative"));
+                }
+                camera.aperture = aperture;
+            }
+            "texture" => {
+                textures.push(ImageTexture::load(rest.trim(), directory).map_err(|e| error(&e))?);
+            }
+            "procedural" => {
+                procedurals.push(parse_procedural(rest).map_err(|e| error(&e))?);
+            }
+            "material" => {
+                let (material, name) = parse_material(rest).map_err(|e| error(&e))?;
+                let name = match name.trim() {
+                    "" => default_material_name(materials.len()),
+                    name => name.to_string(),
+                };
+                materials.push((material, name));
+            }
+            "textures" => {
+                let (material, _) = materials
+                    .last_mut()
+                    .ok_or_else(|| error("textures without a material"))?;
+                let words: Vec<&str> = rest.split_whitespace().collect();
+                if words.len() != material.textures.len() {
+                    return Err(error("expected a texture for every channel"));
+                }
+                for (slot, word) in material.textures.iter_mut().zip(words) {
+                    *slot = texture_slot(word, textures.len(), procedurals.len())
+                        .map_err(|e| error(&e))?;
+                }
+            }
+            "transmission" => {
+                let (material, _) = materials
+                    .last_mut()
+                    .ok_or_else(|| error("transmission without a material"))?;
+                parse_transmission(rest, material).map_err(|e| error(&e))?;
+            }
+            "sphere" => {
+                let ([x, y, z, radius, material], rest) = floats(rest).map_err(|e| error(&e))?;
+                if material < 0. || material.fract() != 0. {
+                    return Err(error("material index has to be a positive integer"));
+                }
+                let mut sphere = Sphere::new(Vec3::new(x, y, z), radius, material as usize);
+                if !rest.trim().is_empty() {
+                    let (velocity, _) = floats(rest).map_err(|e| error(&e))?;
+                    sphere.velocity = Vec3::from_array(velocity);
+                }
+                spheres.push(sphere);
+            }
+            "node" => {
+                let ([parent, tx, ty, tz, rx, ry, rz, sx, sy, sz, sphere], name) =
+                    floats(rest).map_err(|e| error(&e))?;
+                let index = |value: f32, what: &str| match value {
+                    -1. => Ok(None),
+                    _ if value >= 0. && value.fract() == 0. => Ok(Some(value as usize)),
+                    _ => Err(error(&format!("{what} has to be a positive integer or -1"))),
+                };
+                let parent = index(parent, "parent index")?;
+                if parent.is_some_and(|p| p >= graph.nodes().len()) {
+                    return Err(error("the parent has to be defined before the node"));
+                }
+                let kind = match index(sphere, "sphere index")? {
+                    Some(i) => NodeKind::Sphere(i),
+                    None => NodeKind::Group,
+                };
+                let mut node = Node::new(name.trim(), kind).with_transform(Transform {
+                    translation: Vec3::new(tx, ty, tz),
+                    rotation: Vec3::new(rx, ry, rz),
+                    scale: Vec3::new(sx, sy, sz),
+                });
+                node.parent = parent;
+                graph.add(node);
+            }
+            "bookmark" => {
+                let (pose, name) = pose(rest).map_err(|e| error(&e))?;
+                bookmarks.push(Bookmark {
+                    name: name.trim().to_string(),
+                    pose,
+                });
+            }
+            "path" => camera_paths.push(CameraPath::new(rest.trim())),
+            "key" => {
+                let ([time], rest) = floats(rest).map_err(|e| error(&e))?;
+                let (pose, _) = pose(rest).map_err(|e| error(&e))?;
+                let path = camera_paths
+                    .last_mut()
+                    .ok_or_else(|| error("key without a path"))?;
+                path.insert(Keyframe { time, pose });
+            }
+            "timeline" => {
+                let ([fps, start, end, path], _) = floats(rest).map_err(|e| error(&e))?;
+                if fps <= 0. || start < 0. || end < start {
+                    return Err(error("invalid timeline"));
+                }
+                if path < -1. || path.fract() != 0. {
+                    return Err(error(&format!("invalid camera path index {path}")));
+                }
+                timeline.fps = fps;
+                timeline.start_frame = start as u32;
+                timeline.end_frame = end as u32;
+                timeline.camera_path = (path >= 0.).then_some(path as usize);
+            }
+            "track" => {
+                let property = Property::parse(rest)
+                    .ok_or_else(|| error(&format!("unknown property {:?}", rest.trim())))?;
+                timeline.tracks.push(Track::new(property));
+            }
+            "value" => {
+                let ([time, x, y, z, w], _) = floats(rest).map_err(|e| error(&e))?;
+                let track = timeline
+                    .tracks
+                    .last_mut()
+                    .ok_or_else(|| error("value without a track"))?;
+                track.insert(time, Vec4::new(x, y, z, w));
+            }
+            _ => return Err(error(&format!("unknown kind {kind:?}"))),
+        }
+    }
+    if let Some(sphere) = spheres
+        .iter()
+        .find(|s| s.material_index as usize >= materials.len())
+    {
+        return Err(format!(
+            "sphere uses material {}, but there are only {}",
+            sphere.material_index,
+            materials.len()
+        ));
+    }
+    if let Some(path) = timeline.camera_path.filter(|&i| i >= camera_paths.len()) {
+        return Err(format!(
+            "the timeline follows camera path {path}, but there are only {}",
+            camera_paths.len()
+        ));
+    }
+    if let Some(node) = graph.nodes().iter().find(|node| match node.kind {
+        NodeKind::Sphere(i) => i >= spheres.len(),
+        NodeKind::Group => false,
+    }) {
+        return Err(format!(
+            "node {:?} places a sphere which does not exist",
+            node.name
+        ));
+    }
+    let mut scene = Scene::new(spheres, Vec::new(), camera);
+    for (material, name) in materials {
+        scene.add_material(name, material);
+    }
+    scene.textures = textures;
+    scene.dirty_textures.mark_all(scene.textures.len());
+    scene.procedurals = procedurals;
+    scene.dirty_procedurals.mark_all(scene.procedurals.len());
+    if !graph.nodes().is_empty() {
+        scene.graph = graph;
+        scene.update_instances();
+    }
+    scene.bookmarks = bookmarks;
+    scene.camera_paths = camera_paths;
+    scene.timeline = timeline;
+    *globals = parsed_globals;
+    Ok(scene)
+}
+
+/// Writes the scene and the settings of `globals` in the format read by [`parse`]
+pub fn write(scene: &Scene, globals: &Globals) -> String {
+    let mut out = String::from("# rays scene\n");
+    let [r, g, b, _] = globals.sky_color;
+    writeln!(
+        out,
+        "globals {} {} {r} {g} {b} {} {} {} {} {}",
+        globals.seed,
+        globals.bounces,
+        globals.sampler_kind,
+        globals.adaptive_sampling,
+        globals.convergence_threshold,
+        globals.min_samples,
+        globals.recheck_interval
+    )
+    .unwrap();
+    let pose = |p: &CameraPose| {
+        let (position, forward) = (p.position, p.forward);
+        format!(
+            "{} {} {} {} {} {} {} {}",
+            position.x, position.y, position.z, forward.x, forward.y, forward.z, p.fov, p.focus
+        )
+    };
+    writeln!(out, "camera {}", pose(&scene.camera.pose())).unwrap();
+    let [open, close] = scene.camera.shutter;
+    writeln!(out, "shutter {open} {close}").unwrap();
+    writeln!(out, "aperture {}", scene.camera.aperture).unwrap();
+    for texture in &scene.textures {
+        writeln!(out, "texture {}", texture.path).unwrap();
+    }
+    for p in &scene.procedurals {
+        let [ar, ag, ab, _] = p.color_a;
+        let [br, bg, bb, _] = p.color_b;
+        writeln!(
+            out,
+            "procedural {} {} {} {} {ar} {ag} {ab} {br} {bg} {bb}",
+            p.kind().name(),
+            p.space().name(),
+            p.scale,
+            p.octaves
+        )
+        .unwrap();
+    }
+    for NamedMaterial { name, material } in &scene.materials {
+        write_material(&mut out, material, name);
+        if material.textures != [NO_TEXTURE; 4] {
+            let [albedo, roughness, metallic, emission] =
+                material
+                    .textures
+                    .map(|slot| match TextureRef::from_slot(slot) {
+                        None => "-1".to_string(),
+                        Some(TextureRef::Image(i)) => i.to_string(),
+                        Some(TextureRef::Procedural(i)) => format!("p{i}"),
+                    });
+            writeln!(out, "textures {albedo} {roughness} {metallic} {emission}").unwrap();
+        }
+    }
+    for s in &scene.spheres {
+        let c = s.center;
+        write!(
+            out,
+            "sphere {} {} {} {} {}",
+            c.x, c.y, c.z, s.radius, s.material_index
+        )
+        .unwrap();
+        let v = s.velocity;
+        if v != Vec3::ZERO {
+            write!(out, " {} {} {}", v.x, v.y, v.z).unwrap();
+        }
+        out.push('\n');
+    }
+    for node in scene.graph.nodes() {
+        let Transform {
+            translation: t,
+            rotation: r,
+            scale: s,
+        } = node.transform;
+        let parent = node.parent.map_or(-1, |i| i as i64);
+        let sphere = match node.kind {
+            NodeKind::Sphere(i) => i as i64,
+            NodeKind::Group => -1,
+        };
+        writeln!(
+            out,
+            "node {parent} {} {} {} {} {} {} {} {} {} {sphere} {}",
+            t.x, t.y, t.z, r.x, r.y, r.z, s.x, s.y, s.z, node.name
+        )
+        .unwrap();
+    }
+    for bookmark in &scene.bookmarks {
+        writeln!(out, "bookmark {} {}", pose(&bookmark.pose), bookmark.name).unwrap();
+    }
+    for path in &scene.camera_paths {
+        writeln!(out, "path {}", path.name).unwrap();
+        for key in path.keyframes() {
+            writeln!(out, "key {} {}", key.time, pose(&key.pose)).unwrap();
+        }
+    }
+    let timeline = &scene.timeline;
+    if !timeline.is_empty() {
+        let path = timeline.camera_path.map_or(-1, |i| i as i64);
+        writeln!(
+            out,
+            "timeline {} {} {} {path}",
+            timeline.fps, timeline.start_frame, timeline.end_frame
+        )
+        .unwrap();
+        for track in &timeline.tracks {
+            writeln!(out, "track {}", track.property.name()).unwrap();
+            for (time, v) in track.keys() {
+                writeln!(out, "value {time} {} {} {} {}", v.x, v.y, v.z, v.w).unwrap();
+            }
+        }
+    }
+    out
+}
+
+/// Parses the numbers of a `material` line and returns the rest of the line, the name
+pub(crate) fn parse_material(text: &str) -> Result<(Material, &str), String> {
+    let ([metallic, specular_intensity, roughness, fog, r, g, b, a], rest) = floats(text)?;
+    let (emission, rest) = floats(rest)?;
+    let material = Material {
+        metallic,
+        specular_intensity,
+        roughness,
+        fog,
+        albedo: Rgba([r, g, b, a]),
+        emission,
+        ..Material::new()
+    };
+    Ok((material, rest))
+}
+
+/// Parses the numbers of a `transmission` line into the material
+pub(crate) fn parse_transmission(text: &str, material: &mut Material) -> Result<(), String> {
+    let ([transmission, ior], _) = floats(text)?;
+    if ior <= 0. {
+        return Err("the index of refraction has to be positive".to_string());
+    }
+    material.transmission = transmission;
+    material.ior = ior;
+    Ok(())
+}
+
+/// Appends the `material` line read by [`parse_material`],
+/// followed by a `transmission` line if the material lets light through
+pub(crate) fn write_material(out: &mut String, m: &Material, name: &str) {
+    let [r, g, b, a] = m.albedo.0;
+    let [er, eg, eb, strength] = m.emission;
+    writeln!(
+        out,
+        "material {} {} {} {} {r} {g} {b} {a} {er} {eg} {eb} {strength} {name}",
+        m.metallic, m.specular_intensity, m.roughness, m.fog
+    )
+    .unwrap();
+    let default = Material::new();
+    if m.transmission != default.transmission || m.ior != default.ior {
+        writeln!(out, "transmission {} {}", m.transmission, m.ior).unwrap();
+    }
+}
+
+fn parse_globals(text: &str, globals: &mut Globals) -> Result<(), String> {
+    // seeds use all 32 bits, more than a float holds exactly
+    let (seed, rest) = word(text);
+    let seed = seed
+        .parse()
+        .map_err(|_| format!("expected a seed, found {seed:?}"))?;
+    let ([bounces, r, g, b, sampler_kind, adaptive, threshold, min_samples, recheck], _) =
+        floats(rest)?;
+    let integer = |value: f32, what: &str| {
+        if value >= 0. && value.fract() == 0. {
+            Ok(value as u32)
+        } else {
+            Err(format!("{what} has to be a positive integer"))
+        }
+    };
+    *globals = Globals {
+        seed,
+        bounces: integer(bounces, "bounces")?,
+        sky_color: [r, g, b, 1.],
+        sampler_kind: integer(sampler_kind, "sampler kind")?,
+        adaptive_sampling: integer(adaptive, "adaptive sampling")?,
+        convergence_threshold: threshold,
+        min_samples: integer(min_samples, "min samples")?,
+        recheck_interval: integer(recheck, "recheck interval")?,
+        ..*globals
+    };
+    Ok(())
+}
+
+fn parse_procedural(text: &str) -> Result<ProceduralTexture, String> {
+    let (kind, rest) = word(text);
+    let kind = ProceduralKind::parse(kind)
+        .ok_or_else(|| format!("unknown procedural texture {kind:?}"))?;
+    let (space, rest) = word(rest);
+    let space =
+        TextureSpace::parse(space).ok_or_else(|| format!("unknown texture space {space:?}"))?;
+    let ([scale, octaves, ar, ag, ab, br, bg, bb], _) = floats(rest)?;
+    if octaves < 0. || octaves.fract() != 0. {
+        return Err("octaves have to be a positive integer".to_string());
+    }
+    let mut procedural = ProceduralTexture::new(kind)
+        .with_space(space)
+        .with_scale(scale)
+        .with_colors([ar, ag, ab], [br, bg, bb]);
+    procedural.octaves = octaves as u32;
+    Ok(procedural)
+}
+
+/// Parses a texture slot of a `textures` line, checking that the texture exists
+fn texture_slot(word: &str, images: usize, procedurals: usize) -> Result<u32, String> {
+    if word == "-1" {
+        return Ok(NO_TEXTURE);
+    }
+    let (texture, count) = match word.strip_prefix('p') {
+        Some(index) => (index.parse().map(TextureRef::Procedural), procedurals),
+        None => (word.parse().map(TextureRef::Image), images),
+    };
+    match texture {
+        Ok(TextureRef::Image(i) | TextureRef::Procedural(i)) if i >= count => {
+            Err(format!("texture {word} does not exist"))
+        }
+        Ok(texture) => Ok(texture.to_slot()),
+        Err(_) => Err(format!("expected a texture, found {word:?}")),
+    }
+}
+
+/// Splits off the first whitespace separated word
+fn word(text: &str) -> (&str, &str) {
+    let trimmed = text.trim_start();
+    trimmed.split_at(trimmed.find(char::is_whitespace).unwrap_or(trimmed.len()))
+}
+
+/// Parses `N` whitespace separated numbers and returns the rest of the line
+fn floats<const N: usize>(text: &str) -> Result<([f32; N], &str), String> {
+    let mut values = [0.; N];
+    let mut rest = text;
+    for value in &mut values {
+        let trimmed = rest.trim_start();
+        let end = trimmed.find(char::is_whitespace).unwrap_or(trimmed.len());
+        let (number, tail) = trimmed.split_at(end);
+        *value = number
+            .parse()
+            .map_err(|_| format!("expected a number, found {number:?}"))?;
+        rest = tail;
+    }
+    Ok((values, rest))
+}
+
+fn pose(text: &str) -> Result<(CameraPose, &str), String> {
+    let ([px, py, pz, fx, fy, fz, fov, focus], rest) = floats(text)?;
+    let forward = Vec3::new(fx, fy, fz);
+    if forward.length_squared() == 0. {
+        return Err("the forward direction must not be zero".to_string());
+    }
+    if focus <= 0. {
+        return Err("the focus distance must be positive".to_string());
+    }
+    let pose = CameraPose {
+        position: Vec3::new(px, py, pz),
+        forward: forward.normalize(),
+        fov,
+        focus,
+    };
+    Ok((pose, rest))
+}
@@ -0,0 +1,216 @@
+//! Hierarchy of named nodes which place the spheres in the world.
+//!
+//! Every node has a transform relative to its parent and either groups other nodes
+//! or places one of `Scene::spheres`. Several nodes can place the same sphere,
+//! so the spheres are shared geometry which gets instanced.
+//! For rendering the tree is flattened into one [`Instance`] per sphere node.
+use glam::{EulerRot, Mat4, Quat, Vec3};
+
+use crate::layout::shader_layout;
+
+/// Affine transform relative to the parent node
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: Vec3,
+    /// Euler angles in degrees, applied in x, y, z order
+    pub rotation: Vec3,
+    /// Can differ per axis, which turns spheres into ellipsoids
+    pub scale: Vec3,
+}
+
+/// Smallest scale the editor allows, a scale of 0 flattens the sphere and can not be inverted
+pub const MIN_SCALE: f32 = 0.001;
+
+impl Transform {
+    pub const IDENTITY: Transform = Transform {
+        translation: Vec3::ZERO,
+        rotation: Vec3::ZERO,
+        scale: Vec3::ONE,
+    };
+
+    pub fn matrix(&self) -> Mat4 {
+        let [x, y, z] = self.rotation.to_array().map(f32::to_radians);
+        let rotation = Quat::from_euler(EulerRot::XYZ, x, y, z);
+        Mat4::from_scale_rotation_translation(self.scale, rotation, self.translation)
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform::IDENTITY
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NodeKind {
+    Group,
+    /// Index into `Scene::spheres`
+    Sphere(usize),
+}
+
+#[derive(Clone, Debug)]
+pub struct Node {
+    pub name: String,
+    /// Index of the parent node, which always comes before its children
+    pub parent: Option<usize>,
+    pub transform: Transform,
+    pub kind: NodeKind,
+}
+
+impl Node {
+    pub fn new(name: impl Into<String>, kind: NodeKind) -> Self {
+        Self {
+            name: name.into(),
+            parent: None,
+            transform: Transform::IDENTITY,
+            kind,
+        }
+    }
+    pub fn with_parent(mut self, parent: usize) -> Self {
+        self.parent = Some(parent);
+        self
+    }
+    pub fn with_transform(mut self, transform: Transform) -> Self {
+        self.transform = transform;
+        self
+    }
+}
+
+/// The nodes of a scene, sorted so that parents come before their children
+#[derive(Clone, Debug, Default)]
+pub struct SceneGraph {
+    nodes: Vec<Node>,
+    /// The instances have to be rebuilt
+    changed: bool,
+}
+
+impl SceneGraph {
+    /// One root node for every sphere, used by scenes without a hierarchy
+    pub fn flat(sphere_count: usize) -> Self {
+        let mut graph = SceneGraph::default();
+        for i in 0..sphere_count {
+            graph.add(Node::new(format!("Sphere {i}"), NodeKind::Sphere(i)));
+        }
+        graph
+    }
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+    /// Marks the graph as changed, since the node is most likely edited
+    pub fn node_mut(&mut self, index: usize) -> &mut Node {
+        self.changed = true;
+        &mut self.nodes[index]
+    }
+    /// Appends a node and returns its index.
+    /// Panics if the parent does not exist yet
+    pub fn add(&mut self, node: Node) -> usize {
+        if let Some(parent) = node.parent {
+            assert!(parent < self.nodes.len(), "parent {parent} does not exist");
+        }
+        self.nodes.push(node);
+        self.changed = true;
+        self.nodes.len() - 1
+    }
+    /// Removes the node together with all of its descendants
+    pub fn remove(&mut self, index: usize) {
+        // descendants always come after their ancestors, so a single pass finds them all
+        let mut removed = vec![false; self.nodes.len()];
+        for i in index..self.nodes.len() {
+            removed[i] = i == index || self.nodes[i].parent.is_some_and(|p| removed[p]);
+        }
+        let mut new_index = Vec::with_capacity(self.nodes.len());
+        let mut kept = 0;
+        for &removed in &removed {
+            new_index.push(kept);
+            kept += !removed as usize;
+        }
+        let mut i = 0;
+        self.nodes.retain(|_| {
+            i += 1;
+            !removed[i - 1]
+        });
+        for node in &mut self.nodes {
+            node.parent = node.parent.map(|p| new_index[p]);
+        }
+        self.changed = true;
+    }
+    /// Indices of the children of `parent`, or of the root nodes if None
+    pub fn children(&self, parent: Option<usize>) -> impl Iterator<Item = usize> + '_ {
+        (0..self.nodes.len()).filter(move |&i| self.nodes[i].parent == parent)
+    }
+    /// Object to world matrix of every node
+    pub fn world_matrices(&self) -> Vec<Mat4> {
+        let mut matrices: Vec<Mat4> = Vec::with_capacity(self.nodes.len());
+        for node in &self.nodes {
+            let local = node.transform.matrix();
+            matrices.push(match node.parent {
+                Some(parent) => matrices[parent] * local,
+                None => local,
+            });
+        }
+        matrices
+    }
+    /// One instance for every node placing an existing sphere.
+    /// Nodes scaled to 0 along an axis are skipped, since their transform can not be inverted
+    pub fn instances(&self, sphere_count: usize) -> Vec<Instance> {
+        self.nodes
+            .iter()
+            .zip(self.world_matrices())
+            .filter_map(|(node, matrix)| match node.kind {
+                NodeKind::Sphere(i) if i < sphere_count => Instance::new(matrix, i),
+                _ => None,
+            })
+            .collect()
+    }
+    /// Returns true once after the graph changed
+    pub fn take_changed(&mut self) -> bool {
+        std::mem::take(&mut self.changed)
+    }
+    pub fn mark_changed(&mut self) {
+        self.changed = true;
+    }
+}
+
+/// A sphere placed in the world, uploaded to the gpu
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Instance {
+    pub object_to_world: Mat4,
+    pub world_to_object: Mat4,
+    /// Index into `Scene::spheres`
+    pub sphere_index: u32,
+    pub _offset: [u32; 3],
+}
+
+impl Instance {
+    /// Stands in for an empty scene, since buffers can not be empty.
+    /// The sphere does not exist, so the shader skips it
+    pub const NONE: Instance = Instance {
+        object_to_world: Mat4::IDENTITY,
+        world_to_object: Mat4::IDENTITY,
+        sphere_index: u32::MAX,
+        _offset: [0; 3],
+    };
+
+    /// None if `object_to_world` can not be inverted
+    pub fn new(object_to_world: Mat4, sphere_index: usize) -> Option<Self> {
+        let world_to_object = object_to_world.inverse();
+        if object_to_world.determinant() == 0. || !world_to_object.is_finite() {
+            return None;
+        }
+        Some(Self {
+            object_to_world,
+            world_to_object,
+            sphere_index: sphere_index as u32,
+            _offset: [0; 3],
+        })
+    }
+}
+unsafe impl bytemuck::Pod for Instance {}
+unsafe impl bytemuck::Zeroable for Instance {}
+
+shader_layout!(Instance => "Instance" {
+    object_to_world,
+    world_to_object,
+    sphere_index,
+});
@@ -0,0 +1,230 @@
+//! Small canonical scenes, used by the golden image tests and the benchmark.
+//!
+//! Every scene comes with the [`Globals`] it is meant to be rendered with,
+//! since the sky color is part of them.
+use glam::Vec3;
+use image::{DynamicImage, Rgba, RgbaImage};
+
+use crate::{
+    camera::Camera,
+    globals::Globals,
+    material::Material,
+    procedural::{ProceduralKind, ProceduralTexture, TextureSpace},
+    scene::Scene,
+    sphere::Sphere,
+    texture::{ImageTexture, TextureChannel, TextureRef},
+};
+
+/// Creates a scene together with its globals
+pub type SceneFn = fn() -> (Scene, Globals);
+
+/// Name and constructor of every built-in scene
+pub const ALL: [(&str, SceneFn); 6] = [
+    ("example", example),
+    ("cornell_box", cornell_box),
+    ("furnace", furnace),
+    ("glossy_spheres", glossy_spheres),
+    ("glass", glass),
+    ("textured", textured),
+];
+
+fn camera(position: Vec3, target: Vec3) -> Camera {
+    let mut camera = Camera::new(100., 0.1, 100., 1., 1.);
+    camera.position = position;
+    camera.look_at(target);
+    camera
+}
+
+fn globals(sky_color: [f32; 3]) -> Globals {
+    Globals {
+        sky_color: [sky_color[0], sky_color[1], sky_color[2], 1.],
+        ..Globals::default()
+    }
+}
+
+/// The scene shown when starting the app
+pub fn example() -> (Scene, Globals) {
+    (Scene::example_scene(), Globals::default())
+}
+
+/// Box made of huge spheres with colored side walls, lit by a small emissive sphere
+pub fn cornell_box() -> (Scene, Globals) {
+    let spheres = vec![
+        Sphere::new(Vec3::new(-1001., 0., 0.), 1000., 1),
+        Sphere::new(Vec3::new(1001., 0., 0.), 1000., 2),
+        Sphere::new(Vec3::new(0., 1001., 0.), 1000., 0),
+        Sphere::new(Vec3::new(0., -1001., 0.), 1000., 0),
+        Sphere::new(Vec3::new(0., 0., -1001.), 1000., 0),
+        Sphere::new(Vec3::new(0., -1.3, -0.2), 0.6, 3),
+        Sphere::new(Vec3::new(-0.4, 0.6, -0.3), 0.4, 0),
+        Sphere::new(Vec3::new(0.45, 0.7, 0.2), 0.3, 4),
+    ];
+    let materials = vec![
+        Material::new()
+            .with_color([0.73, 0.73, 0.73])
+            .with_roughness(1.),
+        Material::new()
+            .with_color([0.65, 0.05, 0.05])
+            .with_roughness(1.),
+        Material::new()
+            .with_color([0.12, 0.45, 0.15])
+            .with_roughness(1.),
+        Material::new().with_emission([1., 0.9, 0.7], 5.),
+        Material::new()
+            .with_color([0.9, 0.9, 0.9])
+            .with_roughness(0.),
+    ];
+    let camera = camera(Vec3::new(0., 0., 8.), Vec3::ZERO);
+    (
+        Scene::new(spheres, materials, camera),
+        globals([0., 0., 0.]),
+    )
+}
+
+/// A white, fully rough sphere under a uniform white sky
+pub fn furnace() -> (Scene, Globals) {
+    let spheres = vec![Sphere::new(Vec3::ZERO, 1., 0)];
+    let materials = vec![Material::new().with_roughness(1.)];
+    let camera = camera(Vec3::new(0., 0., 12.), Vec3::ZERO);
+    (
+        Scene::new(spheres, materials, camera),
+        globals([1., 1., 1.]),
+    )
+}
+
+/// Spheres of increasing roughness on a ground plane
+pub fn glossy_spheres() -> (Scene, Globals) {
+    let mut spheres = vec![Sphere::new(Vec3::new(0., 1000.5, 0.), 1000., 0)];
+    let mut materials = vec![Material::new()
+        .with_color([0.5, 0.5, 0.5])
+        .with_roughness(0.8)];
+    for (i, roughness) in [0., 0.25, 0.5, 1.].into_iter().enumerate() {
+        let x = i as f32 * 1.1 - 1.65;
+        spheres.push(Sphere::new(Vec3::new(x, 0., 0.), 0.5, i + 1));
+        materials.push(
+            Material::new()
+                .with_color([0.9, 0.6, 0.3])
+                .with_roughness(roughness),
+        );
+    }
+    let camera = camera(Vec3::new(0., -2., 14.), Vec3::ZERO);
+    (
+        Scene::new(spheres, materials, camera),
+        globals([0.8, 0.85, 1.]),
+    )
+}
+
+/// A clear and a frosted, tinted glass sphere in front of colored spheres on a ground plane
+pub fn glass() -> (Scene, Globals) {
+    let spheres = vec![
+        Sphere::new(Vec3::new(0., 1000.5, 0.), 1000., 0),
+        Sphere::new(Vec3::new(-0.7, 0., 0.), 0.5, 1),
+        Sphere::new(Vec3::new(0.7, 0., 0.), 0.5, 2),
+        Sphere::new(Vec3::new(-1.2, 0., -2.), 0.5, 3),
+        Sphere::new(Vec3::new(1.2, 0., -2.), 0.5, 4),
+    ];
+    let materials = vec![
+        Material::new()
+            .with_color([0.5, 0.5, 0.5])
+            .with_roughness(0.8),
+        Material::new()
+            .with_roughness(0.)
+            .with_transmission(1., 1.5),
+        Material::new()
+            .with_color([0.7, 0.9, 0.8])
+            .with_roughness(0.3)
+            .with_transmission(1., 1.5),
+        Material::new()
+            .with_color([0.8, 0.2, 0.1])
+            .with_roughness(1.),
+        Material::new()
+            .with_color([0.1, 0.3, 0.8])
+            .with_roughness(1.),
+    ];
+    let camera = camera(Vec3::new(0., -1.5, 9.), Vec3::new(0., 0., -0.5));
+    (
+        Scene::new(spheres, materials, camera),
+        globals([0.8, 0.85, 1.]),
+    )
+}
+
+/// Spheres with procedural and image textures on a checkerboard ground plane
+pub fn textured() -> (Scene, Globals) {
+    let mut spheres = vec![Sphere::new(Vec3::new(0., 1000.5, 0.), 1000., 0)];
+    for i in 0..4 {
+        let x = i as f32 * 1.1 - 1.65;
+        spheres.push(Sphere::new(Vec3::new(x, 0., 0.), 0.5, i + 1));
+    }
+    let mut scene = Scene::new(
+        spheres,
+        Vec::new(),
+        camera(Vec3::new(0., -2., 14.), Vec3::ZERO),
+    );
+
+    let checker = scene.add_procedural(
+        ProceduralTexture::new(ProceduralKind::Checker)
+            .with_colors([0.8, 0.8, 0.8], [0.2, 0.2, 0.2])
+            .with_space(TextureSpace::World)
+            .with_scale(2.),
+    );
+    let marble = scene.add_procedural(
+        ProceduralTexture::new(ProceduralKind::Marble)
+            .with_colors([0.9, 0.85, 0.8], [0.3, 0.1, 0.1])
+            .with_scale(2.),
+    );
+    let noise = scene.add_procedural(
+        ProceduralTexture::new(ProceduralKind::Noise)
+            .with_colors([0., 0., 0.], [1., 1., 1.])
+            .with_scale(6.),
+    );
+    let voronoi = scene.add_procedural(
+        ProceduralTexture::new(ProceduralKind::Voronoi)
+            .with_colors([0.1, 0.4, 0.8], [0.9, 0.9, 0.2])
+            .with_scale(4.),
+    );
+    // generated, so the scene does not depend on files
+    let stripes = RgbaImage::from_fn(8, 8, |x, _| match x / 2 {
+        0 => Rgba([230, 60, 40, 255]),
+        1 => Rgba([240, 220, 60, 255]),
+        2 => Rgba([60, 160, 80, 255]),
+        _ => Rgba([50, 80, 200, 255]),
+    });
+    let stripes = scene.add_texture(ImageTexture::from_image(
+        "stripes.png",
+        DynamicImage::ImageRgba8(stripes),
+    ));
+
+    scene.add_material(
+        "Ground",
+        Material::new()
+            .with_roughness(0.8)
+            .with_texture(TextureChannel::Albedo, TextureRef::Procedural(checker)),
+    );
+    scene.add_material(
+        "Marble",
+        Material::new()
+            .with_roughness(0.2)
+            .with_texture(TextureChannel::Albedo, TextureRef::Procedural(marble)),
+    );
+    scene.add_material(
+        "Stripes",
+        Material::new()
+            .with_roughness(1.)
+            .with_texture(TextureChannel::Albedo, TextureRef::Image(stripes)),
+    );
+    scene.add_material(
+        "Scratched metal",
+        Material::new()
+            .with_color([0.9, 0.6, 0.3])
+            .with_metallic(1.)
+            .with_roughness(1.)
+            .with_texture(TextureChannel::Roughness, TextureRef::Procedural(noise)),
+    );
+    scene.add_material(
+        "Cells",
+        Material::new()
+            .with_roughness(1.)
+            .with_texture(TextureChannel::Albedo, TextureRef::Procedural(voronoi)),
+    );
+    (scene, globals([0.8, 0.85, 1.]))
+}
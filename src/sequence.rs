@@ -0,0 +1,159 @@
+//! `rays render`: renders the animation of a scene file to a numbered image sequence.
+//!
+//! Uses the gpu if available and falls back to the cpu path tracer otherwise.
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use image::Rgba32FImage;
+use log::{info, warn};
+use winit::dpi::PhysicalSize;
+
+use crate::{
+    bench::parse_resolution,
+    globals::Globals,
+    renderer::{self, cpu, headless::Headless},
+    scene::Scene,
+};
+
+pub const USAGE: &str = "usage: rays render <scene file> [options]
+    --frames <first>..<last>  frames to render, both included (default the timeline range)
+    --samples <n>             samples per pixel of every frame (default 64)
+    --size <w>x<h>            resolution (default 1280x720)
+    --output <pattern>        file of every frame, the #s are replaced by the zero padded
+                              frame number (default frame_####.png).
+                              .exr files store the linear radiance instead of srgb
+    --cpu                     render with the cpu even if a gpu is available";
+
+pub struct SequenceConfig {
+    pub scene: PathBuf,
+    /// Both included. None renders the range of the timeline
+    pub frames: Option<(u32, u32)>,
+    pub samples: u32,
+    pub size: PhysicalSize<u32>,
+    pub output: String,
+    pub cpu: bool,
+}
+
+impl SequenceConfig {
+    /// Parses the arguments following `render`. Returns None if `--help` asks for the usage
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Result<Option<Self>, String> {
+        let mut scene = None;
+        let mut config = SequenceConfig {
+            scene: PathBuf::new(),
+            frames: None,
+            samples: 64,
+            size: PhysicalSize::new(1280, 720),
+            output: "frame_####.png".to_owned(),
+            cpu: false,
+        };
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("{arg} needs a value"));
+            match arg.as_str() {
+                "--frames" => config.frames = Some(parse_frames(&value()?)?),
+                "--samples" => {
+                    config.samples = value()?
+                        .parse()
+                        .map_err(|e| format!("invalid sample count: {e}"))?;
+                }
+                "--size" => config.size = parse_resolution(&value()?)?,
+                "--output" => config.output = value()?,
+                "--cpu" => config.cpu = true,
+                "--help" => return Ok(None),
+                _ if arg.starts_with("--") || scene.is_some() => {
+                    return Err(format!("unknown argument {arg}\n{USAGE}"))
+                }
+                _ => scene = Some(PathBuf::from(arg)),
+            }
+        }
+        config.scene = scene.ok_or(format!("no scene file given\n{USAGE}"))?;
+        if config.samples == 0 {
+            return Err("at least one sample is needed".to_owned());
+        }
+        if !config.output.contains('#') {
+            return Err("the output pattern needs a # for the frame number".to_owned());
+        }
+        Ok(Some(config))
+    }
+}
+
+fn parse_frames(frames: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("invalid frames {frames}, expected e.g. 0..120");
+    let (first, last) = frames.split_once("..").ok_or_else(invalid)?;
+    let first: u32 = first.parse().map_err(|_| invalid())?;
+    let last: u32 = last.parse().map_err(|_| invalid())?;
+    if last < first {
+        return Err(invalid());
+    }
+    Ok((first, last))
+}
+
+/// Replaces the run of #s in `pattern` with `frame`, padded to the length of the run
+pub fn frame_path(pattern: &str, frame: u32) -> String {
+    let start = pattern.find('#').unwrap_or(pattern.len());
+    let len = pattern[start..].chars().take_while(|&c| c == '#').count();
+    format!(
+        "{}{frame:0len$}{}",
+        &pattern[..start],
+        &pattern[start + len..]
+    )
+}
+
+/// Entry point of `rays render`, `args` are the arguments following `render`
+pub fn main(args: impl Iterator<Item = String>) -> Result<(), String> {
+    let Some(config) = SequenceConfig::from_args(args)? else {
+        println!("{USAGE}");
+        return Ok(());
+    };
+    let mut base_globals = Globals::default();
+    let mut scene = Scene::load(&config.scene, &mut base_globals)?;
+    scene.camera.resize(config.size);
+    let (first, last) = config
+        .frames
+        .unwrap_or((scene.timeline.start_frame, scene.timeline.end_frame));
+    let headless = if config.cpu { None } else { Headless::new() };
+    if headless.is_none() && !config.cpu {
+        warn!("No suitable gpu adapter found, rendering on the cpu instead");
+    }
+    for frame in first..=last {
+        let start = Instant::now();
+        let mut globals = Globals {
+            // a different noise pattern every frame, otherwise the noise looks like a dirty lens
+            seed: base_globals.seed.wrapping_add(frame),
+            ..base_globals
+        };
+        scene.apply_animation(scene.timeline.frame_time(frame), &mut globals);
+        let image = match &headless {
+            Some(headless) => {
+                headless
+                    .render(&mut scene, globals, config.size, config.samples)
+                    .ok_or(format!("could not read back frame {frame}"))?
+                    .image
+            }
+            None => {
+                scene.update_instances();
+                let mut image = Rgba32FImage::new(config.size.width, config.size.height);
+                cpu::render_image(&mut image, &scene, &globals, config.samples);
+                image
+            }
+        };
+        let path = frame_path(&config.output, frame);
+        if let Some(directory) = Path::new(&path).parent() {
+            fs::create_dir_all(directory)
+                .map_err(|e| format!("could not create {}: {e}", directory.display()))?;
+        }
+        let saved = if path.ends_with(".exr") {
+            renderer::resolve_linear(&image).save(&path)
+        } else {
+            renderer::resolve(&image).save(&path)
+        };
+        saved.map_err(|e| format!("could not write {path}: {e}"))?;
+        info!(
+            "Frame {frame} of {first}..{last} written to {path} in {:.1}s",
+            start.elapsed().as_secs_f32()
+        );
+    }
+    Ok(())
+}
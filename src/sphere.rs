@@ -1,4 +1,7 @@
 use glam::Vec3;
+
+use crate::layout::shader_layout;
+
 #[repr(C)]
 #[derive(Clone, Copy, Debug)]
 pub struct Sphere {
@@ -6,11 +9,12 @@ pub struct Sphere {
     pub center: Vec3,
     /// Radius of the sphere
     pub radius: f32,
+    /// Movement in units per second, used for motion blur.
+    /// At time `t` of the shutter interval the center is at `center + velocity * t`
+    pub velocity: Vec3,
     /// Which material to use.
     /// panics if the index is not found.
     pub material_index: u32,
-    // needed for shader alignment
-    _offset: [f32; 3],
 }
 
 impl Sphere {
@@ -18,10 +22,26 @@ impl Sphere {
         Sphere {
             center,
             radius,
+            velocity: Vec3::ZERO,
             material_index: material_index as u32,
-            _offset: [0.; 3],
         }
     }
+    /// Defaults to not moving
+    pub fn with_velocity(mut self, velocity: Vec3) -> Self {
+        self.velocity = velocity;
+        self
+    }
+    /// Center at `time` of the shutter interval
+    pub fn center_at(&self, time: f32) -> Vec3 {
+        self.center + self.velocity * time
+    }
 }
 unsafe impl bytemuck::Pod for Sphere {}
 unsafe impl bytemuck::Zeroable for Sphere {}
+
+shader_layout!(Sphere => "Sphere" {
+    center,
+    radius,
+    velocity,
+    material_index,
+});
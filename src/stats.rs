@@ -0,0 +1,83 @@
+//! Frame time and path throughput statistics shown in the stats window.
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use crate::renderer::gpu_timer::PassTimes;
+
+/// Amount of frames kept for the frame time graph
+const FRAME_HISTORY: usize = 240;
+/// Time over which the path throughput is averaged
+const THROUGHPUT_WINDOW: Duration = Duration::from_secs(1);
+
+pub struct Stats {
+    /// Frame times of the last frames in milliseconds, oldest first
+    pub frame_times: VecDeque<f32>,
+    /// Paths traced since the start, one per sample of every pixel. The bounces of a path
+    /// are not counted separately. Pixels skipped by adaptive sampling are counted as well
+    pub total_paths: u64,
+    /// Paths traced by the recent frames, used for the throughput
+    recent_paths: VecDeque<(Instant, u64)>,
+    /// Duration of the passes of a recent frame
+    pub pass_times: PassTimes,
+    /// True if `pass_times` are measured with gpu timestamps.
+    /// Otherwise they are the cpu time spent recording the passes
+    pub gpu_timing: bool,
+}
+
+impl Stats {
+    pub fn new(gpu_timing: bool) -> Self {
+        Self {
+            frame_times: VecDeque::with_capacity(FRAME_HISTORY),
+            total_paths: 0,
+            recent_paths: VecDeque::new(),
+            pass_times: [None; 3],
+            gpu_timing,
+        }
+    }
+    /// Records a finished frame, which traced `paths` paths
+    pub fn frame(&mut self, frame_time: Duration, paths: u64) {
+        if self.frame_times.len() == FRAME_HISTORY {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(frame_time.as_secs_f32() * 1000.);
+        self.total_paths += paths;
+
+        let now = Instant::now();
+        self.recent_paths.push_back((now, paths));
+        while let Some((time, _)) = self.recent_paths.front() {
+            if now.duration_since(*time) <= THROUGHPUT_WINDOW {
+                break;
+            }
+            self.recent_paths.pop_front();
+        }
+    }
+    /// Paths traced per second, averaged over the last second
+    pub fn paths_per_second(&self) -> f64 {
+        let (Some((first, _)), Some((last, _))) =
+            (self.recent_paths.front(), self.recent_paths.back())
+        else {
+            return 0.;
+        };
+        let elapsed = last.duration_since(*first).as_secs_f64();
+        if elapsed == 0. {
+            return 0.;
+        }
+        // the paths of the first frame were traced before the window started
+        let paths: u64 = self
+            .recent_paths
+            .iter()
+            .skip(1)
+            .map(|(_, paths)| paths)
+            .sum();
+        paths as f64 / elapsed
+    }
+    /// Average frame time of the recorded frames in milliseconds
+    pub fn average_frame_time(&self) -> f32 {
+        if self.frame_times.is_empty() {
+            return 0.;
+        }
+        self.frame_times.iter().sum::<f32>() / self.frame_times.len() as f32
+    }
+}
@@ -0,0 +1,195 @@
+//! Image textures which vary the channels of a material over a surface.
+//!
+//! A texture slot of a material refers either to an image or to one of the procedural
+//! textures of [`crate::procedural`], see [`TextureRef`].
+//! Every image is scaled to the same size when it is loaded, so all of them fit into
+//! the layers of a single texture array on the gpu. Surfaces look them up by the uv
+//! coordinates of the hit, which the intersection of every primitive provides.
+use std::path::Path;
+
+use glam::{Vec2, Vec3, Vec4};
+use image::{imageops::FilterType, DynamicImage, RgbaImage};
+
+use crate::{procedural::ProceduralTexture, scene::Scene};
+
+/// Edge length of every texture in pixels
+pub const TEXTURE_SIZE: u32 = 512;
+/// Texture slot of a material channel without texture
+pub const NO_TEXTURE: u32 = u32::MAX;
+/// Set in the texture slots which refer to procedural textures
+pub const PROCEDURAL_TEXTURE: u32 = 1 << 31;
+
+/// Material channels which can be textured, in the order of `Material::textures`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextureChannel {
+    /// Multiplies the albedo. Images are decoded from srgb
+    Albedo,
+    /// Multiplies the roughness by the red channel
+    Roughness,
+    /// Multiplies the metallic value by the red channel
+    Metallic,
+    /// Multiplies the emission color. Images are decoded from srgb
+    Emission,
+}
+
+impl TextureChannel {
+    pub const ALL: [TextureChannel; 4] = [
+        TextureChannel::Albedo,
+        TextureChannel::Roughness,
+        TextureChannel::Metallic,
+        TextureChannel::Emission,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TextureChannel::Albedo => "albedo",
+            TextureChannel::Roughness => "roughness",
+            TextureChannel::Metallic => "metallic",
+            TextureChannel::Emission => "emission",
+        }
+    }
+}
+
+/// Texture in a slot of `Material::textures`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextureRef {
+    /// Index into `Scene::textures`
+    Image(usize),
+    /// Index into `Scene::procedurals`
+    Procedural(usize),
+}
+
+impl TextureRef {
+    /// Decodes a texture slot, None for `NO_TEXTURE`
+    pub fn from_slot(slot: u32) -> Option<TextureRef> {
+        match slot {
+            NO_TEXTURE => None,
+            _ if slot & PROCEDURAL_TEXTURE != 0 => Some(TextureRef::Procedural(
+                (slot & !PROCEDURAL_TEXTURE) as usize,
+            )),
+            _ => Some(TextureRef::Image(slot as usize)),
+        }
+    }
+    pub fn to_slot(self) -> u32 {
+        match self {
+            TextureRef::Image(i) => i as u32,
+            TextureRef::Procedural(i) => i as u32 | PROCEDURAL_TEXTURE,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ImageTexture {
+    /// The path the image was loaded from, as it is stored in scene files
+    pub path: String,
+    /// Scaled to `TEXTURE_SIZE`
+    pub image: RgbaImage,
+}
+
+impl ImageTexture {
+    /// Loads the image at `path`, which is resolved relative to `directory`
+    pub fn load(path: &str, directory: &Path) -> Result<Self, String> {
+        let image = image::open(directory.join(path))
+            .map_err(|e| format!("could not load texture {path}: {e}"))?;
+        Ok(Self::from_image(path, image))
+    }
+    pub fn from_image(path: impl Into<String>, image: DynamicImage) -> Self {
+        let image = image
+            .resize_exact(TEXTURE_SIZE, TEXTURE_SIZE, FilterType::Triangle)
+            .into_rgba8();
+        Self {
+            path: path.into(),
+            image,
+        }
+    }
+    /// File name of the image, shown in the ui
+    pub fn name(&self) -> &str {
+        Path::new(&self.path)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or(&self.path)
+    }
+    /// Bilinear lookup which repeats the image outside of [0, 1].
+    /// Mirrors the linear sampler of the compute shader
+    pub fn sample(&self, uv: Vec2) -> Vec4 {
+        let size = TEXTURE_SIZE as i32;
+        let position = uv * TEXTURE_SIZE as f32 - 0.5;
+        let base = position.floor();
+        let t = position - base;
+        let texel = |dx: i32, dy: i32| {
+            let x = (base.x as i32 + dx).rem_euclid(size);
+            let y = (base.y as i32 + dy).rem_euclid(size);
+            let [r, g, b, a] = self.image.get_pixel(x as u32, y as u32).0;
+            Vec4::new(r as f32, g as f32, b as f32, a as f32) / 255.
+        };
+        let top = texel(0, 0).lerp(texel(1, 0), t.x);
+        let bottom = texel(0, 1).lerp(texel(1, 1), t.x);
+        top.lerp(bottom, t.y)
+    }
+}
+
+/// Uv coordinates of a point on the unit sphere.
+/// The top of the image lies at -y, the up direction of the world
+pub fn sphere_uv(p: Vec3) -> Vec2 {
+    let u = 0.5 + p.z.atan2(p.x) / std::f32::consts::TAU;
+    let v = (-p.y).clamp(-1., 1.).acos() / std::f32::consts::PI;
+    Vec2::new(u, v)
+}
+
+/// Converts a color of a texture from srgb to linear
+pub fn srgb_to_linear(color: Vec3) -> Vec3 {
+    Vec3::from_array(color.to_array().map(|s| {
+        if s <= 0.04045 {
+            s / 12.92
+        } else {
+            ((s + 0.055) / 1.055).powf(2.4)
+        }
+    }))
+}
+
+impl Scene {
+    /// Adds a texture and returns its index
+    pub fn add_texture(&mut self, texture: ImageTexture) -> usize {
+        self.textures.push(texture);
+        self.dirty_textures.mark(self.textures.len() - 1);
+        self.textures.len() - 1
+    }
+    /// Adds a procedural texture and returns its index
+    pub fn add_procedural(&mut self, procedural: ProceduralTexture) -> usize {
+        self.procedurals.push(procedural);
+        self.dirty_procedurals.mark(self.procedurals.len() - 1);
+        self.procedurals.len() - 1
+    }
+    /// Removes an image or procedural texture. Materials using it lose that texture,
+    /// the ones using later textures of the same kind are updated to the new indices
+    pub fn remove_texture(&mut self, texture: TextureRef) {
+        match texture {
+            TextureRef::Image(i) => {
+                self.textures.remove(i);
+                self.dirty_textures.mark_all(self.textures.len());
+            }
+            TextureRef::Procedural(i) => {
+                self.procedurals.remove(i);
+                self.dirty_procedurals.mark_all(self.procedurals.len());
+            }
+        }
+        for named in &mut self.materials {
+            for slot in &mut named.material.textures {
+                let new = match (TextureRef::from_slot(*slot), texture) {
+                    (Some(used), _) if used == texture => None,
+                    (Some(TextureRef::Image(i)), TextureRef::Image(removed)) if i > removed => {
+                        Some(TextureRef::Image(i - 1))
+                    }
+                    (Some(TextureRef::Procedural(i)), TextureRef::Procedural(removed))
+                        if i > removed =>
+                    {
+                        Some(TextureRef::Procedural(i - 1))
+                    }
+                    (used, _) => used,
+                };
+                *slot = new.map_or(NO_TEXTURE, TextureRef::to_slot);
+            }
+        }
+        self.dirty_materials.mark_all(self.materials.len());
+    }
+}
@@ -8,6 +8,11 @@ pub struct Timer {
     frame_count: usize,
     log_frequency: usize,
 }
+impl Default for Timer {
+    fn default() -> Self {
+        Timer::new()
+    }
+}
 impl Timer {
     pub fn new() -> Timer {
         Timer {
@@ -27,6 +32,16 @@ impl Timer {
         let dt = Instant::now().duration_since(self.frame_starts[len - 2]);
         dt.as_secs_f32()
     }
+    /// Average frame time in seconds over the last `frames` frames
+    pub fn average_dt(&self, frames: usize) -> f32 {
+        let len = self.frame_starts.len();
+        let frames = frames.clamp(1, len.max(2) - 1);
+        if len < 2 {
+            return 0.;
+        }
+        let dt = self.frame_starts[len - 1].duration_since(self.frame_starts[len - 1 - frames]);
+        dt.as_secs_f32() / frames as f32
+    }
     fn increment_frame(&mut self) {
         self.frame_count += 1;
         self.frame_starts.push(Instant::now());
@@ -35,7 +50,7 @@ impl Timer {
         }
     }
     fn log(&self) {
-        if self.frame_count % self.log_frequency != 0 {
+        if !self.frame_count.is_multiple_of(self.log_frequency) {
             return;
         }
         let i_last = self.log_frequency - 1;
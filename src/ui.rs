@@ -1,11 +1,86 @@
-use egui::{Color32, Context, DragValue, RichText};
+use std::path::Path;
+
+use egui::{
+    plot::{Line, Plot, PlotPoints},
+    Color32, Context, DragValue, ProgressBar, RichText, Ui,
+};
 use egui_wgpu::renderer::ScreenDescriptor;
 use egui_winit::State;
+use glam::Vec3;
 
 use wgpu::{CommandEncoder, Device, Queue, RenderPass, TextureFormat};
 use winit::{event::WindowEvent, event_loop::EventLoopWindowTarget};
 
-use crate::{globals::Globals, scene::Scene};
+use crate::{
+    animation::{Playback, Property},
+    budget::{SampleBudget, SampleTarget, SampleTuner},
+    camera::{Projection, StereoLayout},
+    camera_path::{Bookmark, CameraPath, Keyframe, PathPlayer},
+    controller::{CameraController, ControllerMode},
+    globals::Globals,
+    material::Material,
+    material_library::{self, MaterialLibrary, NamedMaterial},
+    physics::Simulation,
+    procedural::{ProceduralKind, ProceduralTexture, TextureSpace, MAX_OCTAVES},
+    renderer::{
+        gpu_timer::Pass,
+        hot_reload::ShaderWatcher,
+        material_preview::{MaterialPreviews, PREVIEW_SIZE},
+        preview::Preview,
+        render_pipeline::ViewMode,
+    },
+    sampler::SamplerKind,
+    scene::Scene,
+    scene_file,
+    scene_graph::{Node, NodeKind, MIN_SCALE},
+    stats::Stats,
+    texture::{ImageTexture, TextureChannel, TextureRef, NO_TEXTURE},
+};
+
+/// Save and load of the scene file, requested by the ui and handled by the app
+#[derive(Debug)]
+pub struct SceneFileState {
+    pub path: String,
+    pub request: Option<FileRequest>,
+    /// Outcome of the last request
+    pub status: Option<Result<String, String>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileRequest {
+    Save,
+    Load,
+}
+
+impl Default for SceneFileState {
+    fn default() -> Self {
+        Self {
+            path: scene_file::DEFAULT_PATH.to_string(),
+            request: None,
+            status: None,
+        }
+    }
+}
+
+/// Parts of the app which can be inspected and edited through the ui
+pub struct EditorState<'a> {
+    pub scene: &'a mut Scene,
+    pub globals: &'a mut Globals,
+    pub view_mode: &'a mut ViewMode,
+    pub budget: &'a mut SampleBudget,
+    pub tuner: &'a mut SampleTuner,
+    pub preview: &'a mut Preview,
+    pub shader_watcher: &'a mut ShaderWatcher,
+    pub stats: &'a Stats,
+    pub controller: &'a mut CameraController,
+    pub player: &'a mut PathPlayer,
+    pub scene_file: &'a mut SceneFileState,
+    pub playback: &'a mut Playback,
+    pub simulation: &'a mut Simulation,
+    pub material_previews: &'a MaterialPreviews,
+    /// Samples per pixel accumulated so far
+    pub acc_samples: usize,
+}
 
 pub struct UiManager {
     egui_renderer: egui_wgpu::Renderer,
@@ -14,6 +89,19 @@ pub struct UiManager {
     screen_descriptor: ScreenDescriptor,
     state: State,
     header_color: Color32,
+    /// Property of the next track added in the timeline window
+    new_track: Property,
+    /// Spheres larger than this are pinned by the button in the physics window
+    pin_radius: f32,
+    /// Materials which can be imported into the scene
+    library: MaterialLibrary,
+    library_path: String,
+    /// Outcome of the last save or load of the library
+    library_status: Option<Result<String, String>>,
+    /// Image loaded by the button in the textures section
+    texture_path: String,
+    /// Outcome of the last texture load
+    texture_status: Option<Result<String, String>>,
 }
 impl UiManager {
     pub fn new(
@@ -22,7 +110,7 @@ impl UiManager {
         screen_descriptor: ScreenDescriptor,
         event_loop: &EventLoopWindowTarget<()>,
     ) -> Self {
-        let egui_renderer = egui_wgpu::Renderer::new(&device, surface_format, None, 1);
+        let egui_renderer = egui_wgpu::Renderer::new(device, surface_format, None, 1);
         let context = Context::default();
         let state = State::new(&event_loop);
 
@@ -33,10 +121,21 @@ impl UiManager {
             screen_descriptor,
             state,
             header_color: Color32::from_rgb(255, 150, 150),
+            new_track: Property::SphereCenter(0),
+            pin_radius: 20.,
+            library: MaterialLibrary::presets(),
+            library_path: material_library::DEFAULT_PATH.to_string(),
+            library_status: None,
+            texture_path: String::new(),
+            texture_status: None,
         }
     }
+    pub fn context(&self) -> &Context {
+        &self.context
+    }
+    /// Returns true if the ui consumed the event, e.g. a click into one of its windows
     pub fn handle_window_event(&mut self, window_event: &WindowEvent) -> bool {
-        self.state.on_event(&self.context, window_event).repaint
+        self.state.on_event(&self.context, window_event).consumed
     }
 
     pub fn resize(&mut self, screen_descriptor: ScreenDescriptor) {
@@ -47,132 +146,1007 @@ impl UiManager {
         device: &Device,
         queue: &Queue,
         window: &winit::window::Window,
-        scene: &mut Scene,
-        globals: &mut Globals,
+        state: EditorState,
     ) -> bool {
+        let EditorState {
+            scene,
+            globals,
+            view_mode,
+            budget,
+            tuner,
+            preview,
+            shader_watcher,
+            stats,
+            controller,
+            player,
+            scene_file,
+            playback,
+            simulation,
+            material_previews,
+            acc_samples,
+        } = state;
         let egui_raw_input = self.state.take_egui_input(window);
         let mut reset_renderer = false;
         let egui_full_output = self.context.run(egui_raw_input, |ctx| {
             egui::Window::new("Scene").vscroll(true).show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("file");
+                    ui.text_edit_singleline(&mut scene_file.path);
+                });
+                ui.horizontal(|ui| {
+                    if ui.button("save").clicked() {
+                        scene_file.request = Some(FileRequest::Save);
+                    }
+                    if ui.button("load").clicked() {
+                        scene_file.request = Some(FileRequest::Load);
+                    }
+                });
+                match &scene_file.status {
+                    Some(Ok(message)) => {
+                        ui.label(message);
+                    }
+                    Some(Err(error)) => {
+                        ui.label(RichText::new(error).color(Color32::LIGHT_RED));
+                    }
+                    None => {}
+                }
+                ui.add_space(10.);
+
                 ui.heading(RichText::new("Materials").color(self.header_color));
 
-                for (i, material) in scene.materials.iter_mut().enumerate() {
-                    let i_name = format!("{}", i);
-                    let name = Scene::material_name(i).unwrap_or(&i_name);
-                    ui.collapsing(name, |ui| {
-                        ui.horizontal(|ui| {
-                            ui.label("color");
-                            let color = &mut material.albedo.0;
-                            reset_renderer |=
-                                ui.color_edit_button_rgba_premultiplied(color).changed();
+                let textures = (&scene.textures[..], &scene.procedurals[..]);
+                for (i, NamedMaterial { name, material }) in scene.materials.iter_mut().enumerate()
+                {
+                    let mut changed = false;
+                    let header =
+                        egui::CollapsingHeader::new(name.clone()).id_source(("material", i));
+                    header.show(ui, |ui| {
+                        ui.horizontal_top(|ui| {
+                            if let Some(texture) = material_previews.texture(i) {
+                                let size = PREVIEW_SIZE as f32;
+                                ui.image(texture.id(), [size, size]);
+                            }
+                            ui.vertical(|ui| {
+                                ui.horizontal(|ui| {
+                                    ui.label("name");
+                                    ui.text_edit_singleline(name);
+                                });
+                                ui.horizontal(|ui| {
+                                    ui.label("color");
+                                    let color = &mut material.albedo.0;
+                                    changed |=
+                                        ui.color_edit_button_rgba_premultiplied(color).changed();
+                                });
+                                ui.horizontal(|ui| {
+                                    ui.label("emission");
+                                    let color = &mut material.emission;
+                                    changed |=
+                                        ui.color_edit_button_rgba_unmultiplied(color).changed();
+                                });
+                                ui.horizontal(|ui| {
+                                    ui.label("emission strength");
+                                    let color = &mut material.emission[3];
+                                    changed |= ui.add(DragValue::new(color).speed(0.01)).changed();
+                                });
+                                ui.horizontal(|ui| {
+                                    ui.label("metallic");
+                                    changed |= ui
+                                        .add(
+                                            DragValue::new(&mut material.metallic)
+                                                .speed(0.01)
+                                                .clamp_range(0..=1),
+                                        )
+                                        .changed();
+                                });
+                                ui.horizontal(|ui| {
+                                    ui.label("specular_intensity");
+                                    changed |= ui
+                                        .add(
+                                            DragValue::new(&mut material.specular_intensity)
+                                                .speed(0.01)
+                                                .clamp_range(0..=1),
+                                        )
+                                        .changed();
+                                });
+                                ui.horizontal(|ui| {
+                                    ui.label("roughness");
+                                    changed |= ui
+                                        .add(
+                                            DragValue::new(&mut material.roughness)
+                                                .speed(0.01)
+                                                .clamp_range(0..=1),
+                                        )
+                                        .changed();
+                                });
+                                ui.horizontal(|ui| {
+                                    ui.label("transmission");
+                                    changed |= ui
+                                        .add(
+                                            DragValue::new(&mut material.transmission)
+                                                .speed(0.01)
+                                                .clamp_range(0..=1),
+                                        )
+                                        .changed();
+                                });
+                                ui.horizontal(|ui| {
+                                    ui.label("index of refraction");
+                                    changed |= ui
+                                        .add(
+                                            DragValue::new(&mut material.ior)
+                                                .speed(0.01)
+                                                .clamp_range(1..=3),
+                                        )
+                                        .changed();
+                                });
+                                for channel in TextureChannel::ALL {
+                                    changed |= texture_slot(ui, material, channel, textures, i);
+                                }
+                                if ui.button("add to library").clicked() {
+                                    self.library.insert(NamedMaterial::new(
+                                        name.clone(),
+                                        material.without_textures(),
+                                    ));
+                                }
+                            });
                         });
+                    });
+                    if changed {
+                        scene.dirty_materials.mark(i);
+                        reset_renderer = true;
+                    }
+                }
+                ui.collapsing("Library", |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("file");
+                        ui.text_edit_singleline(&mut self.library_path);
+                    });
+                    ui.horizontal(|ui| {
+                        if ui.button("save").clicked() {
+                            self.library_status = Some(
+                                self.library
+                                    .save(&self.library_path)
+                                    .map(|()| format!("Saved {}", self.library_path)),
+                            );
+                        }
+                        if ui.button("load").clicked() {
+                            self.library_status =
+                                Some(MaterialLibrary::load(&self.library_path).map(|library| {
+                                    self.library = library;
+                                    format!("Loaded {}", self.library_path)
+                                }));
+                        }
+                        if ui.button("presets").clicked() {
+                            self.library = MaterialLibrary::presets();
+                            self.library_status = None;
+                        }
+                    });
+                    match &self.library_status {
+                        Some(Ok(message)) => {
+                            ui.label(message);
+                        }
+                        Some(Err(error)) => {
+                            ui.label(RichText::new(error).color(Color32::LIGHT_RED));
+                        }
+                        None => {}
+                    }
+                    for named in &self.library.materials {
                         ui.horizontal(|ui| {
-                            ui.label("emission");
-                            let color = &mut material.emission;
-                            reset_renderer |=
-                                ui.color_edit_button_rgba_unmultiplied(color).changed();
+                            if ui.button("import").clicked() {
+                                scene.import_material(named);
+                                reset_renderer = true;
+                            }
+                            ui.label(&named.name);
                         });
+                    }
+                });
+                ui.collapsing("Textures", |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("file");
+                        ui.text_edit_singleline(&mut self.texture_path);
+                    });
+                    if ui.button("load").clicked() {
+                        let loaded = ImageTexture::load(&self.texture_path, Path::new(""));
+                        self.texture_status = Some(loaded.map(|texture| {
+                            scene.add_texture(texture);
+                            format!("Loaded {}", self.texture_path)
+                        }));
+                    }
+                    match &self.texture_status {
+                        Some(Ok(message)) => {
+                            ui.label(message);
+                        }
+                        Some(Err(error)) => {
+                            ui.label(RichText::new(error).color(Color32::LIGHT_RED));
+                        }
+                        None => {}
+                    }
+                    let mut removed = None;
+                    for (i, texture) in scene.textures.iter().enumerate() {
                         ui.horizontal(|ui| {
-                            ui.label("emission strength");
-                            let color = &mut material.emission[3];
-                            reset_renderer |= ui.add(DragValue::new(color).speed(0.01)).changed();
+                            if ui.button("remove").clicked() {
+                                removed = Some(i);
+                            }
+                            ui.label(texture.name()).on_hover_text(&texture.path);
                         });
+                    }
+                    if let Some(i) = removed {
+                        scene.remove_texture(TextureRef::Image(i));
+                        reset_renderer = true;
+                    }
+                });
+                ui.collapsing("Procedural textures", |ui| {
+                    let mut removed = None;
+                    for (i, procedural) in scene.procedurals.iter_mut().enumerate() {
+                        let label = format!("{} {i}", procedural.kind().name());
+                        let header =
+                            egui::CollapsingHeader::new(label).id_source(("procedural", i));
+                        let changed = header.show(ui, |ui| {
+                            let changed = procedural_editor(ui, procedural, i);
+                            if ui.button("remove").clicked() {
+                                removed = Some(i);
+                            }
+                            changed
+                        });
+                        if changed.body_returned == Some(true) {
+                            scene.dirty_procedurals.mark(i);
+                            reset_renderer = true;
+                        }
+                    }
+                    if ui.button("add").clicked() {
+                        scene.add_procedural(ProceduralTexture::default());
+                    }
+                    if let Some(i) = removed {
+                        scene.remove_texture(TextureRef::Procedural(i));
+                        reset_renderer = true;
+                    }
+                });
+                ui.add_space(10.);
+
+                ui.heading(RichText::new("Scene graph").color(self.header_color));
+                let mut edits = Vec::new();
+                reset_renderer |= node_tree(ui, scene, None, &mut edits);
+                if ui.button("add group").clicked() {
+                    edits.push(GraphEdit::Add(Node::new("Group", NodeKind::Group)));
+                }
+                for edit in edits {
+                    match edit {
+                        GraphEdit::Add(node) => {
+                            scene.graph.add(node);
+                        }
+                        GraphEdit::Remove(index) => scene.graph.remove(index),
+                    }
+                    reset_renderer = true;
+                }
+            });
+
+            egui::Window::new("Camera").show(ctx, |ui| {
+                ui.label("Transform");
+                ui.horizontal(|ui| {
+                    reset_renderer |= ui
+                        .add(DragValue::new(&mut scene.camera.position.x).speed(0.01))
+                        .changed();
+                    reset_renderer |= ui
+                        .add(DragValue::new(&mut scene.camera.position.y).speed(0.01))
+                        .changed();
+                    reset_renderer |= ui
+                        .add(DragValue::new(&mut scene.camera.position.z).speed(0.01))
+                        .changed();
+                });
+                ui.label("Fov");
+                ui.horizontal(|ui| {
+                    reset_renderer |= ui
+                        .add(DragValue::new(&mut scene.camera.fov).speed(0.01))
+                        .changed();
+                });
+                ui.label("Shutter");
+                ui.horizontal(|ui| {
+                    let [open, close] = &mut scene.camera.shutter;
+                    reset_renderer |= ui
+                        .add(DragValue::new(open).speed(0.001).suffix(" s"))
+                        .changed();
+                    *close = close.max(*open);
+                    reset_renderer |= ui
+                        .add(
+                            DragValue::new(close)
+                                .speed(0.001)
+                                .suffix(" s")
+                                .clamp_range(*open..=f32::MAX),
+                        )
+                        .changed();
+                })
+                .response
+                .on_hover_text("moving spheres are blurred while the shutter is open");
+                ui.label("Focus");
+                ui.horizontal(|ui| {
+                    reset_renderer |= ui
+                        .add(
+                            DragValue::new(&mut scene.camera.focus_distance)
+                                .speed(0.01)
+                                .clamp_range(0.001..=f32::MAX),
+                        )
+                        .on_hover_text("distance which is in focus")
+                        .changed();
+                    reset_renderer |= ui
+                        .add(
+                            DragValue::new(&mut scene.camera.aperture)
+                                .speed(0.001)
+                                .clamp_range(0.0..=f32::MAX)
+                                .prefix("aperture "),
+                        )
+                        .on_hover_text("radius of the lens, zero keeps everything sharp")
+                        .changed();
+                });
+                ui.label("Projection");
+                let projection = &mut scene.camera.projection;
+                egui::ComboBox::from_id_source("projection")
+                    .selected_text(projection.name())
+                    .show_ui(ui, |ui| {
+                        for default in Projection::ALL {
+                            let selected = projection.index() == default.index();
+                            if ui.selectable_label(selected, default.name()).clicked() && !selected
+                            {
+                                *projection = default;
+                                reset_renderer = true;
+                            }
+                        }
+                    });
+                match projection {
+                    Projection::Orthographic { width } => {
                         ui.horizontal(|ui| {
-                            ui.label("metallic");
+                            ui.label("width");
                             reset_renderer |= ui
-                                .add(
-                                    DragValue::new(&mut material.metallic)
-                                        .speed(0.01)
-                                        .clamp_range(0..=1),
-                                )
+                                .add(DragValue::new(width).speed(0.01).clamp_range(0.01..=1000.))
                                 .changed();
                         });
+                    }
+                    Projection::Fisheye { fov } => {
                         ui.horizontal(|ui| {
-                            ui.label("specular_intensity");
+                            ui.label("fisheye fov");
                             reset_renderer |= ui
-                                .add(
-                                    DragValue::new(&mut material.specular_intensity)
-                                        .speed(0.01)
-                                        .clamp_range(0..=1),
-                                )
+                                .add(DragValue::new(fov).speed(0.5).clamp_range(1..=360))
                                 .changed();
                         });
-                        ui.horizontal(|ui| {
-                            ui.label("roughness");
+                    }
+                    _ => {}
+                }
+                ui.label("Stereo");
+                let stereo = &mut scene.camera.stereo;
+                egui::ComboBox::from_id_source("stereo")
+                    .selected_text(stereo.layout.name())
+                    .show_ui(ui, |ui| {
+                        for layout in StereoLayout::ALL {
                             reset_renderer |= ui
-                                .add(
-                                    DragValue::new(&mut material.roughness)
-                                        .speed(0.01)
-                                        .clamp_range(0..=1),
-                                )
+                                .selectable_value(&mut stereo.layout, layout, layout.name())
                                 .changed();
+                        }
+                    });
+                if stereo.layout != StereoLayout::Mono {
+                    ui.horizontal(|ui| {
+                        ui.label("eye distance");
+                        reset_renderer |= ui
+                            .add(
+                                DragValue::new(&mut stereo.eye_distance)
+                                    .speed(0.001)
+                                    .clamp_range(0.0..=f32::MAX),
+                            )
+                            .changed();
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("convergence");
+                        reset_renderer |= ui
+                            .add(
+                                DragValue::new(&mut stereo.convergence)
+                                    .speed(0.05)
+                                    .clamp_range(0.01..=f32::MAX),
+                            )
+                            .changed();
+                    });
+                }
+
+                ui.add_space(10.);
+                ui.heading(RichText::new("Controller").color(self.header_color));
+                let mut mode = controller.mode();
+                egui::ComboBox::from_id_source("controller")
+                    .selected_text(mode.name())
+                    .show_ui(ui, |ui| {
+                        for m in ControllerMode::ALL {
+                            ui.selectable_value(&mut mode, m, m.name());
+                        }
+                    });
+                if mode != controller.mode() {
+                    controller.set_mode(mode, &scene.camera);
+                }
+                match mode {
+                    ControllerMode::Fly => {
+                        ui.label("WASD, Q and E to move, shift to move faster");
+                        ui.horizontal(|ui| {
+                            ui.label("speed");
+                            ui.add(
+                                DragValue::new(&mut controller.speed)
+                                    .speed(0.1)
+                                    .clamp_range(0.01..=f32::MAX),
+                            );
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("acceleration");
+                            ui.add(
+                                DragValue::new(&mut controller.acceleration)
+                                    .speed(0.5)
+                                    .clamp_range(0.01..=f32::MAX),
+                            );
                         });
+                        ui.horizontal(|ui| {
+                            ui.label("fast multiplier");
+                            ui.add(
+                                DragValue::new(&mut controller.fast_multiplier)
+                                    .speed(0.05)
+                                    .clamp_range(1.0..=f32::MAX),
+                            );
+                        });
+                    }
+                    ControllerMode::Orbit | ControllerMode::Turntable => {
+                        ui.label("right drag to rotate, scroll to zoom");
+                        let target_name = |sphere: Option<usize>| match sphere {
+                            Some(i) => scene
+                                .sphere_name(i)
+                                .map_or(format!("Sphere {i}"), str::to_string),
+                            None => "free point".to_string(),
+                        };
+                        egui::ComboBox::from_label("target")
+                            .selected_text(target_name(controller.target_sphere))
+                            .show_ui(ui, |ui| {
+                                let targets =
+                                    std::iter::once(None).chain((0..scene.spheres.len()).map(Some));
+                                for target in targets {
+                                    ui.selectable_value(
+                                        &mut controller.target_sphere,
+                                        target,
+                                        target_name(target),
+                                    );
+                                }
+                            });
+                        if controller.target_sphere.is_none() {
+                            ui.horizontal(|ui| {
+                                ui.label("point");
+                                ui.add(DragValue::new(&mut controller.target.x).speed(0.01));
+                                ui.add(DragValue::new(&mut controller.target.y).speed(0.01));
+                                ui.add(DragValue::new(&mut controller.target.z).speed(0.01));
+                            });
+                        }
+                        if mode == ControllerMode::Turntable {
+                            ui.horizontal(|ui| {
+                                ui.label("degrees per second");
+                                ui.add(DragValue::new(&mut controller.turntable_speed).speed(0.5));
+                            });
+                        }
+                    }
+                }
+            });
+
+            egui::Window::new("Globals").show(ctx, |ui| {
+                ui.label("bounces");
+                reset_renderer |= ui.add(DragValue::new(&mut globals.bounces)).changed();
+                ui.label("sky color");
+                let color = &mut globals.sky_color;
+                reset_renderer |= ui.color_edit_button_rgba_unmultiplied(color).changed();
+                ui.label("sampler");
+                let mut sampler_kind = SamplerKind::from_u32(globals.sampler_kind);
+                egui::ComboBox::from_id_source("sampler")
+                    .selected_text(sampler_kind.name())
+                    .show_ui(ui, |ui| {
+                        for kind in SamplerKind::ALL {
+                            ui.selectable_value(&mut sampler_kind, kind, kind.name());
+                        }
                     });
+                if sampler_kind as u32 != globals.sampler_kind {
+                    globals.sampler_kind = sampler_kind as u32;
+                    reset_renderer = true;
                 }
+                ui.horizontal(|ui| {
+                    ui.label("seed");
+                    reset_renderer |= ui.add(DragValue::new(&mut globals.seed)).changed();
+                });
+
                 ui.add_space(10.);
+                ui.heading(RichText::new("Adaptive sampling").color(self.header_color));
+                let mut adaptive_sampling = globals.adaptive_sampling != 0;
+                if ui.checkbox(&mut adaptive_sampling, "enabled").changed() {
+                    globals.adaptive_sampling = adaptive_sampling as u32;
+                }
+                ui.horizontal(|ui| {
+                    ui.label("convergence threshold");
+                    ui.add(
+                        DragValue::new(&mut globals.convergence_threshold)
+                            .speed(0.0001)
+                            .clamp_range(0..=1),
+                    );
+                });
+                ui.horizontal(|ui| {
+                    ui.label("min samples");
+                    ui.add(DragValue::new(&mut globals.min_samples).clamp_range(2..=u32::MAX));
+                });
+                ui.horizontal(|ui| {
+                    ui.label("recheck interval");
+                    ui.add(DragValue::new(&mut globals.recheck_interval).clamp_range(1..=u32::MAX));
+                });
+                ui.label("viewport");
+                ui.horizontal(|ui| {
+                    ui.radio_value(view_mode, ViewMode::Render, "render");
+                    ui.radio_value(view_mode, ViewMode::SampleHeatmap, "sample heatmap");
+                });
+            });
 
-                ui.heading(RichText::new("Spheres").color(self.header_color));
-                for (i, sphere) in scene.spheres.iter_mut().enumerate() {
-                    ui.collapsing(format!("Sphere {i}"), |ui| {
+            egui::Window::new("Render").show(ctx, |ui| {
+                ui.label(format!(
+                    "{acc_samples} samples per pixel in {:.1}s",
+                    budget.elapsed().as_secs_f32()
+                ));
+                ui.horizontal(|ui| {
+                    ui.label("samples per frame");
+                    ui.add_enabled(
+                        !tuner.enabled,
+                        DragValue::new(&mut globals.samples_per_frame).clamp_range(1..=1024),
+                    );
+                });
+                ui.checkbox(&mut tuner.enabled, "tune samples per frame");
+                ui.horizontal(|ui| {
+                    ui.label("target frame time");
+                    ui.add_enabled(
+                        tuner.enabled,
+                        DragValue::new(&mut tuner.target_frame_time)
+                            .suffix(" ms")
+                            .clamp_range(1..=1000),
+                    );
+                });
+                ui.checkbox(&mut preview.enabled, "low resolution while navigating");
+                ui.horizontal(|ui| {
+                    ui.label("preview scale");
+                    ui.add_enabled(
+                        preview.enabled,
+                        DragValue::new(&mut preview.scale)
+                            .speed(0.01)
+                            .clamp_range(0.05..=1.),
+                    );
+                });
+                let mut temporal = globals.temporal != 0;
+                if ui
+                    .checkbox(&mut temporal, "reproject when the camera moves")
+                    .changed()
+                {
+                    globals.temporal = temporal as u32;
+                }
+                ui.horizontal(|ui| {
+                    ui.label("history samples");
+                    ui.add_enabled(
+                        temporal,
+                        DragValue::new(&mut globals.history_limit).clamp_range(1..=4096),
+                    );
+                    ui.label("depth tolerance");
+                    ui.add_enabled(
+                        temporal,
+                        DragValue::new(&mut globals.depth_tolerance)
+                            .speed(0.001)
+                            .clamp_range(0..=1),
+                    );
+                });
+                ui.checkbox(&mut shader_watcher.enabled, "reload shaders on change");
+                ui.label("stop after");
+                let mut target = budget.target;
+                ui.horizontal(|ui| {
+                    let unlimited = matches!(target, SampleTarget::Unlimited);
+                    let samples = matches!(target, SampleTarget::SamplesPerPixel(_));
+                    let time = matches!(target, SampleTarget::TimeLimit(_));
+                    let converged = matches!(target, SampleTarget::Converged(_));
+                    if ui.radio(unlimited, "never").clicked() {
+                        target = SampleTarget::Unlimited;
+                    }
+                    if ui.radio(samples, "samples").clicked() && !samples {
+                        target = SampleTarget::SamplesPerPixel(1024);
+                    }
+                    if ui.radio(time, "time").clicked() && !time {
+                        target = SampleTarget::TimeLimit(60.);
+                    }
+                    if ui.radio(converged, "noise").clicked() && !converged {
+                        target = SampleTarget::Converged(0.99);
+                    }
+                });
+                match &mut target {
+                    SampleTarget::Unlimited => {}
+                    SampleTarget::SamplesPerPixel(spp) => {
+                        ui.add(DragValue::new(spp).suffix(" spp").clamp_range(1..=u32::MAX));
+                    }
+                    SampleTarget::TimeLimit(seconds) => {
+                        ui.add(
+                            DragValue::new(seconds)
+                                .suffix(" s")
+                                .clamp_range(0.1..=f32::MAX),
+                        );
+                    }
+                    SampleTarget::Converged(fraction) => {
                         ui.horizontal(|ui| {
-                            ui.label("position");
-                            reset_renderer |= ui
-                                .add(DragValue::new(&mut sphere.center.x).speed(0.01))
-                                .changed();
-                            reset_renderer |= ui
-                                .add(DragValue::new(&mut sphere.center.y).speed(0.01))
-                                .changed();
-                            reset_renderer |= ui
-                                .add(DragValue::new(&mut sphere.center.z).speed(0.01))
-                                .changed();
+                            ui.add(DragValue::new(fraction).speed(0.001).clamp_range(0..=1));
+                            ui.label("of pixels converged");
                         });
+                    }
+                }
+                if target != budget.target {
+                    budget.target = target;
+                    budget.retarget();
+                }
+
+                ui.add(ProgressBar::new(budget.progress(acc_samples)).show_percentage());
+                match budget.eta(acc_samples) {
+                    _ if budget.is_paused() => ui.label("done"),
+                    Some(eta) => ui.label(format!("eta {:.1}s", eta.as_secs_f32())),
+                    None => ui.label("eta unknown"),
+                };
+                ui.horizontal(|ui| {
+                    let button = ui.add_enabled(budget.is_paused(), egui::Button::new("continue"));
+                    if button.clicked() {
+                        budget.continue_render(acc_samples);
+                    }
+                    ui.add(DragValue::new(&mut budget.continue_samples).suffix(" spp"));
+                });
+            });
+
+            egui::Window::new("Stats").show(ctx, |ui| {
+                let frame_time = stats.average_frame_time();
+                ui.label(format!(
+                    "frame time {frame_time:.2} ms ({:.0} fps)",
+                    1000. / frame_time.max(0.001)
+                ));
+                let points: PlotPoints = stats
+                    .frame_times
+                    .iter()
+                    .enumerate()
+                    .map(|(i, ms)| [i as f64, *ms as f64])
+                    .collect();
+                Plot::new("frame times")
+                    .height(80.)
+                    .include_y(0.)
+                    .show_x(false)
+                    .allow_drag(false)
+                    .allow_zoom(false)
+                    .allow_scroll(false)
+                    .allow_boxed_zoom(false)
+                    .show(ui, |plot_ui| plot_ui.line(Line::new(points).name("ms")));
+                ui.label(format!("{acc_samples} samples per pixel"));
+                ui.label(format!(
+                    "{}paths, {}paths/s",
+                    si_prefixed(stats.total_paths as f64),
+                    si_prefixed(stats.paths_per_second())
+                ));
+
+                ui.add_space(10.);
+                // without timestamp queries only the recording of the commands is timed,
+                // which says nothing about the time the gpu spends on the pass
+                let (heading, unit) = if stats.gpu_timing {
+                    ("Gpu pass times", "ms")
+                } else {
+                    ("Cpu recording time of the passes", "ms recording")
+                };
+                ui.heading(RichText::new(heading).color(self.header_color));
+                if !stats.gpu_timing {
+                    ui.label("timestamp queries are not supported, so the gpu time is unknown");
+                }
+                for pass in Pass::ALL {
+                    let time = match stats.pass_times[pass as usize] {
+                        Some(time) => format!("{:.3} {unit}", time.as_secs_f64() * 1000.),
+                        None => "-".to_owned(),
+                    };
+                    ui.label(format!("{}: {time}", pass.name()));
+                }
+            });
+
+            egui::Window::new("Bookmarks").show(ctx, |ui| {
+                let mut remove = None;
+                for (i, bookmark) in scene.bookmarks.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        if ui.button("go").clicked() {
+                            scene.camera.set_pose(bookmark.pose);
+                            player.playing = false;
+                            reset_renderer = true;
+                        }
+                        ui.text_edit_singleline(&mut bookmark.name);
+                        if ui.button("update").clicked() {
+                            bookmark.pose = scene.camera.pose();
+                        }
+                        if ui.button("delete").clicked() {
+                            remove = Some(i);
+                        }
+                    });
+                }
+                if let Some(i) = remove {
+                    scene.bookmarks.remove(i);
+                }
+                if ui.button("add current view").clicked() {
+                    scene.bookmarks.push(Bookmark {
+                        name: format!("View {}", scene.bookmarks.len() + 1),
+                        pose: scene.camera.pose(),
+                    });
+                }
+
+                ui.add_space(10.);
+                ui.heading(RichText::new("Camera paths").color(self.header_color));
+                let mut remove = None;
+                for (i, path) in scene.camera_paths.iter_mut().enumerate() {
+                    ui.push_id(i, |ui| {
                         ui.horizontal(|ui| {
-                            ui.label("radius");
-                            reset_renderer |= ui
-                                .add(DragValue::new(&mut sphere.radius).speed(0.001))
-                                .changed();
+                            ui.text_edit_singleline(&mut path.name);
+                            if ui.button("delete").clicked() {
+                                remove = Some(i);
+                            }
                         });
+                        let active = player.path == Some(i);
                         ui.horizontal(|ui| {
-                            ui.label("material index");
-                            reset_renderer |= ui
-                                .add(
-                                    DragValue::new(&mut sphere.material_index)
-                                        .clamp_range(0..=scene.materials.len() - 1),
-                                )
-                                .changed();
+                            if active && player.playing {
+                                if ui.button("stop").clicked() {
+                                    player.playing = false;
+                                }
+                            } else if ui.button("play").clicked() {
+                                player.play(i);
+                            }
+                            ui.checkbox(&mut player.looping, "loop");
                         });
+                        if active {
+                            let slider = egui::Slider::new(&mut player.time, 0.0..=path.duration())
+                                .text("time");
+                            player.seeked |= ui.add(slider).changed();
+                        }
+                        let mut remove_key = None;
+                        let mut retime = None;
+                        for (k, key) in path.keyframes().iter().enumerate() {
+                            ui.horizontal(|ui| {
+                                let mut time = key.time;
+                                if ui
+                                    .add(DragValue::new(&mut time).speed(0.05).suffix(" s"))
+                                    .changed()
+                                {
+                                    retime = Some((k, time.max(0.)));
+                                }
+                                if ui.button("go").clicked() {
+                                    scene.camera.set_pose(key.pose);
+                                    player.playing = false;
+                                    reset_renderer = true;
+                                }
+                                if ui.button("delete").clicked() {
+                                    remove_key = Some(k);
+                                }
+                            });
+                        }
+                        if let Some((k, time)) = retime {
+                            path.set_time(k, time);
+                        }
+                        if let Some(k) = remove_key {
+                            path.remove(k);
+                        }
+                        if ui.button("add keyframe at current view").clicked() {
+                            let time = match path.keyframes().is_empty() {
+                                true => 0.,
+                                false => path.duration() + 1.,
+                            };
+                            path.insert(Keyframe {
+                                time,
+                                pose: scene.camera.pose(),
+                            });
+                        }
                     });
+                    ui.separator();
+                }
+                if let Some(i) = remove {
+                    scene.remove_camera_path(i);
+                    player.remove_path(i);
+                }
+                if ui.button("new path").clicked() {
+                    let name = format!("Path {}", scene.camera_paths.len() + 1);
+                    scene.camera_paths.push(CameraPath::new(name));
                 }
             });
 
-            egui::Window::new("Camera").show(ctx, |ui| {
-                ui.label(format!("Transform"));
+            egui::Window::new("Timeline").show(ctx, |ui| {
+                let timeline = &mut scene.timeline;
                 ui.horizontal(|ui| {
-                    reset_renderer |= ui
-                        .add(DragValue::new(&mut scene.camera.position.x).speed(0.01))
-                        .changed();
-                    reset_renderer |= ui
-                        .add(DragValue::new(&mut scene.camera.position.y).speed(0.01))
-                        .changed();
-                    reset_renderer |= ui
-                        .add(DragValue::new(&mut scene.camera.position.z).speed(0.01))
-                        .changed();
+                    if playback.playing {
+                        if ui.button("pause").clicked() {
+                            playback.playing = false;
+                        }
+                    } else if ui.button("play").clicked() {
+                        playback.playing = true;
+                    }
+                    ui.checkbox(&mut playback.looping, "loop");
                 });
-                ui.label(format!("Fov"));
+                let range = timeline.start_time()..=timeline.end_time();
+                let slider = egui::Slider::new(&mut playback.time, range).text("time");
+                playback.seeked |= ui.add(slider).changed();
+                ui.label(format!(
+                    "frame {}",
+                    (playback.time * timeline.fps).round() as u32
+                ));
                 ui.horizontal(|ui| {
-                    reset_renderer |= ui
-                        .add(DragValue::new(&mut scene.camera.fov).speed(0.01))
-                        .changed();
+                    ui.label("fps");
+                    ui.add(DragValue::new(&mut timeline.fps).clamp_range(1..=240));
+                    ui.label("frames");
+                    ui.add(
+                        DragValue::new(&mut timeline.start_frame)
+                            .clamp_range(0..=timeline.end_frame),
+                    );
+                    ui.add(
+                        DragValue::new(&mut timeline.end_frame)
+                            .clamp_range(timeline.start_frame..=u32::MAX),
+                    );
+                });
+                let path_name = |path: Option<usize>| {
+                    path.and_then(|i| scene.camera_paths.get(i))
+                        .map_or("none".to_string(), |path| path.name.clone())
+                };
+                egui::ComboBox::from_label("camera path")
+                    .selected_text(path_name(timeline.camera_path))
+                    .show_ui(ui, |ui| {
+                        let paths =
+                            std::iter::once(None).chain((0..scene.camera_paths.len()).map(Some));
+                        for path in paths {
+                            ui.selectable_value(&mut timeline.camera_path, path, path_name(path));
+                        }
+                    });
+
+                ui.add_space(10.);
+                ui.heading(RichText::new("Tracks").color(self.header_color));
+                let mut remove = None;
+                let mut key = None;
+                for (i, track) in timeline.tracks.iter_mut().enumerate() {
+                    ui.push_id(i, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label(track.property.name());
+                            if ui
+                                .button("key")
+                                .on_hover_text("key the current value")
+                                .clicked()
+                            {
+                                key = Some(i);
+                            }
+                            if ui.button("delete").clicked() {
+                                remove = Some(i);
+                            }
+                        });
+                        ui.collapsing(format!("{} keys", track.keys().len()), |ui| {
+                            let mut remove_key = None;
+                            for (k, (time, _)) in track.keys().iter().enumerate() {
+                                ui.horizontal(|ui| {
+                                    ui.label(format!("{time:.2} s"));
+                                    if ui.button("go").clicked() {
+                                        playback.time = *time;
+                                        playback.seeked = true;
+                                    }
+                                    if ui.button("delete").clicked() {
+                                        remove_key = Some(k);
+                                    }
+                                });
+                            }
+                            if let Some(k) = remove_key {
+                                track.remove(k);
+                            }
+                        });
+                    });
+                }
+                if let Some(i) = key {
+                    let property = timeline.tracks[i].property;
+                    if let Some(value) = property.get(scene, globals) {
+                        scene.timeline.tracks[i].insert(playback.time, value);
+                    }
+                }
+                let timeline = &mut scene.timeline;
+                if let Some(i) = remove {
+                    timeline.tracks.remove(i);
+                }
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_id_source("new track")
+                        .selected_text(self.new_track.kind_name())
+                        .show_ui(ui, |ui| {
+                            for kind in Property::KINDS {
+                                let index = self.new_track.index().unwrap_or(0);
+                                let property = kind.with_index(index);
+                                ui.selectable_value(
+                                    &mut self.new_track,
+                                    property,
+                                    kind.kind_name(),
+                                );
+                            }
+                        });
+                    if let Some(mut index) = self.new_track.index() {
+                        ui.add(DragValue::new(&mut index));
+                        self.new_track = self.new_track.with_index(index);
+                    }
+                    if ui.button("add track").clicked() {
+                        timeline.track(self.new_track);
+                    }
                 });
             });
 
-            egui::Window::new("Globals").show(ctx, |ui| {
-                ui.label(format!("bounces"));
-                reset_renderer |= ui.add(DragValue::new(&mut globals.bounces)).changed();
-                ui.label(format!("sky color"));
-                let color = &mut globals.sky_color;
-                reset_renderer |= ui.color_edit_button_rgba_unmultiplied(color).changed();
+            egui::Window::new("Physics").show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    let label = if simulation.running { "pause" } else { "play" };
+                    if ui.button(label).clicked() {
+                        simulation.running = !simulation.running;
+                    }
+                    if ui.button("step").clicked() {
+                        simulation.step(scene, 1. / scene.timeline.fps);
+                        reset_renderer = true;
+                    }
+                    if ui
+                        .add_enabled(simulation.has_started(), egui::Button::new("reset"))
+                        .clicked()
+                    {
+                        simulation.reset(scene);
+                        reset_renderer = true;
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("gravity");
+                    ui.add(DragValue::new(&mut simulation.gravity.x).speed(0.05));
+                    ui.add(DragValue::new(&mut simulation.gravity.y).speed(0.05));
+                    ui.add(DragValue::new(&mut simulation.gravity.z).speed(0.05));
+                });
+                ui.horizontal(|ui| {
+                    ui.label("restitution");
+                    ui.add(
+                        DragValue::new(&mut simulation.restitution)
+                            .speed(0.01)
+                            .clamp_range(0.0..=1.0),
+                    );
+                    ui.label("friction");
+                    ui.add(
+                        DragValue::new(&mut simulation.friction)
+                            .speed(0.01)
+                            .clamp_range(0.0..=f32::MAX),
+                    );
+                });
+                ui.horizontal(|ui| {
+                    let mut has_ground = simulation.ground.is_some();
+                    ui.checkbox(&mut has_ground, "ground plane at y");
+                    let mut ground = simulation.ground.unwrap_or(0.);
+                    ui.add_enabled(has_ground, DragValue::new(&mut ground).speed(0.01));
+                    simulation.ground = has_ground.then_some(ground);
+                });
+                ui.horizontal(|ui| {
+                    if ui.button("pin spheres larger than").clicked() {
+                        simulation.pin_larger_than(scene, self.pin_radius);
+                    }
+                    ui.add(DragValue::new(&mut self.pin_radius).speed(0.1));
+                });
+                ui.collapsing(
+                    format!("{} pinned spheres", simulation.pinned.len()),
+                    |ui| {
+                        for i in 0..scene.spheres.len() {
+                            let mut pinned = simulation.pinned.contains(&i);
+                            if ui.checkbox(&mut pinned, format!("sphere {i}")).changed() {
+                                match pinned {
+                                    true => simulation.pinned.insert(i),
+                                    false => simulation.pinned.remove(&i),
+                                };
+                            }
+                        }
+                    },
+                );
+                let timeline = &scene.timeline;
+                let bake = ui.button("bake to keyframes").on_hover_text(format!(
+                    "simulates frames {}..={} from the current state \
+                    and stores the sphere centers in the timeline",
+                    timeline.start_frame, timeline.end_frame
+                ));
+                if bake.clicked() {
+                    simulation.bake(scene);
+                }
             });
+
+            if !shader_watcher.errors.is_empty() {
+                egui::Window::new("Shader errors").show(ctx, |ui| {
+                    ui.label("The last working pipeline is used until the errors are fixed");
+                    for (name, error) in &shader_watcher.errors {
+                        ui.heading(RichText::new(name).color(self.header_color));
+                        ui.label(RichText::new(error).monospace().color(Color32::LIGHT_RED));
+                    }
+                });
+            }
         });
 
         for (id, image_delta) in egui_full_output.textures_delta.set {
@@ -184,8 +1158,8 @@ impl UiManager {
     }
     pub fn update_buffers(&mut self, encoder: &mut CommandEncoder, device: &Device, queue: &Queue) {
         let _commands = self.egui_renderer.update_buffers(
-            &device,
-            &queue,
+            device,
+            queue,
             encoder,
             &self.egui_primitives,
             &self.screen_descriptor,
@@ -196,3 +1170,256 @@ impl UiManager {
             .render(render_pass, &self.egui_primitives, &self.screen_descriptor);
     }
 }
+
+/// Formats large counts like `12.3 M`
+fn si_prefixed(value: f64) -> String {
+    const PREFIXES: [&str; 5] = ["", "k", "M", "G", "T"];
+    let mut value = value;
+    let mut prefix = 0;
+    while value >= 1000. && prefix < PREFIXES.len() - 1 {
+        value /= 1000.;
+        prefix += 1;
+    }
+    format!("{value:.1} {}", PREFIXES[prefix])
+}
+
+/// Change to the structure of the scene graph, applied after the tree is drawn
+enum GraphEdit {
+    Add(Node),
+    /// Removes the node and its descendants
+    Remove(usize),
+}
+
+/// Draws the children of `parent`, or the root nodes if None, as a collapsible tree.
+/// Returns true if the scene changed
+fn node_tree(
+    ui: &mut Ui,
+    scene: &mut Scene,
+    parent: Option<usize>,
+    edits: &mut Vec<GraphEdit>,
+) -> bool {
+    let mut changed = false;
+    let children: Vec<usize> = scene.graph.children(parent).collect();
+    for i in children {
+        let node = &scene.graph.nodes()[i];
+        let label = match node.kind {
+            NodeKind::Group => node.name.clone(),
+            NodeKind::Sphere(sphere) => format!("{} (sphere {sphere})", node.name),
+        };
+        egui::CollapsingHeader::new(label)
+            .id_source(("node", i))
+            .show(ui, |ui| {
+                changed |= node_editor(ui, scene, i, edits);
+                changed |= node_tree(ui, scene, Some(i), edits);
+            });
+    }
+    changed
+}
+
+/// Edits a single node and the sphere it places. Returns true if the scene changed
+fn node_editor(ui: &mut Ui, scene: &mut Scene, index: usize, edits: &mut Vec<GraphEdit>) -> bool {
+    // edit a copy, so the graph is only marked as changed if something was edited
+    let mut node = scene.graph.nodes()[index].clone();
+    let mut renamed = false;
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        ui.label("name");
+        renamed |= ui.text_edit_singleline(&mut node.name).changed();
+    });
+    changed |= drag_vec3(ui, "translation", &mut node.transform.translation, 0.01);
+    changed |= drag_vec3(ui, "rotation", &mut node.transform.rotation, 0.5);
+    if drag_vec3(ui, "scale", &mut node.transform.scale, 0.01) {
+        node.transform.scale = node.transform.scale.max(Vec3::splat(MIN_SCALE));
+        changed = true;
+    }
+    if let NodeKind::Sphere(mut sphere) = node.kind {
+        ui.horizontal(|ui| {
+            ui.label("sphere");
+            let max = scene.spheres.len().saturating_sub(1);
+            changed |= ui
+                .add(DragValue::new(&mut sphere).clamp_range(0..=max))
+                .changed();
+        });
+        node.kind = NodeKind::Sphere(sphere);
+    }
+    ui.horizontal(|ui| {
+        if ui.button("add group").clicked() {
+            let group = Node::new("Group", NodeKind::Group).with_parent(index);
+            edits.push(GraphEdit::Add(group));
+        }
+        if ui.button("add instance").clicked() {
+            // instances the sphere of this node, or the first one for groups
+            let sphere = match node.kind {
+                NodeKind::Sphere(sphere) => sphere,
+                NodeKind::Group => 0,
+            };
+            let instance =
+                Node::new(format!("Sphere {sphere}"), NodeKind::Sphere(sphere)).with_parent(index);
+            edits.push(GraphEdit::Add(instance));
+        }
+        if ui.button("remove").clicked() {
+            edits.push(GraphEdit::Remove(index));
+        }
+    });
+    let kind = node.kind;
+    if changed || renamed {
+        *scene.graph.node_mut(index) = node;
+    }
+    if let NodeKind::Sphere(sphere) = kind {
+        if sphere < scene.spheres.len() {
+            let users = scene
+                .graph
+                .nodes()
+                .iter()
+                .filter(|node| node.kind == kind)
+                .count();
+            ui.collapsing(format!("sphere {sphere}, placed by {users} nodes"), |ui| {
+                changed |= sphere_editor(ui, scene, sphere);
+            });
+        }
+    }
+    changed
+}
+
+/// Edits the geometry of a sphere, which is shared by every node placing it.
+/// Returns true if the sphere changed
+fn sphere_editor(ui: &mut Ui, scene: &mut Scene, index: usize) -> bool {
+    let sphere = &mut scene.spheres[index];
+    let materials = &scene.materials;
+    let mut changed = false;
+    changed |= drag_vec3(ui, "position", &mut sphere.center, 0.01);
+    changed |= drag_vec3(ui, "velocity", &mut sphere.velocity, 0.01);
+    ui.horizontal(|ui| {
+        ui.label("radius");
+        changed |= ui
+            .add(DragValue::new(&mut sphere.radius).speed(0.001))
+            .changed();
+    });
+    ui.horizontal(|ui| {
+        ui.label("material");
+        let selected = materials.get(sphere.material_index as usize);
+        egui::ComboBox::from_id_source(("sphere material", index))
+            .selected_text(selected.map_or("", |m| m.name.as_str()))
+            .show_ui(ui, |ui| {
+                for (i, named) in materials.iter().enumerate() {
+                    changed |= ui
+                        .selectable_value(&mut sphere.material_index, i as u32, &named.name)
+                        .changed();
+                }
+            });
+    });
+    if changed {
+        scene.dirty_spheres.mark(index);
+    }
+    changed
+}
+
+/// Selects the image or procedural texture of a material channel.
+/// Returns true if the material changed
+fn texture_slot(
+    ui: &mut Ui,
+    material: &mut Material,
+    channel: TextureChannel,
+    textures: (&[ImageTexture], &[ProceduralTexture]),
+    material_index: usize,
+) -> bool {
+    let (images, procedurals) = textures;
+    let label = |texture: Option<TextureRef>| match texture {
+        None => "none".to_string(),
+        Some(TextureRef::Image(i)) => images.get(i).map_or("", ImageTexture::name).to_string(),
+        Some(TextureRef::Procedural(i)) => procedurals
+            .get(i)
+            .map_or(String::new(), |p| format!("{} {i}", p.kind().name())),
+    };
+    let slot = &mut material.textures[channel as usize];
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        ui.label(format!("{} texture", channel.name()));
+        egui::ComboBox::from_id_source(("material texture", material_index, channel as usize))
+            .selected_text(label(TextureRef::from_slot(*slot)))
+            .show_ui(ui, |ui| {
+                changed |= ui.selectable_value(slot, NO_TEXTURE, "none").changed();
+                let images = (0..images.len()).map(TextureRef::Image);
+                let procedurals = (0..procedurals.len()).map(TextureRef::Procedural);
+                for texture in images.chain(procedurals) {
+                    changed |= ui
+                        .selectable_value(slot, texture.to_slot(), label(Some(texture)))
+                        .changed();
+                }
+            });
+    });
+    changed
+}
+
+/// Returns true if the procedural texture changed
+fn procedural_editor(ui: &mut Ui, procedural: &mut ProceduralTexture, index: usize) -> bool {
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        ui.label("kind");
+        egui::ComboBox::from_id_source(("procedural kind", index))
+            .selected_text(procedural.kind().name())
+            .show_ui(ui, |ui| {
+                for kind in ProceduralKind::ALL {
+                    changed |= ui
+                        .selectable_value(&mut procedural.kind, kind as u32, kind.name())
+                        .changed();
+                }
+            });
+    });
+    ui.horizontal(|ui| {
+        ui.label("space");
+        egui::ComboBox::from_id_source(("procedural space", index))
+            .selected_text(procedural.space().name())
+            .show_ui(ui, |ui| {
+                for space in TextureSpace::ALL {
+                    changed |= ui
+                        .selectable_value(&mut procedural.space, space as u32, space.name())
+                        .changed();
+                }
+            });
+    });
+    ui.horizontal(|ui| {
+        ui.label("scale");
+        changed |= ui
+            .add(
+                DragValue::new(&mut procedural.scale)
+                    .speed(0.01)
+                    .clamp_range(0.001..=1000.),
+            )
+            .changed();
+    });
+    if matches!(
+        procedural.kind(),
+        ProceduralKind::Noise | ProceduralKind::Marble
+    ) {
+        ui.horizontal(|ui| {
+            ui.label("octaves");
+            changed |= ui
+                .add(DragValue::new(&mut procedural.octaves).clamp_range(1..=MAX_OCTAVES))
+                .changed();
+        });
+    }
+    for (label, color) in [
+        ("color a", &mut procedural.color_a),
+        ("color b", &mut procedural.color_b),
+    ] {
+        ui.horizontal(|ui| {
+            ui.label(label);
+            let rgb: &mut [f32; 3] = (&mut color[..3]).try_into().unwrap();
+            changed |= ui.color_edit_button_rgb(rgb).changed();
+        });
+    }
+    changed
+}
+
+fn drag_vec3(ui: &mut Ui, label: &str, value: &mut Vec3, speed: f32) -> bool {
+    ui.horizontal(|ui| {
+        ui.label(label);
+        let mut changed = false;
+        changed |= ui.add(DragValue::new(&mut value.x).speed(speed)).changed();
+        changed |= ui.add(DragValue::new(&mut value.y).speed(speed)).changed();
+        changed |= ui.add(DragValue::new(&mut value.z).speed(speed)).changed();
+        changed
+    })
+    .inner
+}
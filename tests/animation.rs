@@ -0,0 +1,87 @@
+//! Checks keyframed properties and the motion of spheres during the shutter interval.
+use glam::{UVec2, Vec3, Vec4};
+use rays::{
+    animation::{Property, Track},
+    camera::Camera,
+    globals::Globals,
+    material::Material,
+    renderer::cpu,
+    sampler::{Sampler, SamplerKind},
+    scene::Scene,
+    sphere::Sphere,
+};
+
+fn camera() -> Camera {
+    let mut camera = Camera::new(60., 0.1, 100., 16., 16.);
+    camera.set_forward(Vec3::new(0., 0., -1.));
+    camera
+}
+
+#[test]
+fn spheres_move_with_their_velocity() {
+    let sphere = Sphere::new(Vec3::new(1., 2., 3.), 1., 0).with_velocity(Vec3::new(2., 0., -4.));
+    assert_eq!(sphere.center_at(0.), Vec3::new(1., 2., 3.));
+    assert_eq!(sphere.center_at(0.25), Vec3::new(1.5, 2., 2.));
+    assert_eq!(sphere.center_at(-0.5), Vec3::new(0., 2., 5.));
+    let resting = Sphere::new(Vec3::ONE, 1., 0);
+    assert_eq!(resting.center_at(10.), Vec3::ONE);
+}
+
+#[test]
+fn animated_centers_set_the_velocity() {
+    let mut scene = Scene::new(
+        vec![Sphere::new(Vec3::ZERO, 1., 0)],
+        vec![Material::new()],
+        camera(),
+    );
+    let mut track = Track::new(Property::SphereCenter(0));
+    track.insert(0., Vec4::ZERO);
+    track.insert(2., Vec4::new(4., 0., -2., 0.));
+    track.insert(3., Vec4::new(4., 1., -2., 0.));
+    scene.timeline.tracks.push(track);
+    let mut globals = Globals::default();
+
+    scene.apply_animation(1., &mut globals);
+    assert_eq!(scene.spheres[0].center, Vec3::new(2., 0., -1.));
+    assert_eq!(scene.spheres[0].velocity, Vec3::new(2., 0., -1.));
+    scene.apply_animation(2.5, &mut globals);
+    assert_eq!(scene.spheres[0].velocity, Vec3::new(0., 1., 0.));
+    // the sphere rests after the last key
+    scene.apply_animation(4., &mut globals);
+    assert_eq!(scene.spheres[0].center, Vec3::new(4., 1., -2.));
+    assert_eq!(scene.spheres[0].velocity, Vec3::ZERO);
+}
+
+/// Light seen through the center pixel, with a glowing sphere in front of the camera
+/// which moves out of view within a second
+fn center_light(shutter: [f32; 2]) -> Vec3 {
+    let mut camera = camera();
+    camera.shutter = shutter;
+    let sphere = Sphere::new(Vec3::new(0., 0., -5.), 1., 0).with_velocity(Vec3::new(10., 0., 0.));
+    let material = Material::new().with_emission([1., 1., 1.], 1.);
+    let scene = Scene::new(vec![sphere], vec![material], camera);
+    let globals = Globals {
+        bounces: 1,
+        sky_color: [0., 0., 0., 1.],
+        ..Globals::default()
+    };
+    let (pixel, size) = (UVec2::new(8, 8), UVec2::new(16, 16));
+    (0..64)
+        .map(|index| {
+            let mut sampler = Sampler::new(SamplerKind::Pcg, 1, pixel, index);
+            cpu::trace_path(pixel, size, &scene, &globals, &mut sampler)
+        })
+        .sum::<Vec3>()
+        / 64.
+}
+
+#[test]
+fn spheres_are_traced_at_the_shutter_time() {
+    assert!(center_light([0., 0.]).x > 0.);
+    // closed once the sphere moved away
+    assert_eq!(center_light([1., 1.]), Vec3::ZERO);
+    // open while it moves away, so only some paths hit it
+    let blurred = center_light([0., 1.]).x;
+    let still = center_light([0., 0.]).x;
+    assert!(blurred > 0. && blurred < still, "{blurred} {still}");
+}
@@ -0,0 +1,166 @@
+//! Checks the argument parsing and the reports of `rays bench` without rendering.
+use std::time::Duration;
+
+use rays::bench::{read_baseline, BenchConfig, BenchResult, Report};
+use winit::dpi::PhysicalSize;
+
+fn args(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split_whitespace().map(str::to_owned)
+}
+
+fn config(text: &str) -> Result<BenchConfig, String> {
+    BenchConfig::from_args(args(text)).map(|config| config.expect("usage was requested"))
+}
+
+#[test]
+fn arguments_are_parsed() {
+    let parsed = config(
+        "--samples 8 --resolutions 64x48,1920x1080 --scenes furnace,glass \
+         --json report.json --csv report.csv --baseline old.csv",
+    )
+    .unwrap();
+    assert_eq!(parsed.samples, 8);
+    assert_eq!(
+        parsed.resolutions,
+        [PhysicalSize::new(64, 48), PhysicalSize::new(1920, 1080)]
+    );
+    assert_eq!(parsed.scenes, ["furnace", "glass"]);
+    assert_eq!(parsed.json.unwrap().to_str(), Some("report.json"));
+    assert_eq!(parsed.csv.unwrap().to_str(), Some("report.csv"));
+    assert_eq!(parsed.baseline.unwrap().to_str(), Some("old.csv"));
+
+    let defaults = config("").unwrap();
+    assert_eq!(defaults.samples, 64);
+    assert_eq!(defaults.resolutions.len(), 3);
+    assert!(defaults.json.is_none() && defaults.csv.is_none());
+}
+
+#[test]
+fn help_is_not_an_error() {
+    assert!(matches!(BenchConfig::from_args(args("--help")), Ok(None)));
+    assert!(matches!(
+        BenchConfig::from_args(args("--samples 4 --help")),
+        Ok(None)
+    ));
+}
+
+#[test]
+fn invalid_arguments_are_rejected() {
+    let cases = [
+        ("--samples", "--samples needs a value"),
+        ("--samples many", "invalid sample count"),
+        ("--samples 0", "at least one sample is needed"),
+        ("--resolutions 640", "invalid resolution 640"),
+        ("--resolutions 0x480", "invalid resolution 0x480"),
+        ("--resolutions 640x480,x", "invalid resolution x"),
+        ("--scenes furnace,teapot", "unknown scene teapot"),
+        ("--fast", "unknown argument --fast"),
+    ];
+    for (text, expected) in cases {
+        match config(text) {
+            Ok(_) => panic!("{text:?} parsed"),
+            Err(error) => assert!(error.starts_with(expected), "{text:?}: {error}"),
+        }
+    }
+}
+
+fn report() -> Report {
+    let result = |scene: &str, width, samples, seconds, rss| BenchResult {
+        scene: scene.to_owned(),
+        size: PhysicalSize::new(width, 10),
+        samples,
+        render_time: Duration::from_secs_f64(seconds),
+        gpu_memory: 4096,
+        process_peak_rss: rss,
+    };
+    Report {
+        adapter: "Gpu \"Turbo\", rev 2".to_owned(),
+        backend: "Vulkan".to_owned(),
+        driver: "driver\\1.0\n".to_owned(),
+        unix_time: 1700000000,
+        results: vec![
+            result("furnace", 10, 4, 0.5, Some(1 << 20)),
+            result("glass", 20, 2, 0.25, None),
+        ],
+    }
+}
+
+#[test]
+fn results_derive_the_rates() {
+    let report = report();
+    let furnace = &report.results[0];
+    assert_eq!(furnace.ms_per_sample(), 125.);
+    // 4 samples of 10x10 pixels in half a second
+    assert_eq!(furnace.paths_per_second(), 800.);
+    assert_eq!(report.results[1].paths_per_second(), 1600.);
+}
+
+#[test]
+fn json_report_escapes_strings() {
+    let json = report().to_json();
+    assert!(
+        json.contains(r#""adapter": "Gpu \"Turbo\", rev 2""#),
+        "{json}"
+    );
+    assert!(json.contains(r#""driver": "driver\\1.0\u000a""#), "{json}");
+    assert!(json.contains(r#""unix_time": 1700000000"#), "{json}");
+    assert!(json.contains(
+        r#"{"scene": "furnace", "width": 10, "height": 10, "samples": 4, "seconds": 0.500000, "ms_per_sample": 125.000000, "paths_per_second": 800.0, "gpu_memory_bytes": 4096, "process_peak_rss_bytes": 1048576}"#
+    ), "{json}");
+    assert!(
+        json.contains(r#""process_peak_rss_bytes": null}"#),
+        "{json}"
+    );
+    assert_eq!(json.matches("\"scene\"").count(), 2);
+}
+
+#[test]
+fn csv_report_quotes_fields() {
+    let csv = report().to_csv();
+    let lines: Vec<&str> = csv.lines().collect();
+    // the driver ends with a newline, so its field spans two lines in every row
+    assert_eq!(lines.len(), 5);
+    assert!(lines[0].starts_with("adapter,backend,driver,scene,"));
+    assert!(lines[0].ends_with(",process_peak_rss_bytes"));
+    assert!(
+        csv.contains("\"Gpu \"\"Turbo\"\", rev 2\",Vulkan,\"driver\\1.0\n\",furnace,10,10,4,"),
+        "{csv}"
+    );
+    assert!(csv.ends_with(",4096,\n"), "{csv}");
+}
+
+#[test]
+fn csv_reports_are_read_as_baseline() {
+    let mut report = report();
+    // a line break within a field would need a full csv reader
+    report.driver = "driver 1.0".to_owned();
+    let baseline = read_baseline(&report.to_csv()).unwrap();
+    assert_eq!(baseline.len(), 2);
+    assert_eq!(baseline[&("furnace".to_owned(), 10, 10)], 800.);
+    assert_eq!(baseline[&("glass".to_owned(), 20, 10)], 1600.);
+}
+
+#[test]
+fn invalid_baselines_are_rejected() {
+    let cases = [
+        ("", "the baseline is empty"),
+        (
+            "scene,width,height\n",
+            "the baseline has no paths_per_second column",
+        ),
+        (
+            "scene,width,height,paths_per_second\nfurnace,10,ten,5\n",
+            "invalid baseline row furnace,10,ten,5",
+        ),
+    ];
+    for (csv, expected) in cases {
+        match read_baseline(csv) {
+            Ok(_) => panic!("{csv:?} parsed"),
+            Err(error) => assert_eq!(error, expected, "{csv:?}"),
+        }
+    }
+    // columns are found by name and empty lines are skipped
+    let csv = "paths_per_second,height,scene,width\n\n12.5,2,\"a,b\",3\n";
+    let baseline = read_baseline(csv).unwrap();
+    assert_eq!(baseline[&("a,b".to_owned(), 3, 2)], 12.5);
+}
@@ -0,0 +1,149 @@
+//! Checks the bookkeeping of the sample budget and the samples per frame tuner.
+use std::{thread, time::Duration};
+
+use rays::budget::{SampleBudget, SampleTarget, SampleTuner};
+
+#[test]
+fn samples_per_pixel_target() {
+    let mut budget = SampleBudget::new(SampleTarget::SamplesPerPixel(100));
+    assert_eq!(budget.progress(25), 0.25);
+    assert_eq!(budget.remaining_samples(25), Some(75));
+    budget.update(99);
+    assert!(!budget.is_paused());
+    budget.update(100);
+    assert!(budget.is_paused());
+    assert_eq!(budget.eta(100), None);
+    // overshooting by the samples of a whole dispatch stays at the target
+    assert_eq!(budget.progress(130), 1.);
+    assert_eq!(budget.remaining_samples(130), Some(0));
+}
+
+#[test]
+fn zero_sample_target_does_not_divide_by_zero() {
+    let budget = SampleBudget::new(SampleTarget::SamplesPerPixel(0));
+    assert_eq!(budget.progress(0), 0.);
+    assert_eq!(budget.progress(1), 1.);
+    assert_eq!(budget.remaining_samples(5), Some(0));
+}
+
+#[test]
+fn unlimited_target_never_finishes() {
+    let mut budget = SampleBudget::new(SampleTarget::Unlimited);
+    budget.update(1_000_000);
+    assert!(!budget.is_paused());
+    assert_eq!(budget.progress(1_000_000), 0.);
+    assert_eq!(budget.remaining_samples(10), None);
+    assert_eq!(budget.eta(10), None);
+}
+
+#[test]
+fn continue_render_extends_a_finished_target() {
+    let mut budget = SampleBudget::new(SampleTarget::SamplesPerPixel(10));
+    budget.continue_samples = 20;
+    budget.update(10);
+    assert!(budget.is_paused());
+
+    budget.continue_render(10);
+    assert!(!budget.is_paused());
+    assert_eq!(budget.progress(10), 0.);
+    assert_eq!(budget.progress(15), 0.25);
+    assert_eq!(budget.remaining_samples(15), Some(15));
+    budget.update(29);
+    assert!(!budget.is_paused());
+    budget.update(30);
+    assert!(budget.is_paused());
+
+    // a new target drops the continuation
+    budget.target = SampleTarget::SamplesPerPixel(50);
+    budget.retarget();
+    assert_eq!(budget.remaining_samples(30), Some(20));
+    budget.update(30);
+    assert!(!budget.is_paused());
+}
+
+#[test]
+fn reset_discards_the_progress() {
+    let mut budget = SampleBudget::new(SampleTarget::SamplesPerPixel(10));
+    budget.continue_samples = 5;
+    budget.update(10);
+    budget.continue_render(10);
+    budget.update(15);
+    assert!(budget.is_paused());
+    budget.reset();
+    assert!(!budget.is_paused());
+    assert_eq!(budget.remaining_samples(0), Some(10));
+    assert_eq!(budget.elapsed(), Duration::ZERO);
+}
+
+#[test]
+fn converged_target_ignores_stale_reports() {
+    let mut budget = SampleBudget::new(SampleTarget::Converged(0.5));
+    let stale = budget.epoch();
+    budget.reset();
+    budget.report_converged(stale, 1.);
+    assert_eq!(budget.progress(0), 0.);
+    assert_eq!(budget.eta(0), None);
+
+    budget.report_converged(budget.epoch(), 0.25);
+    assert_eq!(budget.progress(0), 0.5);
+    budget.report_converged(budget.epoch(), 0.6);
+    budget.update(0);
+    assert!(budget.is_paused());
+}
+
+#[test]
+fn time_limit_counts_down() {
+    let mut budget = SampleBudget::new(SampleTarget::TimeLimit(60.));
+    budget.update(0);
+    thread::sleep(Duration::from_millis(20));
+    budget.update(1);
+    assert!(!budget.is_paused());
+    assert!(budget.elapsed() >= Duration::from_millis(20));
+    let eta = budget.eta(1).unwrap();
+    assert!(eta < Duration::from_secs(60) && eta > Duration::from_secs(59));
+    assert!(budget.progress(1) > 0.);
+
+    budget.target = SampleTarget::TimeLimit(0.);
+    budget.update(1);
+    assert!(budget.is_paused());
+}
+
+#[test]
+fn eta_extrapolates_the_progress() {
+    let mut budget = SampleBudget::new(SampleTarget::SamplesPerPixel(4));
+    budget.update(0);
+    // no progress yet, so there is nothing to extrapolate from
+    assert_eq!(budget.eta(0), None);
+    thread::sleep(Duration::from_millis(20));
+    budget.update(1);
+    let elapsed = budget.elapsed().as_secs_f32();
+    let eta = budget.eta(1).unwrap().as_secs_f32();
+    assert!((eta - elapsed * 3.).abs() < 1e-3, "{eta} {elapsed}");
+}
+
+#[test]
+fn tuner_keeps_the_frame_time() {
+    let tuner = SampleTuner {
+        enabled: true,
+        target_frame_time: 16.,
+        max_samples_per_frame: 64,
+    };
+    assert_eq!(tuner.tune(8, 16.), 8);
+    assert_eq!(tuner.tune(8, 40.), 6);
+    assert_eq!(tuner.tune(8, 4.), 11);
+    // a frame time of zero grows the samples, but not past the maximum
+    assert_eq!(tuner.tune(8, 0.), 11);
+    assert_eq!(tuner.tune(60, 0.), 64);
+    // never drops below a single sample
+    assert_eq!(tuner.tune(1, 1000.), 1);
+    assert_eq!(tuner.tune(0, 16.), 1);
+}
+
+#[test]
+fn tuner_without_maximum_traces_one_sample() {
+    let tuner = SampleTuner {
+        max_samples_per_frame: 0,
+        ..SampleTuner::new()
+    };
+    assert_eq!(tuner.tune(8, 0.), 1);
+}
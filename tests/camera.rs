@@ -0,0 +1,168 @@
+//! Checks the rays of the camera, which the cpu renderer uses and the compute shader mirrors.
+use glam::{UVec2, Vec2, Vec3};
+use rays::camera::{Camera, Projection, Stereo, StereoLayout};
+
+fn camera() -> Camera {
+    let mut camera = Camera::new(90., 0.1, 100., 64., 48.);
+    camera.set_forward(Vec3::new(0., 0., -1.));
+    camera
+}
+
+const SIZE: UVec2 = UVec2::new(64, 48);
+const CENTER: UVec2 = UVec2::new(32, 24);
+const LEFT: UVec2 = UVec2::new(0, 24);
+const TOP: UVec2 = UVec2::new(32, 0);
+
+fn ray(projection: Projection, pixel: UVec2) -> Option<(Vec3, Vec3)> {
+    let mut camera = camera();
+    camera.projection = projection;
+    camera.ray(pixel, SIZE)
+}
+
+fn assert_close(actual: Vec3, expected: Vec3) {
+    assert!(
+        (actual - expected).length() < 1e-5,
+        "{actual} is not {expected}"
+    );
+}
+
+#[test]
+fn every_projection_looks_forward_in_the_center() {
+    for projection in Projection::ALL {
+        let (origin, direction) = ray(projection, CENTER).unwrap();
+        assert_close(origin, Vec3::ZERO);
+        assert_close(direction, Vec3::new(0., 0., -1.));
+    }
+}
+
+#[test]
+fn orthographic_rays_are_parallel() {
+    let orthographic = Projection::Orthographic { width: 10. };
+    let (origin, direction) = ray(orthographic, LEFT).unwrap();
+    assert_close(origin, Vec3::new(-5., 0., 0.));
+    assert_close(direction, Vec3::new(0., 0., -1.));
+    // the height follows the aspect ratio of the image
+    let (origin, direction) = ray(orthographic, TOP).unwrap();
+    assert_close(origin, Vec3::new(0., -3.75, 0.));
+    assert_close(direction, Vec3::new(0., 0., -1.));
+}
+
+#[test]
+fn fisheye_rays_cover_the_image_circle() {
+    let fisheye = Projection::Fisheye { fov: 180. };
+    // the circle spans the width, so its edge looks sideways with a fov of 180°
+    let (origin, direction) = ray(fisheye, LEFT).unwrap();
+    assert_close(origin, Vec3::ZERO);
+    assert_close(direction, Vec3::new(-1., 0., 0.));
+    let (_, direction) = ray(fisheye, TOP).unwrap();
+    let expected = Vec3::new(
+        0.,
+        -(67.5f32.to_radians().sin()),
+        -(67.5f32.to_radians().cos()),
+    );
+    assert_close(direction, expected);
+    // the corners are outside of the circle
+    assert_eq!(ray(fisheye, UVec2::ZERO), None);
+}
+
+#[test]
+fn equirectangular_rays_cover_the_sphere() {
+    let (_, direction) = ray(Projection::Equirectangular, LEFT).unwrap();
+    assert_close(direction, Vec3::new(0., 0., 1.));
+    let (_, direction) = ray(Projection::Equirectangular, UVec2::new(16, 24)).unwrap();
+    assert_close(direction, Vec3::new(-1., 0., 0.));
+    let (origin, direction) = ray(Projection::Equirectangular, TOP).unwrap();
+    assert_close(origin, Vec3::ZERO);
+    assert_close(direction, Vec3::new(0., -1., 0.));
+}
+
+#[test]
+fn lens_rays_meet_at_the_focus_distance() {
+    let mut camera = camera();
+    camera.aperture = 0.5;
+    camera.focus_distance = 4.;
+    let (origin, direction) = camera.ray(UVec2::new(10, 40), SIZE).unwrap();
+    let focus = origin + direction * 4.;
+    for u in [Vec2::ZERO, Vec2::new(0.5, 0.25), Vec2::new(0.99, 0.7)] {
+        let (lens_origin, lens_direction) = camera.lens_ray(origin, direction, u);
+        // the origin stays on the lens, which lies in the image plane
+        assert!((lens_origin - origin).z.abs() < 1e-6, "{lens_origin}");
+        assert!((lens_origin - origin).length() <= 0.5 + 1e-6);
+        assert!((lens_direction.length() - 1.).abs() < 1e-6);
+        let distance = (focus - lens_origin).length();
+        let reached = lens_origin + lens_direction * distance;
+        assert!((reached - focus).length() < 1e-5, "{u}: {reached} {focus}");
+    }
+    // the center of the lens keeps the ray
+    let (center_origin, center_direction) = camera.lens_ray(origin, direction, Vec2::ZERO);
+    assert_eq!(center_origin, origin);
+    assert!((center_direction - direction).length() < 1e-6);
+}
+
+fn stereo(layout: StereoLayout) -> Stereo {
+    Stereo {
+        layout,
+        ..Stereo::default()
+    }
+}
+
+#[test]
+fn mono_images_are_not_split() {
+    let mono = stereo(StereoLayout::Mono);
+    let pixel = UVec2::new(40, 30);
+    assert_eq!(mono.split(pixel, SIZE), (0., pixel, SIZE));
+}
+
+#[test]
+fn side_by_side_images_split_the_width() {
+    let side_by_side = stereo(StereoLayout::SideBySide);
+    let half = UVec2::new(32, 48);
+    assert_eq!(
+        side_by_side.split(UVec2::new(31, 47), SIZE),
+        (-1., UVec2::new(31, 47), half)
+    );
+    assert_eq!(
+        side_by_side.split(UVec2::new(32, 5), SIZE),
+        (1., UVec2::new(0, 5), half)
+    );
+    // the right eye gets the extra column of odd widths
+    let odd = UVec2::new(65, 48);
+    assert_eq!(
+        side_by_side.split(UVec2::new(31, 0), odd),
+        (-1., UVec2::new(31, 0), half)
+    );
+    assert_eq!(
+        side_by_side.split(UVec2::new(64, 0), odd),
+        (1., UVec2::new(32, 0), UVec2::new(33, 48))
+    );
+}
+
+#[test]
+fn top_bottom_images_split_the_height() {
+    let top_bottom = stereo(StereoLayout::TopBottom);
+    let half = UVec2::new(64, 24);
+    assert_eq!(
+        top_bottom.split(UVec2::new(63, 23), SIZE),
+        (-1., UVec2::new(63, 23), half)
+    );
+    assert_eq!(
+        top_bottom.split(UVec2::new(5, 24), SIZE),
+        (1., UVec2::new(5, 0), half)
+    );
+    let odd = UVec2::new(64, 49);
+    assert_eq!(
+        top_bottom.split(UVec2::new(0, 48), odd),
+        (1., UVec2::new(0, 24), UVec2::new(64, 25))
+    );
+}
+
+#[test]
+fn eyes_are_offset_to_their_side() {
+    let mut camera = camera();
+    camera.stereo = stereo(StereoLayout::SideBySide);
+    let (left, _) = camera.ray(UVec2::new(16, 24), SIZE).unwrap();
+    let (right, _) = camera.ray(UVec2::new(48, 24), SIZE).unwrap();
+    let half_distance = camera.stereo.eye_distance * 0.5;
+    assert_close(left, Vec3::new(-half_distance, 0., 0.));
+    assert_close(right, Vec3::new(half_distance, 0., 0.));
+}
@@ -0,0 +1,95 @@
+//! Helpers shared by the integration tests.
+use std::{iter, sync::mpsc};
+
+use bytemuck::Pod;
+use rays::renderer::headless::Headless;
+use wgpu::util::DeviceExt;
+
+/// Binding of the storage buffer which test entry points write their results to
+pub const OUTPUT_BINDING: u32 = 15;
+
+/// Runs `entry_point`, which `source` appends to the compute shader, with one invocation
+/// per workgroup and reads back the `len` values it wrote to the storage buffer at
+/// [`OUTPUT_BINDING`]. `inputs` are bound as buffers of the given binding and usage.
+/// None if the values could not be read back
+pub fn run_entry_point<T: Pod>(
+    headless: &Headless,
+    source: &str,
+    entry_point: &str,
+    inputs: &[(u32, wgpu::BufferUsages, &[u8])],
+    workgroups: [u32; 3],
+    len: usize,
+) -> Option<Vec<T>> {
+    let device = headless.device();
+    let queue = headless.queue();
+    let source = include_str!("../../assets/shaders/compute.wgsl").to_owned() + source;
+    let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some(entry_point),
+        source: wgpu::ShaderSource::Wgsl(source.into()),
+    });
+    // the derived layout only holds the bindings the entry point uses
+    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some(entry_point),
+        layout: None,
+        module: &module,
+        entry_point,
+    });
+    let inputs: Vec<_> = inputs
+        .iter()
+        .map(|&(binding, usage, contents)| {
+            let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Test input"),
+                contents,
+                usage,
+            });
+            (binding, buffer)
+        })
+        .collect();
+    let size = (len * std::mem::size_of::<T>()) as u64;
+    let output = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Test output"),
+        size,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+    let readback = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Test readback"),
+        size,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    let entries: Vec<_> = inputs
+        .iter()
+        .map(|(binding, buffer)| (*binding, buffer))
+        .chain(iter::once((OUTPUT_BINDING, &output)))
+        .map(|(binding, buffer)| wgpu::BindGroupEntry {
+            binding,
+            resource: buffer.as_entire_binding(),
+        })
+        .collect();
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Test bind group"),
+        layout: &pipeline.get_bind_group_layout(0),
+        entries: &entries,
+    });
+    let mut encoder = device.create_command_encoder(&Default::default());
+    {
+        let mut pass = encoder.begin_compute_pass(&Default::default());
+        pass.set_pipeline(&pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        let [x, y, z] = workgroups;
+        pass.dispatch_workgroups(x, y, z);
+    }
+    encoder.copy_buffer_to_buffer(&output, 0, &readback, 0, size);
+    queue.submit(iter::once(encoder.finish()));
+
+    let slice = readback.slice(..);
+    let (sender, receiver) = mpsc::channel();
+    slice.map_async(wgpu::MapMode::Read, move |result| {
+        let _ = sender.send(result);
+    });
+    device.poll(wgpu::Maintain::Wait);
+    receiver.recv().ok()?.ok()?;
+    let values = bytemuck::cast_slice::<u8, T>(&slice.get_mapped_range()).to_vec();
+    Some(values)
+}
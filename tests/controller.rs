@@ -0,0 +1,84 @@
+//! Checks that the camera controllers move the camera by the frame time and input.
+use glam::Vec3;
+use rays::{
+    camera::Camera,
+    controller::{CameraController, ControllerMode},
+    scene::Scene,
+};
+use winit::event::VirtualKeyCode;
+
+fn scene() -> Scene {
+    let mut camera = Camera::new(60., 0.1, 100., 16., 16.);
+    camera.set_forward(Vec3::new(0., 0., -1.));
+    Scene::new(Vec::new(), Vec::new(), camera)
+}
+
+/// Distance flown forward with W held for `frames` frames of `dt` seconds
+fn fly_distance(frames: u32, dt: f32) -> f32 {
+    let mut scene = scene();
+    let mut controller = CameraController::default();
+    controller.speed = 10.;
+    // reaches the full speed within the first frame
+    controller.acceleration = 1e6;
+    controller.set_key(VirtualKeyCode::W, true);
+    for _ in 0..frames {
+        assert!(controller.update(&mut scene, dt));
+    }
+    assert_eq!(scene.camera.position.x, 0.);
+    assert_eq!(scene.camera.position.y, 0.);
+    -scene.camera.position.z
+}
+
+#[test]
+fn flying_depends_on_the_time_not_the_frames() {
+    assert!((fly_distance(10, 0.1) - 10.).abs() < 1e-4);
+    assert!((fly_distance(100, 0.01) - 10.).abs() < 1e-4);
+    assert!((fly_distance(1, 0.5) - 5.).abs() < 1e-4);
+}
+
+#[test]
+fn flying_brakes_once_the_keys_are_released() {
+    let mut scene = scene();
+    let mut controller = CameraController::default();
+    controller.speed = 10.;
+    controller.acceleration = 20.;
+    controller.set_key(VirtualKeyCode::W, true);
+    // half a second to reach the full speed
+    controller.update(&mut scene, 0.25);
+    controller.update(&mut scene, 0.25);
+    let z = scene.camera.position.z;
+    assert!((z - -3.75).abs() < 1e-4, "{z}");
+    controller.set_key(VirtualKeyCode::W, false);
+    for _ in 0..4 {
+        controller.update(&mut scene, 0.25);
+    }
+    assert!(!controller.update(&mut scene, 0.25));
+    let z = scene.camera.position.z;
+    assert!((z - -5.).abs() < 1e-4, "{z}");
+}
+
+#[test]
+fn scrolling_zooms_the_orbit() {
+    let mut scene = scene();
+    let mut controller = CameraController::default();
+    controller.set_mode(ControllerMode::Orbit, &scene.camera);
+    // the target is in front of the camera
+    assert_eq!(controller.target, Vec3::new(0., 0., -10.));
+    assert!(controller.update(&mut scene, 0.1));
+    let distance = |scene: &Scene| (scene.camera.position - Vec3::new(0., 0., -10.)).length();
+    assert!((distance(&scene) - 10.).abs() < 1e-4);
+    // nothing changes without input
+    assert!(!controller.update(&mut scene, 0.1));
+
+    controller.add_scroll(2.);
+    assert!(controller.update(&mut scene, 0.1));
+    assert!(
+        (distance(&scene) - 8.1).abs() < 1e-4,
+        "{}",
+        distance(&scene)
+    );
+    controller.add_scroll(-2.);
+    controller.update(&mut scene, 0.1);
+    assert!((distance(&scene) - 10.).abs() < 1e-4);
+    assert!((scene.camera.forward() - Vec3::new(0., 0., -1.)).length() < 1e-4);
+}
@@ -0,0 +1,130 @@
+//! Golden image tests.
+//!
+//! Every scene is rendered at a fixed seed and sample count and compared against the
+//! reference image in `tests/golden/`. The cpu path tracer is always checked, the compute
+//! shader only if a gpu adapter is available.
+//!
+//! Run with `RAYS_BLESS=1` to (re)write the reference images after an intended change.
+//! On failure the rendered image and a diff are written to `target/tmp/golden/`.
+use std::{env, fs, path::PathBuf};
+
+use image::{Rgba, Rgba32FImage, RgbaImage};
+use rays::{
+    globals::Globals,
+    renderer::{self, cpu, headless},
+    scene::Scene,
+    scenes,
+};
+use winit::dpi::PhysicalSize;
+
+const SIZE: PhysicalSize<u32> = PhysicalSize::new(64, 48);
+const SAMPLES: u32 = 64;
+/// Root mean squared error of the srgb values in [0, 1].
+/// Srgb is roughly perceptually uniform, so dark and bright regions weigh alike
+const CPU_TOLERANCE: f64 = 0.002;
+/// The gpu draws the same random numbers as the cpu, but float math differs slightly
+const GPU_TOLERANCE: f64 = 0.02;
+
+fn output_dir() -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("golden");
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn reference_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{name}.png"))
+}
+
+/// Root mean squared error over the rgb channels in [0, 1]
+fn rmse(a: &RgbaImage, b: &RgbaImage) -> f64 {
+    let sum: f64 = a
+        .pixels()
+        .zip(b.pixels())
+        .flat_map(|(a, b)| (0..3).map(move |c| (a[c] as f64 - b[c] as f64) / 255.))
+        .map(|d| d * d)
+        .sum();
+    (sum / (a.width() * a.height() * 3) as f64).sqrt()
+}
+
+/// Absolute difference, amplified to make small errors visible
+fn diff_image(a: &RgbaImage, b: &RgbaImage) -> RgbaImage {
+    RgbaImage::from_fn(a.width(), a.height(), |x, y| {
+        let (a, b) = (a.get_pixel(x, y), b.get_pixel(x, y));
+        let d = |c: usize| (a[c].abs_diff(b[c]) as u32 * 8).min(255) as u8;
+        Rgba([d(0), d(1), d(2), 255])
+    })
+}
+
+fn compare(name: &str, backend: &str, accumulated: &Rgba32FImage, tolerance: f64) {
+    let image = renderer::resolve(accumulated);
+    let reference_path = reference_path(name);
+    if env::var_os("RAYS_BLESS").is_some() && backend == "cpu" {
+        fs::create_dir_all(reference_path.parent().unwrap()).unwrap();
+        image.save(&reference_path).unwrap();
+        return;
+    }
+    let reference = match image::open(&reference_path) {
+        Ok(reference) => reference.into_rgba8(),
+        Err(e) => panic!(
+            "could not load {}: {e}. Run with RAYS_BLESS=1 to create it",
+            reference_path.display()
+        ),
+    };
+    assert_eq!(
+        reference.dimensions(),
+        image.dimensions(),
+        "{name}: reference has a different size"
+    );
+    let error = rmse(&image, &reference);
+    if error > tolerance {
+        let dir = output_dir();
+        let actual_path = dir.join(format!("{name}_{backend}.png"));
+        let diff_path = dir.join(format!("{name}_{backend}_diff.png"));
+        image.save(&actual_path).unwrap();
+        diff_image(&image, &reference).save(&diff_path).unwrap();
+        panic!(
+            "{name} ({backend}): rmse {error:.4} exceeds {tolerance}. See {} and {}",
+            actual_path.display(),
+            diff_path.display()
+        );
+    }
+}
+
+fn check(name: &str, (mut scene, globals): (Scene, Globals)) {
+    scene.camera.resize(SIZE);
+    let mut accumulated = Rgba32FImage::new(SIZE.width, SIZE.height);
+    cpu::render_image(&mut accumulated, &scene, &globals, SAMPLES);
+    compare(name, "cpu", &accumulated, CPU_TOLERANCE);
+
+    match headless::render(&mut scene, globals, SIZE, SAMPLES) {
+        Some(accumulated) => compare(name, "gpu", &accumulated, GPU_TOLERANCE),
+        None => eprintln!("{name}: no gpu adapter available, only the cpu image was checked"),
+    }
+}
+
+#[test]
+fn golden_cornell_box() {
+    check("cornell_box", scenes::cornell_box());
+}
+
+#[test]
+fn golden_furnace() {
+    check("furnace", scenes::furnace());
+}
+
+#[test]
+fn golden_glossy_spheres() {
+    check("glossy_spheres", scenes::glossy_spheres());
+}
+
+#[test]
+fn golden_glass() {
+    check("glass", scenes::glass());
+}
+
+#[test]
+fn golden_textured() {
+    check("textured", scenes::textured());
+}
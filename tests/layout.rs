@@ -0,0 +1,112 @@
+//! Checks that the rust types uploaded to the gpu match the layout of their wgsl structs.
+//!
+//! The shaders are parsed with naga, which computes size, alignment and member offsets
+//! following the wgsl rules. Every [`ShaderLayout`] type is compared against them.
+use naga::{proc::Layouter, Module, TypeInner};
+use rays::{
+    camera::CameraUniform, globals::Globals, layout::ShaderLayout, material::Material,
+    procedural::ProceduralTexture, renderer::render_pipeline::DisplayUniform,
+    scene_graph::Instance, sphere::Sphere,
+};
+
+/// How the type is bound, which decides the size the rust type needs
+#[derive(Clone, Copy, Debug)]
+enum Binding {
+    /// The whole buffer is a single struct, padded to 16 bytes
+    Uniform,
+    /// Element of a runtime sized array, so the size has to match the array stride
+    StorageArray,
+}
+
+struct Shader {
+    module: Module,
+    layouter: Layouter,
+}
+
+impl Shader {
+    fn parse(name: &str, source: &str) -> Shader {
+        let module = naga::front::wgsl::parse_str(source)
+            .unwrap_or_else(|e| panic!("{name}: {}", e.emit_to_string(source)));
+        let mut layouter = Layouter::default();
+        layouter
+            .update(&module.types, &module.constants)
+            .unwrap_or_else(|e| panic!("{name}: {e}"));
+        Shader { module, layouter }
+    }
+    /// Returns every mismatch between `T` and its wgsl struct
+    fn check<T: ShaderLayout>(&self, binding: Binding) -> Vec<String> {
+        let name = T::WGSL_NAME;
+        let Some((handle, ty)) = self
+            .module
+            .types
+            .iter()
+            .find(|(_, ty)| ty.name.as_deref() == Some(name))
+        else {
+            return vec![format!("{name}: struct not found in the shader")];
+        };
+        let TypeInner::Struct { members, span } = &ty.inner else {
+            return vec![format!("{name}: not a struct")];
+        };
+        let mut errors = Vec::new();
+        let rust_size = std::mem::size_of::<T>() as u32;
+        let alignment = self.layouter[handle].alignment;
+        let expected_size = match binding {
+            Binding::Uniform => span.next_multiple_of(16),
+            Binding::StorageArray => alignment.round_up(*span),
+        };
+        if rust_size != expected_size {
+            errors.push(format!(
+                "{name}: rust size is {rust_size} bytes, expected {expected_size} for {binding:?} (wgsl span {span})"
+            ));
+        }
+        if alignment.round_up(rust_size) != rust_size {
+            errors.push(format!(
+                "{name}: rust size {rust_size} is not a multiple of the wgsl alignment {alignment}"
+            ));
+        }
+
+        let rust_members = T::members();
+        let wgsl_names: Vec<_> = members.iter().map(|m| m.name.as_deref()).collect();
+        let rust_names: Vec<_> = rust_members.iter().map(|(name, _)| Some(*name)).collect();
+        if wgsl_names != rust_names {
+            errors.push(format!(
+                "{name}: members differ, wgsl has {wgsl_names:?}, rust lists {rust_names:?}"
+            ));
+            return errors;
+        }
+        for (member, (member_name, rust_offset)) in members.iter().zip(rust_members) {
+            if member.offset as usize != rust_offset {
+                errors.push(format!(
+                    "{name}.{member_name}: rust offset is {rust_offset}, wgsl offset is {}",
+                    member.offset
+                ));
+            }
+        }
+        errors
+    }
+}
+
+#[test]
+fn shader_layouts_match() {
+    let compute = Shader::parse(
+        "compute.wgsl",
+        include_str!("../assets/shaders/compute.wgsl"),
+    );
+    let display = Shader::parse("shader.wgsl", include_str!("../assets/shaders/shader.wgsl"));
+
+    let errors = [
+        compute.check::<Globals>(Binding::Uniform),
+        compute.check::<CameraUniform>(Binding::Uniform),
+        compute.check::<Sphere>(Binding::StorageArray),
+        compute.check::<Material>(Binding::StorageArray),
+        compute.check::<Instance>(Binding::StorageArray),
+        compute.check::<ProceduralTexture>(Binding::StorageArray),
+        display.check::<DisplayUniform>(Binding::Uniform),
+    ]
+    .concat();
+    assert!(
+        errors.is_empty(),
+        "rust and wgsl layouts differ:\n{}",
+        errors.join("\n")
+    );
+}
@@ -0,0 +1,103 @@
+//! Checks the collision response of the sphere simulation and baking it to keyframes.
+use glam::Vec3;
+use rays::{
+    animation::Property, camera::Camera, material::Material, physics::Simulation, scene::Scene,
+    sphere::Sphere,
+};
+
+fn scene(spheres: Vec<Sphere>) -> Scene {
+    let camera = Camera::new(100., 0.1, 100., 1., 1.);
+    Scene::new(spheres, vec![Material::new()], camera)
+}
+
+/// A sphere of radius 0.5 moving down onto the ground at y = 1 without gravity
+fn bounce(restitution: f32) -> Vec3 {
+    let mut scene = scene(vec![Sphere::new(Vec3::ZERO, 0.5, 0)]);
+    scene.spheres[0].velocity = Vec3::new(0., 2., 0.);
+    let mut simulation = Simulation::default();
+    simulation.gravity = Vec3::ZERO;
+    simulation.restitution = restitution;
+    simulation.ground = Some(1.);
+    simulation.step(&mut scene, 0.5);
+    scene.spheres[0].velocity
+}
+
+#[test]
+fn restitution_scales_the_bounce() {
+    for restitution in [0., 0.5, 1.] {
+        let velocity = bounce(restitution);
+        assert!(
+            (velocity - Vec3::new(0., -2. * restitution, 0.)).length() < 1e-4,
+            "{restitution}: {velocity}"
+        );
+    }
+}
+
+#[test]
+fn spheres_come_to_rest_on_the_ground() {
+    let mut scene = scene(vec![Sphere::new(Vec3::new(0., 0.5, 0.), 0.5, 0)]);
+    let mut simulation = Simulation::default();
+    simulation.ground = Some(1.);
+    simulation.step(&mut scene, 2.);
+    let sphere = scene.spheres[0];
+    // gravity pulls it in by a single step at most
+    assert!((sphere.center.y - 0.5).abs() < 1e-3, "{}", sphere.center);
+    assert!(sphere.velocity.length() < 0.05, "{}", sphere.velocity);
+}
+
+#[test]
+fn pinned_spheres_do_not_move() {
+    let ground = Sphere::new(Vec3::new(0., 101., 0.), 100., 0);
+    let mut scene = scene(vec![ground, Sphere::new(Vec3::new(0., 0., 0.), 0.5, 0)]);
+    let mut simulation = Simulation::default();
+    simulation.pin_larger_than(&scene, 10.);
+    assert!(simulation.pinned.contains(&0) && !simulation.pinned.contains(&1));
+    simulation.step(&mut scene, 3.);
+    assert_eq!(scene.spheres[0].center, ground.center);
+    assert_eq!(scene.spheres[0].velocity, Vec3::ZERO);
+    // the small sphere lies on top of the pinned one
+    let distance = scene.spheres[1].center.distance(ground.center);
+    assert!((distance - 100.5).abs() < 1e-2, "{distance}");
+}
+
+#[test]
+fn spheres_without_radius_stay_in_place() {
+    let point = Sphere::new(Vec3::new(0., 1., 0.), 0., 0);
+    let mut scene = scene(vec![point, Sphere::new(Vec3::new(0.1, 0., 0.), 0.5, 0)]);
+    let mut simulation = Simulation::default();
+    simulation.ground = Some(2.);
+    simulation.step(&mut scene, 1.);
+    assert_eq!(scene.spheres[0].center, point.center);
+    for sphere in &scene.spheres {
+        assert!(
+            sphere.center.is_finite() && sphere.velocity.is_finite(),
+            "{sphere:?}"
+        );
+    }
+}
+
+#[test]
+fn bake_stores_every_frame_and_restores_the_spheres() {
+    let ground = Sphere::new(Vec3::new(0., 101., 0.), 100., 0);
+    let falling = Sphere::new(Vec3::new(0., -5., 0.), 0.5, 0);
+    let mut scene = scene(vec![ground, falling]);
+    scene.timeline.fps = 10.;
+    scene.timeline.start_frame = 2;
+    scene.timeline.end_frame = 12;
+    let mut simulation = Simulation::default();
+    simulation.pin_larger_than(&scene, 10.);
+    simulation.bake(&mut scene);
+
+    assert_eq!(scene.spheres[1].center, falling.center);
+    assert!(!simulation.has_started());
+    let tracks = &scene.timeline.tracks;
+    assert_eq!(tracks.len(), 1, "pinned spheres are not baked");
+    assert_eq!(tracks[0].property, Property::SphereCenter(1));
+    let keys = tracks[0].keys();
+    assert_eq!(keys.len(), 11);
+    assert_eq!(keys[0], (0.2, falling.center.extend(0.)));
+    assert_eq!(keys[10].0, 1.2);
+    // falling freely for one second
+    let expected = falling.center.y + 0.5 * 9.81;
+    assert!((keys[10].1.y - expected).abs() < 0.05, "{}", keys[10].1);
+}
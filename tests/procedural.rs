@@ -0,0 +1,181 @@
+//! Checks the procedural textures of the cpu renderer and that they match the compute shader.
+//!
+//! The textures are blended from black to white, so every channel holds the pattern value.
+//! The comparison with the shader runs only if a gpu adapter is available.
+mod common;
+
+use glam::{Vec3, Vec4};
+use rays::{
+    procedural::{ProceduralKind, ProceduralTexture, TextureSpace, MAX_OCTAVES},
+    renderer::headless::Headless,
+};
+
+const POINTS: [Vec3; 3] = [
+    Vec3::new(0.3, -1.7, 2.2),
+    Vec3::new(-4.1, 0.55, 0.9),
+    Vec3::new(12.25, 3.5, -7.75),
+];
+
+fn texture(kind: ProceduralKind) -> ProceduralTexture {
+    ProceduralTexture::new(kind)
+        .with_colors([0., 0., 0.], [1., 1., 1.])
+        .with_scale(0.75)
+}
+
+/// Pattern value at `p` in object space
+fn pattern(texture: &ProceduralTexture, p: Vec3) -> f32 {
+    texture.evaluate(p, Vec3::ZERO).x
+}
+
+#[test]
+fn patterns_are_stable() {
+    let expected = [
+        (ProceduralKind::Checker, [1., 0., 1.]),
+        (ProceduralKind::Noise, [0.5393838, 0.45567507, 0.3973803]),
+        (ProceduralKind::Voronoi, [0.24904452, 0.9626251, 0.8389204]),
+        (ProceduralKind::Marble, [0.96633947, 0.8560983, 0.8223021]),
+        (ProceduralKind::Gradient, [1., 0.29375, 0.]),
+    ];
+    for (kind, expected) in expected {
+        let texture = texture(kind);
+        let actual = POINTS.map(|p| pattern(&texture, p));
+        assert_eq!(actual, expected, "{kind:?}");
+    }
+}
+
+#[test]
+fn patterns_blend_between_the_colors() {
+    for kind in ProceduralKind::ALL {
+        let texture = ProceduralTexture::new(kind).with_colors([1., 0.5, 0.], [0., 0.5, 1.]);
+        for i in 0..64 {
+            let p = Vec3::new(i as f32 * 0.37, i as f32 * -0.91, (i * i) as f32 * 0.05);
+            let color = texture.evaluate(p, p);
+            let t = color.z;
+            assert!((0. ..=1.).contains(&t), "{kind:?} {p}: {color}");
+            assert!((color.x - (1. - t)).abs() < 1e-6, "{kind:?} {p}: {color}");
+            assert_eq!(color.y, 0.5, "{kind:?} {p}");
+        }
+    }
+}
+
+#[test]
+fn space_picks_the_position() {
+    let object = Vec3::new(0.5, 0.5, 0.5);
+    let world = Vec3::new(1.5, 0.5, 0.5);
+    let checker = texture(ProceduralKind::Checker).with_scale(1.);
+    assert_eq!(checker.evaluate(object, world), Vec4::new(0., 0., 0., 1.));
+    let checker = checker.with_space(TextureSpace::World);
+    assert_eq!(checker.evaluate(object, world), Vec4::ONE);
+}
+
+#[test]
+fn octaves_are_limited() {
+    let noise = texture(ProceduralKind::Noise);
+    let octaves = |octaves| ProceduralTexture { octaves, ..noise };
+    for p in POINTS {
+        assert_eq!(
+            pattern(&octaves(1000), p),
+            pattern(&octaves(MAX_OCTAVES), p)
+        );
+        assert_eq!(pattern(&octaves(0), p), pattern(&octaves(1), p));
+        assert_ne!(pattern(&octaves(1), p), pattern(&octaves(2), p));
+    }
+}
+
+/// Largest difference between the cpu and the shader. The shader may fuse multiplications
+/// and additions, so the values differ in the last bits
+const GPU_TOLERANCE: f32 = 1e-4;
+
+/// Entry point appended to the compute shader, which writes the texture of every
+/// invocation at every point instead of tracing paths
+const PROCEDURAL_ENTRY_POINT: &str = "
+@group(0) @binding(15)
+var<storage, read_write> procedural_output: array<vec4f>;
+
+@group(0) @binding(16)
+var<storage> procedural_points: array<vec3f>;
+
+@compute
+@workgroup_size(1, 1, 1)
+fn procedural_main(@builtin(global_invocation_id) id: vec3u) {
+    let p = procedural_points[id.y];
+    // the world position differs, so the space is checked as well
+    let payload = HitPayload(vec3f(0., 1., 0.), -p, 0, 1., vec2f(0.), p);
+    procedural_output[id.x * arrayLength(&procedural_points) + id.y] =
+        procedural_texture(procedurals[id.x], payload);
+}
+";
+
+/// Every kind in both spaces
+fn gpu_textures() -> Vec<ProceduralTexture> {
+    TextureSpace::ALL
+        .into_iter()
+        .flat_map(|space| ProceduralKind::ALL.map(|kind| texture(kind).with_space(space)))
+        .collect()
+}
+
+fn gpu_points() -> Vec<Vec3> {
+    (0..16)
+        .map(|i| {
+            Vec3::new(
+                i as f32 * 0.73 - 5.,
+                (i * i) as f32 * 0.11,
+                i as f32 * -1.37,
+            )
+        })
+        .chain(POINTS)
+        .collect()
+}
+
+fn gpu_colors(
+    headless: &Headless,
+    textures: &[ProceduralTexture],
+    points: &[Vec3],
+) -> Option<Vec<Vec4>> {
+    // vec3f has an alignment of 16 bytes in storage buffers
+    let padded: Vec<Vec4> = points.iter().map(|p| p.extend(0.)).collect();
+    let inputs = [
+        (
+            13,
+            wgpu::BufferUsages::STORAGE,
+            bytemuck::cast_slice(textures),
+        ),
+        (
+            16,
+            wgpu::BufferUsages::STORAGE,
+            bytemuck::cast_slice(&padded),
+        ),
+    ];
+    common::run_entry_point(
+        headless,
+        PROCEDURAL_ENTRY_POINT,
+        "procedural_main",
+        &inputs,
+        [textures.len() as u32, points.len() as u32, 1],
+        textures.len() * points.len(),
+    )
+}
+
+#[test]
+fn shader_computes_the_same_patterns() {
+    let Some(headless) = Headless::new() else {
+        eprintln!("no gpu adapter available, the shader textures were not checked");
+        return;
+    };
+    let textures = gpu_textures();
+    let points = gpu_points();
+    let gpu = gpu_colors(&headless, &textures, &points).expect("could not read back the colors");
+    let mut gpu = gpu.into_iter();
+    for texture in &textures {
+        for &p in &points {
+            let cpu = texture.evaluate(p, -p);
+            let gpu = gpu.next().unwrap();
+            assert!(
+                (gpu - cpu).abs().max_element() <= GPU_TOLERANCE,
+                "{:?} {:?} at {p}: cpu {cpu}, gpu {gpu}",
+                texture.kind(),
+                texture.space()
+            );
+        }
+    }
+}
@@ -0,0 +1,189 @@
+//! Checks the cpu samplers and that they reproduce the sequences of the compute shader.
+//!
+//! The sequences feed every image, so a change to them shows up as a changed golden image.
+//! These tests point at the sampler directly. The comparison with the shader runs only if a
+//! gpu adapter is available.
+mod common;
+
+use glam::{UVec2, Vec2};
+use rays::{
+    globals::Globals,
+    renderer::headless::Headless,
+    sampler::{Sampler, SamplerKind},
+};
+
+const SEED: u32 = 0x5748;
+
+fn samples(kind: SamplerKind, pixel: UVec2, index: u32, dimensions: u32) -> Vec<Vec2> {
+    let mut sampler = Sampler::new(kind, SEED, pixel, index);
+    (0..dimensions).map(|_| sampler.next_2d()).collect()
+}
+
+#[test]
+fn sequences_are_stable() {
+    let pixel = UVec2::new(3, 5);
+    let expected = [
+        (
+            SamplerKind::Pcg,
+            [
+                [0.30497462, 0.89699566],
+                [0.59952456, 0.31459588],
+                [0.78072554, 0.38477755],
+            ],
+        ),
+        (
+            SamplerKind::Sobol,
+            [
+                [0.22563034, 0.9084858],
+                [0.3449241, 0.78268117],
+                [0.12751555, 0.7246362],
+            ],
+        ),
+        (
+            SamplerKind::Rank1,
+            [
+                [0.5335641, 0.09522241],
+                [0.008105278, 0.1775651],
+                [0.25489467, 0.41947532],
+            ],
+        ),
+    ];
+    for (kind, expected) in expected {
+        let actual: Vec<_> = samples(kind, pixel, 7, 3)
+            .into_iter()
+            .map(|v| v.to_array())
+            .collect();
+        assert_eq!(actual, expected, "{kind:?}");
+    }
+}
+
+#[test]
+fn samples_lie_in_the_unit_square() {
+    for kind in SamplerKind::ALL {
+        for index in 0..64 {
+            for v in samples(kind, UVec2::new(index, 2 * index), index, 8) {
+                assert!(
+                    v.cmpge(Vec2::ZERO).all() && v.cmplt(Vec2::ONE).all(),
+                    "{kind:?} {v}"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn sobol_is_stratified() {
+    // the first 256 samples of every dimension pair form a (0, 8, 2)-net:
+    // every 16x16 cell and every 1/256 wide stripe holds exactly one sample
+    for dimension in 0..4 {
+        let points: Vec<_> = (0..256)
+            .map(|index| samples(SamplerKind::Sobol, UVec2::new(17, 4), index, 4)[dimension])
+            .collect();
+        let mut cells = [0; 256];
+        let mut columns = [0; 256];
+        let mut rows = [0; 256];
+        for p in points {
+            let cell = (p * 16.).as_uvec2();
+            cells[(cell.y * 16 + cell.x) as usize] += 1;
+            columns[(p.x * 256.) as usize] += 1;
+            rows[(p.y * 256.) as usize] += 1;
+        }
+        assert!(cells.iter().all(|&n| n == 1), "dimension {dimension}");
+        assert!(columns.iter().all(|&n| n == 1), "dimension {dimension}");
+        assert!(rows.iter().all(|&n| n == 1), "dimension {dimension}");
+    }
+}
+
+#[test]
+fn rank1_steps_by_the_r2_generators() {
+    let step = Vec2::new(3242174889. / 4294967296., 2447445414. / 4294967296.);
+    let pixel = UVec2::new(8, 1);
+    for index in 0..32 {
+        let a = samples(SamplerKind::Rank1, pixel, index, 2);
+        let b = samples(SamplerKind::Rank1, pixel, index + 1, 2);
+        for (a, b) in a.into_iter().zip(b) {
+            let difference = b - a - step;
+            let wrapped = difference - difference.round();
+            // u32_to_unit keeps 24 bits
+            assert!(
+                wrapped.abs().max_element() < 2. / 16777216.,
+                "{index} {wrapped}"
+            );
+        }
+    }
+}
+
+#[test]
+fn streams_differ_between_pixels_and_samples() {
+    for kind in SamplerKind::ALL {
+        let first = samples(kind, UVec2::new(0, 0), 0, 4);
+        assert_ne!(first, samples(kind, UVec2::new(1, 0), 0, 4), "{kind:?}");
+        assert_ne!(first, samples(kind, UVec2::new(0, 1), 0, 4), "{kind:?}");
+        assert_ne!(first, samples(kind, UVec2::new(0, 0), 1, 4), "{kind:?}");
+        // every dimension pair is drawn differently
+        assert_ne!(first[0], first[1], "{kind:?}");
+    }
+}
+
+/// Pixels in x and y, sample indices in z and dimension pairs per sample of the shader check
+const GRID: [u32; 3] = [4, 4, 8];
+const DIMENSIONS: u32 = 4;
+
+/// Entry point appended to the compute shader, which writes the samples of every
+/// invocation instead of tracing paths
+const SAMPLER_ENTRY_POINT: &str = "
+@group(0) @binding(15)
+var<storage, read_write> sampler_output: array<vec2f>;
+
+@compute
+@workgroup_size(1, 1, 1)
+fn sampler_main(@builtin(global_invocation_id) id: vec3u) {
+    sampler_init(id.xy, id.z);
+    let base = ((id.z * 4u + id.y) * 4u + id.x) * 4u;
+    for (var d = 0u; d < 4u; d++) {
+        sampler_output[base + d] = sample_2d();
+    }
+}
+";
+
+fn gpu_samples(headless: &Headless, kind: SamplerKind) -> Option<Vec<Vec2>> {
+    let globals = Globals {
+        seed: SEED,
+        sampler_kind: kind as u32,
+        ..Globals::default()
+    };
+    let inputs = [(0, wgpu::BufferUsages::UNIFORM, bytemuck::bytes_of(&globals))];
+    let len = (GRID.iter().product::<u32>() * DIMENSIONS) as usize;
+    common::run_entry_point(
+        headless,
+        SAMPLER_ENTRY_POINT,
+        "sampler_main",
+        &inputs,
+        GRID,
+        len,
+    )
+}
+
+#[test]
+fn shader_draws_the_same_samples() {
+    let Some(headless) = Headless::new() else {
+        eprintln!("no gpu adapter available, the shader samplers were not checked");
+        return;
+    };
+    for kind in SamplerKind::ALL {
+        let gpu = gpu_samples(&headless, kind).expect("could not read back the samples");
+        let mut gpu = gpu.chunks_exact(DIMENSIONS as usize);
+        for index in 0..GRID[2] {
+            for y in 0..GRID[1] {
+                for x in 0..GRID[0] {
+                    let cpu = samples(kind, UVec2::new(x, y), index, DIMENSIONS);
+                    assert_eq!(
+                        gpu.next().unwrap(),
+                        cpu,
+                        "{kind:?} pixel {x} {y} sample {index}"
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,408 @@
+//! Checks that scene files survive a round trip, that errors name their line,
+//! that nearly empty files still render and how camera paths are interpolated.
+use std::{fs, path::Path};
+
+use glam::{Vec3, Vec4};
+use image::{DynamicImage, Rgba32FImage, RgbaImage};
+use rays::{
+    animation::{Property, Track},
+    camera::Camera,
+    camera_path::{Bookmark, CameraPath, CameraPose, Keyframe, PathPlayer},
+    globals::Globals,
+    material::Material,
+    procedural::{ProceduralKind, ProceduralTexture, TextureSpace},
+    renderer::{cpu, headless},
+    scene::Scene,
+    scene_file::{parse, write},
+    scene_graph::{Node, NodeKind, Transform},
+    sphere::Sphere,
+    texture::{ImageTexture, TextureChannel, TextureRef},
+};
+use winit::dpi::PhysicalSize;
+
+fn pose(position: Vec3, fov: f32) -> CameraPose {
+    CameraPose {
+        position,
+        forward: Vec3::new(0., 0., -1.),
+        fov,
+        focus: fov * 0.1,
+    }
+}
+
+fn camera() -> Camera {
+    Camera::new(100., 0.1, 100., 1., 1.)
+}
+
+/// A scene using every kind of line of the format
+fn full_scene(directory: &Path) -> Scene {
+    let mut camera = camera();
+    camera.set_pose(pose(Vec3::new(1., -2., 3.), 70.));
+    camera.shutter = [0., 0.5];
+    camera.aperture = 0.05;
+    let spheres = vec![
+        Sphere::new(Vec3::new(0., 1000.5, 0.), 1000., 0),
+        Sphere::new(Vec3::new(0.25, 0., -1.), 0.5, 1),
+    ];
+    let mut scene = Scene::new(spheres, Vec::new(), camera);
+    scene.spheres[1].velocity = Vec3::new(0., -1., 0.);
+
+    let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(2, 2, image::Rgba([9; 4])));
+    image.save(directory.join("texture.png")).unwrap();
+    let texture = scene.add_texture(ImageTexture::load("texture.png", directory).unwrap());
+    let procedural = scene.add_procedural(
+        ProceduralTexture::new(ProceduralKind::Marble)
+            .with_space(TextureSpace::World)
+            .with_scale(3.)
+            .with_colors([1., 0., 0.], [0., 0., 1.]),
+    );
+    scene.add_material(
+        "Floor",
+        Material::new()
+            .with_color([0.5, 0.4, 0.3])
+            .with_texture(TextureChannel::Albedo, TextureRef::Image(texture))
+            .with_texture(
+                TextureChannel::Roughness,
+                TextureRef::Procedural(procedural),
+            ),
+    );
+    scene.add_material(
+        "Frosted glass",
+        Material::new()
+            .with_roughness(0.3)
+            .with_emission([1., 0.5, 0.25], 2.)
+            .with_transmission(0.9, 1.33),
+    );
+
+    let group = scene.graph.add(
+        Node::new("Group", NodeKind::Group).with_transform(Transform {
+            translation: Vec3::new(1., 2., 3.),
+            rotation: Vec3::new(0., 45., 0.),
+            scale: Vec3::new(1., 2., 1.),
+        }),
+    );
+    scene
+        .graph
+        .add(Node::new("Moved sphere", NodeKind::Sphere(1)).with_parent(group));
+    scene.update_instances();
+
+    scene.bookmarks.push(Bookmark {
+        name: "Close up".to_string(),
+        pose: pose(Vec3::new(0., 0., 2.), 40.),
+    });
+    let mut path = CameraPath::new("Fly by");
+    for (time, x) in [(0., -1.), (2.5, 1.)] {
+        path.insert(Keyframe {
+            time,
+            pose: pose(Vec3::new(x, 0., 5.), 60.),
+        });
+    }
+    scene.camera_paths.push(path);
+    scene.timeline.fps = 30.;
+    scene.timeline.end_frame = 60;
+    scene.timeline.camera_path = Some(0);
+    let mut track = Track::new(Property::SphereCenter(1));
+    track.insert(0., Vec4::new(0., 0., -1., 0.));
+    track.insert(2., Vec4::new(0., -1., -1., 0.));
+    scene.timeline.tracks.push(track);
+    scene
+}
+
+#[test]
+fn written_scenes_parse_to_the_same_scene() {
+    let directory = Path::new(env!("CARGO_TARGET_TMPDIR")).join("scene_file");
+    fs::create_dir_all(&directory).unwrap();
+    let scene = full_scene(&directory);
+    let globals = Globals {
+        seed: u32::MAX - 2,
+        bounces: 3,
+        sky_color: [0.1, 0.2, 0.3, 1.],
+        sampler_kind: 2,
+        adaptive_sampling: 1,
+        convergence_threshold: 0.05,
+        min_samples: 16,
+        recheck_interval: 8,
+        ..Globals::default()
+    };
+    let text = write(&scene, &globals);
+    let mut parsed_globals = Globals {
+        temporal: 1,
+        ..Globals::default()
+    };
+    let parsed =
+        parse(&text, &directory, &mut parsed_globals).unwrap_or_else(|e| panic!("{e}\n{text}"));
+    // everything the format stores is written again unchanged
+    assert_eq!(write(&parsed, &parsed_globals), text);
+    // settings of the viewport are kept
+    assert_eq!(parsed_globals.temporal, 1);
+    assert_eq!(
+        bytemuck::bytes_of(&Globals {
+            temporal: 0,
+            ..parsed_globals
+        }),
+        bytemuck::bytes_of(&globals)
+    );
+
+    assert_eq!(parsed.camera.pose(), scene.camera.pose());
+    assert_eq!(parsed.camera.shutter, [0., 0.5]);
+    assert_eq!(parsed.camera.aperture, 0.05);
+    assert_eq!(parsed.camera.focus_distance, 7.);
+    assert_eq!(parsed.spheres.len(), 2);
+    assert_eq!(parsed.spheres[1].velocity, Vec3::new(0., -1., 0.));
+    assert_eq!(parsed.material_name(1), Some("Frosted glass"));
+    let glass = parsed.materials[1].material;
+    assert_eq!((glass.transmission, glass.ior), (0.9, 1.33));
+    assert_eq!(glass.emission, [1., 0.5, 0.25, 2.]);
+    let floor = parsed.materials[0].material;
+    assert_eq!(floor.textures, scene.materials[0].material.textures);
+    assert_eq!(parsed.textures[0].image, scene.textures[0].image);
+    assert_eq!(parsed.procedurals, scene.procedurals);
+    assert_eq!(parsed.graph.nodes()[3].parent, Some(2));
+    assert_eq!(parsed.instances.len(), scene.instances.len());
+    assert_eq!(parsed.bookmarks[0].name, "Close up");
+    assert_eq!(parsed.camera_paths[0].keyframes().len(), 2);
+    assert_eq!(parsed.timeline.camera_path, Some(0));
+    assert_eq!(
+        parsed.timeline.tracks[0].keys(),
+        scene.timeline.tracks[0].keys()
+    );
+}
+
+#[test]
+fn materials_without_a_name_are_written_with_their_default_name() {
+    let mut scene = Scene::new(Vec::new(), vec![Material::new()], camera());
+    scene.add_material("", Material::new().with_metallic(1.));
+    let text = write(&scene, &Globals::default());
+    let parsed = parse(&text, Path::new(""), &mut Globals::default()).unwrap();
+    assert_eq!(parsed.materials.len(), 2);
+    assert_eq!(parsed.material_name(0), Some("Material 0"));
+    assert_eq!(parsed.material_name(1), Some("Material 1"));
+    assert_eq!(parsed.materials[1].material.metallic, 1.);
+}
+
+#[test]
+fn errors_name_the_line() {
+    let cases = [
+        (
+            "camera 0 0 0 0 0 -1 60 5\n\n# comment\nsphre 0 0 0 1 0",
+            "line 4: unknown kind \"sphre\"",
+        ),
+        ("material 1 2", "line 1: expected a number, found \"\""),
+        (
+            "camera 0 0 0 0 0 0 60 5",
+            "line 1: the forward direction must not be zero",
+        ),
+        (
+            "camera 0 0 0 0 0 -1 60 0",
+            "line 1: the focus distance must be positive",
+        ),
+        ("aperture -1", "line 1: the aperture must not be negative"),
+        (
+            "textures -1 -1 -1 -1",
+            "line 1: textures without a material",
+        ),
+        (
+            "material 0 1 0 0 1 1 1 1 0 0 0 0\ntextures p0 -1 -1 -1",
+            "line 2: texture p0 does not exist",
+        ),
+        (
+            "material 0 1 0 0 1 1 1 1 0 0 0 0\ntransmission 1 0",
+            "line 2: the index of refraction has to be positive",
+        ),
+        ("key 0 0 0 0 0 0 -1 60 5", "line 1: key without a path"),
+        ("value 0 1 2 3 4", "line 1: value without a track"),
+        (
+            "track sphere wobble 0",
+            "line 1: unknown property \"sphere wobble 0\"",
+        ),
+        (
+            "node 0 0 0 0 0 0 0 1 1 1 -1 Orphan",
+            "line 1: the parent has to be defined before the node",
+        ),
+        ("timeline 0 0 10 -1", "line 1: invalid timeline"),
+        (
+            "timeline 24 0 10 0.5",
+            "line 1: invalid camera path index 0.5",
+        ),
+        (
+            "timeline 24 0 10 -2",
+            "line 1: invalid camera path index -2",
+        ),
+        (
+            "globals -1 8 1 1 1 1 0 0.01 32 64",
+            "line 1: expected a seed, found \"-1\"",
+        ),
+        (
+            "globals 1 8.5 1 1 1 1 0 0.01 32 64",
+            "line 1: bounces has to be a positive integer",
+        ),
+        // checked once all lines are read, so there is no single line to blame
+        (
+            "sphere 0 0 0 1 0",
+            "sphere uses material 0, but there are only 0",
+        ),
+        (
+            "timeline 24 0 10 1\npath only",
+            "the timeline follows camera path 1, but there are only 1",
+        ),
+    ];
+    for (text, expected) in cases {
+        let mut globals = Globals::default();
+        match parse(text, Path::new(""), &mut globals) {
+            Ok(_) => panic!("{text:?} parsed"),
+            Err(error) => assert_eq!(error, expected, "{text:?}"),
+        }
+    }
+}
+
+#[test]
+fn files_without_globals_keep_the_settings() {
+    let mut globals = Globals {
+        bounces: 2,
+        ..Globals::default()
+    };
+    parse("# no globals line", Path::new(""), &mut globals).unwrap();
+    assert_eq!(globals.bounces, 2);
+    // a file which fails to parse changes nothing
+    let text = "globals 7 3 0 0 0 1 0 0.01 32 64\nunknown";
+    assert!(parse(text, Path::new(""), &mut globals).is_err());
+    assert_eq!(
+        (globals.seed, globals.bounces),
+        (Globals::default().seed, 2)
+    );
+}
+
+fn path() -> CameraPath {
+    let mut path = CameraPath::new("path");
+    for (time, x) in [(1., 0.), (2., 1.), (3., 2.)] {
+        path.insert(Keyframe {
+            time,
+            pose: pose(Vec3::new(x, 0., 0.), 50. + 10. * x),
+        });
+    }
+    path
+}
+
+#[test]
+fn camera_paths_clamp_to_the_first_and_last_key() {
+    let path = path();
+    let first = path.keyframes()[0].pose;
+    let last = path.keyframes()[2].pose;
+    assert_eq!(path.sample(-10.), Some(first));
+    assert_eq!(path.sample(1.), Some(first));
+    assert_eq!(path.sample(3.), Some(last));
+    assert_eq!(path.sample(100.), Some(last));
+    assert_eq!(path.duration(), 3.);
+}
+
+#[test]
+fn camera_paths_pass_through_their_keys() {
+    let path = path();
+    let middle = path.sample(2.).unwrap();
+    assert!((middle.position - Vec3::X).length() < 1e-6, "{middle:?}");
+    assert!((middle.fov - 60.).abs() < 1e-4);
+    assert!((middle.focus - 6.).abs() < 1e-4);
+    // the first segment repeats the first key as its outer control point
+    let early = path.sample(1.5).unwrap();
+    assert!(early.position.x > 0. && early.position.x < 1., "{early:?}");
+    assert!((early.forward.length() - 1.).abs() < 1e-6);
+}
+
+#[test]
+fn camera_paths_keep_a_direction_between_opposite_keys() {
+    let mut path = CameraPath::new("turn");
+    for (time, z) in [(0., -1.), (1., 1.)] {
+        let mut pose = pose(Vec3::ZERO, 60.);
+        pose.forward = Vec3::new(0., 0., z);
+        path.insert(Keyframe { time, pose });
+    }
+    // the directions cancel out halfway
+    let halfway = path.sample(0.5).unwrap();
+    assert_eq!(halfway.forward, Vec3::new(0., 0., -1.));
+    let mut camera = camera();
+    camera.set_pose(halfway);
+    assert!(camera.forward().is_finite());
+}
+
+#[test]
+fn removing_camera_paths_updates_the_timeline_and_player() {
+    let mut scene = Scene::new(Vec::new(), Vec::new(), camera());
+    for name in ["a", "b", "c"] {
+        scene.camera_paths.push(CameraPath::new(name));
+    }
+    let mut player = PathPlayer::default();
+    player.play(1);
+    scene.timeline.camera_path = Some(2);
+
+    scene.remove_camera_path(0);
+    player.remove_path(0);
+    assert_eq!(scene.timeline.camera_path, Some(1));
+    assert_eq!(scene.camera_paths[1].name, "c");
+    assert_eq!(player.path, Some(0));
+    assert!(player.playing);
+
+    scene.remove_camera_path(0);
+    player.remove_path(0);
+    assert_eq!(scene.timeline.camera_path, Some(0));
+    assert_eq!((player.path, player.playing), (None, false));
+
+    scene.remove_camera_path(0);
+    assert_eq!(scene.timeline.camera_path, None);
+}
+
+#[test]
+fn camera_paths_with_less_than_two_keys() {
+    let mut path = CameraPath::new("single");
+    assert_eq!(path.sample(0.), None);
+    let key = pose(Vec3::ONE, 45.);
+    path.insert(Keyframe {
+        time: 2.,
+        pose: key,
+    });
+    assert_eq!(path.sample(0.), Some(key));
+    assert_eq!(path.sample(5.), Some(key));
+}
+
+#[test]
+fn nodes_scaled_to_zero_are_not_instanced() {
+    let text = "material 0 1 0 0 1 1 1 1 0 0 0 0\n\
+                sphere 0 0 0 1 0\n\
+                node -1 0 0 0 0 0 0 1 1 1 -1 Group\n\
+                node 0 0 0 0 0 0 0 1 0 1 0 Flat\n\
+                node 0 2 0 0 0 0 0 1 2 1 0 Tall";
+    let scene = parse(text, Path::new(""), &mut Globals::default()).unwrap();
+    assert_eq!(scene.instances.len(), 1);
+    let instance = scene.instances[0];
+    assert!(instance.world_to_object.is_finite());
+    assert_eq!(
+        instance.object_to_world.transform_point3(Vec3::Y),
+        Vec3::new(2., 2., 0.)
+    );
+}
+
+#[test]
+fn files_without_spheres_or_materials_render_the_sky() {
+    let mut globals = Globals::default();
+    let mut scene = parse(
+        "camera 0 0 5 0 0 -1 60 5\nglobals 1 4 0.25 0.5 1 0 0 0.01 32 64",
+        Path::new(""),
+        &mut globals,
+    )
+    .unwrap();
+    assert!(scene.spheres.is_empty() && scene.materials.is_empty());
+    let size = PhysicalSize::new(4, 4);
+    scene.camera.resize(size);
+    let sky = Vec4::new(0.25, 0.5, 1., 1.);
+    let mut image = Rgba32FImage::new(size.width, size.height);
+    cpu::render_image(&mut image, &scene, &globals, 1);
+    assert!(image.pixels().all(|p| Vec4::from(p.0) == sky), "{image:?}");
+    // storage buffers can not be empty, so the gpu binds placeholders
+    match headless::render(&mut scene, globals, size, 1) {
+        Some(image) => assert!(
+            image
+                .pixels()
+                .all(|p| (Vec4::from(p.0) - sky).abs().max_element() < 1e-4),
+            "{image:?}"
+        ),
+        None => eprintln!("no gpu adapter available, only the cpu image was checked"),
+    }
+}